@@ -5,34 +5,42 @@ pub fn get_cube() -> (Vec<Vertex>, Vec<u16>) {
         Vertex {
             position: [-0.5, -0.5, -0.5],
             uv: [0.0, 0.0],
+            normal: [0.0, 0.0, 0.0],
         },
         Vertex {
             position: [0.5, -0.5, -0.5],
             uv: [0.5, 0.5],
+            normal: [0.0, 0.0, 0.0],
         },
         Vertex {
             position: [0.5, 0.5, -0.5],
             uv: [1.0, 0.0],
+            normal: [0.0, 0.0, 0.0],
         },
         Vertex {
             position: [-0.5, 0.5, -0.5],
             uv: [0.5, 0.5],
+            normal: [0.0, 0.0, 0.0],
         },
         Vertex {
             position: [-0.5, 0.5, 0.5],
             uv: [0.0, 1.0],
+            normal: [0.0, 0.0, 0.0],
         },
         Vertex {
             position: [0.5, 0.5, 0.5],
             uv: [0.0, 1.0],
+            normal: [0.0, 0.0, 0.0],
         },
         Vertex {
             position: [0.5, -0.5, 0.5],
             uv: [1.0, 1.0],
+            normal: [0.0, 0.0, 0.0],
         },
         Vertex {
             position: [-0.5, -0.5, 0.5],
             uv: [1.0, 1.0],
+            normal: [0.0, 0.0, 0.0],
         },
     ];
 
@@ -48,22 +56,27 @@ pub fn get_data() -> (Vec<Vertex>, Vec<u16>) {
         Vertex {
             position: [-0.0868241, 0.49240386, 0.0],
             uv: [0.4131759, 0.99240386],
+            normal: [0.0, 0.0, 0.0],
         },
         Vertex {
             position: [-0.49513406, 0.06958647, 0.0],
             uv: [0.0048659444, 0.56958647],
+            normal: [0.0, 0.0, 0.0],
         },
         Vertex {
             position: [-0.21918549, -0.44939706, 0.0],
             uv: [0.28081453, 0.05060294],
+            normal: [0.0, 0.0, 0.0],
         },
         Vertex {
             position: [0.35966998, -0.3473291, 0.0],
             uv: [0.85967, 0.1526709],
+            normal: [0.0, 0.0, 0.0],
         },
         Vertex {
             position: [0.44147372, 0.2347359, 0.0],
             uv: [0.9414737, 0.7347359],
+            normal: [0.0, 0.0, 0.0],
         },
     ]
     .to_vec();
@@ -12,7 +12,7 @@ pub fn load_mesh(
 
     let size: u32 = 20;
 
-    let mut result = Vec::with_capacity(size.pow(3) as usize);
+    let mut result: Vec<u8> = Vec::with_capacity(size.pow(3) as usize);
 
     for i in 0..size.pow(3) {
         let x = i % size;
@@ -32,59 +32,40 @@ pub fn load_mesh(
         }
     }
 
-    let tex_size = wgpu::Extent3d {
-        width: size,
-        height: size,
-        depth_or_array_layers: size,
-    };
-    let texture = renderer.device.create_texture(&wgpu::TextureDescriptor {
-        label: None,
-        size: tex_size,
-        mip_level_count: 1,
-        sample_count: 1,
-        dimension: wgpu::TextureDimension::D3,
-        format: wgpu::TextureFormat::R8Uint,
-        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-        view_formats: &[],
-    });
-
-    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-
-    renderer.queue.write_texture(
-        wgpu::ImageCopyTexture {
-            aspect: wgpu::TextureAspect::All,
-            texture: &texture,
-            mip_level: 0,
-            origin: wgpu::Origin3d::ZERO,
-        },
-        &result,
-        wgpu::ImageDataLayout {
-            offset: 0,
-            bytes_per_row: Some(size),
-            rows_per_image: Some(size),
-        },
-        tex_size,
+    let volume = puddle::texture::VolumeTexture::<puddle::texture::R8Uint>::new(
+        &renderer.device,
+        [size; 3],
+        "voxels",
     );
+    if let Err(e) = volume.write(&renderer.queue, &result) {
+        puddle::application::log::error!("{}", e);
+        return;
+    }
 
     let entries = vec![
         puddle::rendering::PuddleBindGroupEntry {
-            ty: wgpu::BindingType::Texture {
-                sample_type: wgpu::TextureSampleType::Uint,
-                view_dimension: wgpu::TextureViewDimension::D3,
-                multisampled: false,
-            },
+            name: "voxel_data".to_string(),
+            ty: volume.binding_type(),
             visibility: wgpu::ShaderStages::FRAGMENT,
-            resource: wgpu::BindingResource::TextureView(&view),
+            resource: puddle::rendering::MaterialResource::TextureView(std::sync::Arc::new(
+                volume.view,
+            )),
         },
     ];
 
-    let mut material = puddle::rendering::Material::new(
-        renderer,
-        entries,
-        camera_bind_group,
-        wgpu::include_wgsl!("./voxel_shader.wgsl"),
-        true,
-    );
+    let mut material = match puddle::rendering::Material::from_wgsl(
+        "voxel_shader.wgsl",
+        include_str!("./voxel_shader.wgsl"),
+    )
+    .entries(entries)
+    .build(renderer, camera_bind_group)
+    {
+        Ok(r) => r,
+        Err(e) => {
+            puddle::application::log::error!("{}", e);
+            return;
+        }
+    };
 
     let data = cube::get_cube();
     material.add_mesh(data.0, data.1, puddle::rendering::ModelMatrix::default());
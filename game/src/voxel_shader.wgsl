@@ -1,9 +1,5 @@
-struct CameraUniform {
-    view_proj: mat4x4<f32>,
-    camera_eye: vec4<f32>,
-};
-@group(0) @binding(0)
-var<uniform> camera: CameraUniform;
+#import puddle::camera
+#import puddle::instancing
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
@@ -11,13 +7,6 @@ struct VertexInput {
 };
 
 
-struct InstanceInput {
-    @location(5) model_matrix_0: vec4<f32>,
-    @location(6) model_matrix_1: vec4<f32>,
-    @location(7) model_matrix_2: vec4<f32>,
-    @location(8) model_matrix_3: vec4<f32>,
-};
-
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(1) uv_cords: vec3<f32>,
@@ -34,12 +23,7 @@ fn vs_main(
     instance: InstanceInput,
 ) -> VertexOutput {
 
-  let model_matrix = mat4x4<f32>(
-        instance.model_matrix_0,
-        instance.model_matrix_1,
-        instance.model_matrix_2,
-        instance.model_matrix_3,
-    );
+  let model_matrix = instance_model_matrix(instance);
 
     var out: VertexOutput;
     out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
@@ -0,0 +1,156 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, RwLock};
+
+#[derive(Debug, Clone)]
+pub enum AssetError {
+    Io {
+        path: PathBuf,
+        message: String,
+    },
+    /// no loader for the extension of the file was registered for the asset type
+    NoLoader {
+        path: PathBuf,
+    },
+    Decode {
+        path: PathBuf,
+        message: String,
+    },
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssetError::Io { path, message } => {
+                write!(f, "cant read {} : {}", path.display(), message)
+            }
+            AssetError::NoLoader { path } => {
+                write!(f, "no loader for {}", path.display())
+            }
+            AssetError::Decode { path, message } => {
+                write!(f, "cant load {} : {}", path.display(), message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+#[derive(Debug, Clone)]
+pub enum LoadState {
+    Loading,
+    Loaded,
+    Failed(AssetError),
+}
+
+pub(crate) enum Slot<T> {
+    Loading,
+    Loaded(Arc<T>),
+    Failed(AssetError),
+}
+
+pub(crate) struct HandleInner<T> {
+    pub(crate) path: PathBuf,
+    pub(crate) slot: RwLock<Slot<T>>,
+    pub(crate) version: AtomicU32,
+    /// goes up with every load that is started, older loads that finish later are dropped
+    pub(crate) generation: AtomicU32,
+}
+
+impl<T> HandleInner<T> {
+    /// the generation of a load that starts now
+    pub(crate) fn next_generation(&self) -> u32 {
+        self.generation.fetch_add(1, Ordering::AcqRel) + 1
+    }
+
+    /// false once a newer load was started
+    pub(crate) fn is_current(&self, generation: u32) -> bool {
+        self.generation.load(Ordering::Acquire) == generation
+    }
+
+    pub(crate) fn set_loaded(&self, asset: T) {
+        *self.slot.write().unwrap() = Slot::Loaded(Arc::new(asset));
+        self.version.fetch_add(1, Ordering::AcqRel);
+    }
+}
+
+/// a reference counted asset that may still be loading.
+/// clones share the asset, it is freed once the last handle and the last `Arc` from `Handle::get` are dropped
+pub struct Handle<T> {
+    pub(crate) inner: Arc<HandleInner<T>>,
+}
+
+impl<T> Handle<T> {
+    pub(crate) fn loading(path: PathBuf) -> Self {
+        Self {
+            inner: Arc::new(HandleInner {
+                path,
+                slot: RwLock::new(Slot::Loading),
+                version: AtomicU32::new(0),
+                generation: AtomicU32::new(0),
+            }),
+        }
+    }
+
+    /// a handle to an asset that doesn't come from a file, it is never reloaded
+    pub fn from_asset(asset: T) -> Self {
+        let handle = Self::loading(PathBuf::new());
+        handle.inner.set_loaded(asset);
+        handle
+    }
+
+    /// `None` until the asset is loaded
+    pub fn get(&self) -> Option<Arc<T>> {
+        match &*self.inner.slot.read().unwrap() {
+            Slot::Loaded(r) => Some(r.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn state(&self) -> LoadState {
+        match &*self.inner.slot.read().unwrap() {
+            Slot::Loading => LoadState::Loading,
+            Slot::Loaded(_) => LoadState::Loaded,
+            Slot::Failed(e) => LoadState::Failed(e.clone()),
+        }
+    }
+
+    /// goes up every time the asset is loaded or reloaded, 0 until it is loaded the first time
+    pub fn version(&self) -> u32 {
+        self.inner.version.load(Ordering::Acquire)
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        matches!(&*self.inner.slot.read().unwrap(), Slot::Loaded(_))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.inner.path
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Handle")
+            .field("path", &self.inner.path)
+            .field("state", &self.state())
+            .finish()
+    }
+}
@@ -0,0 +1,52 @@
+#![allow(unused, dead_code)]
+mod handle;
+mod server;
+mod watcher;
+
+pub use handle::{AssetError, Handle, LoadState};
+pub use server::{AssetLoader, AssetServer, DecodeLoader};
+
+use std::path::PathBuf;
+
+use application::{Application, Plugin, Scheddules};
+
+/// adds the `AssetServer` resource, add it before the plugins that register loaders
+pub struct AssetPlugin {
+    /// where relative asset paths start, the working directory by default
+    pub root: PathBuf,
+    /// reload assets when their file changes, on in debug builds
+    pub hot_reload: bool,
+}
+
+impl Default for AssetPlugin {
+    fn default() -> Self {
+        Self {
+            root: PathBuf::from("."),
+            hot_reload: cfg!(debug_assertions),
+        }
+    }
+}
+
+impl Plugin for AssetPlugin {
+    fn build(&mut self, app: &mut Application) {
+        let server = AssetServer::new(self.root.clone());
+        if self.hot_reload {
+            server.watch_for_changes();
+        }
+        app.resources.insert(server);
+
+        app.scheddules
+            .add_non_parralel(Scheddules::Update, finish_loads);
+    }
+}
+
+fn finish_loads(_world: &mut legion::World, resources: &mut legion::Resources) {
+    let server = match resources.get::<AssetServer>() {
+        Some(r) => r.clone(),
+        None => {
+            return;
+        }
+    };
+
+    server.finish_loads(resources);
+}
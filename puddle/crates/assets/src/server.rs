@@ -0,0 +1,393 @@
+use std::any::{Any, TypeId};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+
+use application::async_std;
+use application::log::{error, info};
+
+use crate::handle::{Handle, HandleInner, Slot};
+use crate::watcher::FileWatcher;
+use crate::AssetError;
+
+/// turns the bytes of a file into an asset
+pub trait AssetLoader: Send + Sync + 'static {
+    type Asset: Send + Sync + 'static;
+    /// what `decode` hands to `finish`
+    type Decoded: Send + 'static;
+
+    /// lowercase, without the dot
+    fn extensions(&self) -> &[&str];
+
+    /// runs on a background thread, do the slow parsing here
+    fn decode(&self, path: &Path, bytes: Vec<u8>) -> Result<Self::Decoded, String>;
+
+    /// runs on the main thread once per frame for every decoded asset, for gpu uploads
+    fn finish(
+        &self,
+        decoded: Self::Decoded,
+        resources: &legion::Resources,
+    ) -> Result<Self::Asset, String>;
+}
+
+/// a loader whose assets are done once they are decoded, nothing of them runs on the main thread
+pub trait DecodeLoader: Send + Sync + 'static {
+    type Asset: Send + Sync + 'static;
+
+    /// lowercase, without the dot
+    fn extensions(&self) -> &[&str];
+
+    /// runs on a background thread
+    fn decode(&self, path: &Path, bytes: Vec<u8>) -> Result<Self::Asset, String>;
+}
+
+impl<L: DecodeLoader> AssetLoader for L {
+    type Asset = L::Asset;
+    type Decoded = L::Asset;
+
+    fn extensions(&self) -> &[&str] {
+        DecodeLoader::extensions(self)
+    }
+
+    fn decode(&self, path: &Path, bytes: Vec<u8>) -> Result<Self::Decoded, String> {
+        DecodeLoader::decode(self, path, bytes)
+    }
+
+    fn finish(&self, asset: Self::Decoded, _: &legion::Resources) -> Result<Self::Asset, String> {
+        Ok(asset)
+    }
+}
+
+type Finish<A> = Box<dyn FnOnce(&legion::Resources) -> Result<A, String> + Send>;
+
+/// stores a decoded asset in its handle once it is finished on the main thread
+type PendingLoad = Box<dyn FnOnce(&legion::Resources) + Send>;
+
+/// an `AssetLoader` with the decoded type hidden, so loaders of one asset type can share a list
+trait ErasedLoader<A>: Send + Sync {
+    fn handles(&self, extension: &str) -> bool;
+    fn decode_erased(self: Arc<Self>, path: &Path, bytes: Vec<u8>) -> Result<Finish<A>, String>;
+}
+
+impl<L: AssetLoader> ErasedLoader<L::Asset> for L {
+    fn handles(&self, extension: &str) -> bool {
+        self.extensions().contains(&extension)
+    }
+
+    fn decode_erased(
+        self: Arc<Self>,
+        path: &Path,
+        bytes: Vec<u8>,
+    ) -> Result<Finish<L::Asset>, String> {
+        let decoded = self.decode(path, bytes)?;
+        Ok(Box::new(move |resources| self.finish(decoded, resources)))
+    }
+}
+
+/// a handle the server knows about
+struct Tracked {
+    handle: Weak<dyn Any + Send + Sync>,
+    /// loads the file into the handle again, with the loader it was loaded with
+    reload: Arc<dyn Fn(&AssetServer) + Send + Sync>,
+}
+
+#[derive(Default)]
+struct Internals {
+    root: PathBuf,
+    /// `Vec<Arc<dyn ErasedLoader<A>>>` for every asset type `A`
+    loaders: Mutex<HashMap<TypeId, Box<dyn Any + Send + Sync>>>,
+    /// the handles that are still alive, so a path is only loaded once
+    /// keyed by the asset type, or by the loader for `AssetServer::load_with`
+    handles: Mutex<HashMap<(TypeId, PathBuf), Tracked>>,
+    /// decoded assets waiting for `AssetServer::finish_loads`
+    pending: Mutex<Vec<PendingLoad>>,
+    /// `None` until `AssetServer::watch_for_changes`, or if the platform watcher failed to start
+    watcher: Mutex<Option<FileWatcher>>,
+}
+
+/// loads files in the background and hands out `Handle`s to them, cheap to clone
+#[derive(Clone, Default)]
+pub struct AssetServer {
+    internals: Arc<Internals>,
+}
+
+impl AssetServer {
+    /// relative paths are looked up in `root`
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            internals: Arc::new(Internals {
+                root: root.into(),
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.internals.root
+    }
+
+    /// the loader is picked by the extension of the path, later loaders win
+    pub fn register_loader<L: AssetLoader>(&self, loader: L) {
+        let loader: Arc<dyn ErasedLoader<L::Asset>> = Arc::new(loader);
+
+        let mut loaders = self.internals.loaders.lock().unwrap();
+        let list = loaders
+            .entry(TypeId::of::<L::Asset>())
+            .or_insert_with(|| Box::new(Vec::<Arc<dyn ErasedLoader<L::Asset>>>::new()));
+        list.downcast_mut::<Vec<Arc<dyn ErasedLoader<L::Asset>>>>()
+            .unwrap()
+            .push(loader);
+    }
+
+    fn loader<A: Send + Sync + 'static>(&self, path: &Path) -> Option<Arc<dyn ErasedLoader<A>>> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+
+        let loaders = self.internals.loaders.lock().unwrap();
+        loaders
+            .get(&TypeId::of::<A>())?
+            .downcast_ref::<Vec<Arc<dyn ErasedLoader<A>>>>()?
+            .iter()
+            .rev()
+            .find(|x| x.handles(&extension))
+            .cloned()
+    }
+
+    /// starts loading the file, or returns the handle of the same path if it is still alive
+    pub fn load<A: Send + Sync + 'static>(&self, path: impl AsRef<Path>) -> Handle<A> {
+        self.load_tracked(path.as_ref(), TypeId::of::<A>(), None)
+    }
+
+    /// like `load`, but with a loader that isn't registered,
+    /// for files that can be loaded in more than one way like cube maps
+    pub fn load_with<L: AssetLoader>(&self, path: impl AsRef<Path>, loader: L) -> Handle<L::Asset> {
+        self.load_tracked(path.as_ref(), TypeId::of::<L>(), Some(Arc::new(loader)))
+    }
+
+    fn load_tracked<A: Send + Sync + 'static>(
+        &self,
+        path: &Path,
+        key: TypeId,
+        loader: Option<Arc<dyn ErasedLoader<A>>>,
+    ) -> Handle<A> {
+        let path = self.path(path);
+        let key = (key, path.clone());
+
+        let mut handles = self.internals.handles.lock().unwrap();
+        if let Some(inner) = handles.get(&key).and_then(|x| x.handle.upgrade()) {
+            if let Ok(inner) = inner.downcast::<HandleInner<A>>() {
+                return Handle { inner };
+            }
+        }
+        handles.retain(|_, x| x.handle.strong_count() > 0);
+
+        let handle = Handle::<A>::loading(path.clone());
+        let weak: Weak<dyn Any + Send + Sync> = Arc::downgrade(&handle.inner) as _;
+        let inner = Arc::downgrade(&handle.inner);
+        let reload_loader = loader.clone();
+        handles.insert(
+            key,
+            Tracked {
+                handle: weak,
+                reload: Arc::new(move |server: &AssetServer| {
+                    if let Some(inner) = inner.upgrade() {
+                        info!("reloading {}", inner.path.display());
+                        server.spawn_load(&inner, reload_loader.clone());
+                    }
+                }),
+            },
+        );
+        drop(handles);
+
+        if let Some(watcher) = self.internals.watcher.lock().unwrap().as_mut() {
+            watcher.watch(&path);
+        }
+
+        self.spawn_load(&handle.inner, loader);
+        handle
+    }
+
+    /// canonical, so every spelling of a path shares a handle and matches the changes of the watcher
+    fn path(&self, path: impl AsRef<Path>) -> PathBuf {
+        let path = self.internals.root.join(path);
+        path.canonicalize().unwrap_or(path)
+    }
+
+    /// reads and decodes the file of the handle in the background, it is finished by `finish_loads`.
+    /// without a loader the registered loader for the extension is used
+    fn spawn_load<A: Send + Sync + 'static>(
+        &self,
+        inner: &Arc<HandleInner<A>>,
+        loader: Option<Arc<dyn ErasedLoader<A>>>,
+    ) {
+        let path = inner.path.clone();
+        let generation = inner.next_generation();
+
+        let loader = match loader.or_else(|| self.loader::<A>(&path)) {
+            Some(r) => r,
+            None => {
+                fail(inner, AssetError::NoLoader { path });
+                return;
+            }
+        };
+
+        let inner = Arc::downgrade(inner);
+        let internals = self.internals.clone();
+        async_std::task::spawn(async move {
+            let bytes = match async_std::fs::read(&path).await {
+                Ok(r) => r,
+                Err(e) => {
+                    if let Some(inner) = current(&inner, generation) {
+                        fail(
+                            &inner,
+                            AssetError::Io {
+                                path,
+                                message: e.to_string(),
+                            },
+                        );
+                    }
+                    return;
+                }
+            };
+
+            // nobody wants it anymore, or a newer load replaces this one
+            if current(&inner, generation).is_none() {
+                return;
+            }
+
+            let finish = match loader.decode_erased(&path, bytes) {
+                Ok(r) => r,
+                Err(message) => {
+                    if let Some(inner) = current(&inner, generation) {
+                        fail(&inner, AssetError::Decode { path, message });
+                    }
+                    return;
+                }
+            };
+
+            internals
+                .pending
+                .lock()
+                .unwrap()
+                .push(Box::new(move |resources| {
+                    // a reload that was started later finishes on its own
+                    let inner = match current(&inner, generation) {
+                        Some(r) => r,
+                        None => {
+                            return;
+                        }
+                    };
+
+                    match finish(resources) {
+                        Ok(asset) => inner.set_loaded(asset),
+                        Err(message) => fail(&inner, AssetError::Decode { path, message }),
+                    }
+                }));
+        });
+    }
+
+    /// reloads assets when their file changes, returns false if the platform has no file watcher
+    pub fn watch_for_changes(&self) -> bool {
+        let mut watcher = self.internals.watcher.lock().unwrap();
+        if watcher.is_some() {
+            return true;
+        }
+
+        *watcher = FileWatcher::new();
+        let watcher = match watcher.as_mut() {
+            Some(r) => r,
+            None => {
+                return false;
+            }
+        };
+
+        // the files that were loaded before
+        for (_, path) in self.internals.handles.lock().unwrap().keys() {
+            watcher.watch(path);
+        }
+        true
+    }
+
+    fn reload_changed(&self) {
+        let changed = match self.internals.watcher.lock().unwrap().as_ref() {
+            Some(r) => r.take_changed(),
+            None => {
+                return;
+            }
+        };
+        self.reload_paths(&changed);
+    }
+
+    /// loads the file again as if the watcher reported a change, for every live handle of it.
+    /// the handles keep their asset until the new one is finished
+    pub fn reload(&self, path: impl AsRef<Path>) {
+        self.reload_paths(&HashSet::from([self.path(path)]));
+    }
+
+    fn reload_paths(&self, changed: &HashSet<PathBuf>) {
+        if changed.is_empty() {
+            return;
+        }
+
+        let reloads: Vec<_> = self
+            .internals
+            .handles
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|((_, path), x)| changed.contains(path) && x.handle.strong_count() > 0)
+            .map(|(_, x)| x.reload.clone())
+            .collect();
+
+        for reload in reloads {
+            reload(self);
+        }
+    }
+
+    /// the handle of a path if something still holds it, without loading it.
+    /// handles from `load_with` aren't found
+    pub fn get_handle<A: Send + Sync + 'static>(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Option<Handle<A>> {
+        let key = (TypeId::of::<A>(), self.path(path));
+        let inner = self
+            .internals
+            .handles
+            .lock()
+            .unwrap()
+            .get(&key)?
+            .handle
+            .upgrade()?;
+
+        Some(Handle {
+            inner: inner.downcast::<HandleInner<A>>().ok()?,
+        })
+    }
+
+    /// finishes every asset decoded since the last call and starts reloading changed files,
+    /// the `AssetPlugin` calls it once per frame
+    pub fn finish_loads(&self, resources: &legion::Resources) {
+        self.reload_changed();
+
+        let pending = std::mem::take(&mut *self.internals.pending.lock().unwrap());
+
+        for finish in pending {
+            finish(resources);
+        }
+    }
+}
+
+/// the handle if it is still alive and no newer load of it was started
+fn current<A>(inner: &Weak<HandleInner<A>>, generation: u32) -> Option<Arc<HandleInner<A>>> {
+    inner.upgrade().filter(|x| x.is_current(generation))
+}
+
+/// a file that breaks after it was loaded keeps the last working asset
+fn fail<A>(inner: &HandleInner<A>, e: AssetError) {
+    error!("{}", e);
+
+    let mut slot = inner.slot.write().unwrap();
+    if !matches!(*slot, Slot::Loaded(_)) {
+        *slot = Slot::Failed(e);
+    }
+}
@@ -0,0 +1,90 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use application::log::{error, warn};
+
+/// watches the folders of loaded files and collects the files that changed
+pub(crate) struct FileWatcher {
+    watcher: notify::RecommendedWatcher,
+    watched_dirs: HashSet<PathBuf>,
+    changed: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl FileWatcher {
+    pub fn new() -> Option<Self> {
+        let changed = Arc::new(Mutex::new(HashSet::new()));
+
+        let events = changed.clone();
+        let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let event = match event {
+                Ok(r) => r,
+                Err(e) => {
+                    warn!("asset watcher : {}", e);
+                    return;
+                }
+            };
+            if !(event.kind.is_modify() || event.kind.is_create()) {
+                return;
+            }
+
+            if let Ok(mut changed) = events.lock() {
+                // editors often save by renaming a new file over the old one
+                changed.extend(
+                    event
+                        .paths
+                        .iter()
+                        .map(|x| x.canonicalize().unwrap_or(x.clone())),
+                );
+            }
+        });
+
+        match watcher {
+            Ok(watcher) => Some(Self {
+                watcher,
+                watched_dirs: HashSet::new(),
+                changed,
+            }),
+            Err(e) => {
+                warn!("asset hot reloading is disabled : {}", e);
+                None
+            }
+        }
+    }
+
+    /// `path` has to be canonical to match the reported changes
+    pub fn watch(&mut self, path: &Path) {
+        use notify::Watcher;
+
+        // the folder is watched instead of the file, the file itself might get replaced
+        let dir = match path.parent() {
+            Some(r) => r.to_path_buf(),
+            None => {
+                return;
+            }
+        };
+        if self.watched_dirs.contains(&dir) {
+            return;
+        }
+
+        match self
+            .watcher
+            .watch(&dir, notify::RecursiveMode::NonRecursive)
+        {
+            Ok(()) => {
+                self.watched_dirs.insert(dir);
+            }
+            Err(e) => warn!("failed to watch {} : {}", dir.display(), e),
+        }
+    }
+
+    pub fn take_changed(&self) -> HashSet<PathBuf> {
+        match self.changed.lock() {
+            Ok(mut r) => std::mem::take(&mut *r),
+            Err(e) => {
+                error!("{}", e);
+                HashSet::new()
+            }
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+
+use assets::{AssetLoader, AssetServer, DecodeLoader, Handle, LoadState};
+
+/// loads `.txt` files as strings, a file that says "broken" fails in `finish`
+#[derive(Default)]
+struct TextLoader {
+    finished: Arc<AtomicUsize>,
+}
+
+impl AssetLoader for TextLoader {
+    type Asset = String;
+    type Decoded = String;
+
+    fn extensions(&self) -> &[&str] {
+        &["txt"]
+    }
+
+    fn decode(&self, _path: &Path, bytes: Vec<u8>) -> Result<String, String> {
+        String::from_utf8(bytes).map_err(|e| e.to_string())
+    }
+
+    fn finish(&self, text: String, _resources: &legion::Resources) -> Result<String, String> {
+        self.finished.fetch_add(1, Ordering::SeqCst);
+        if text == "broken" {
+            return Err("the file is broken".to_string());
+        }
+        Ok(text)
+    }
+}
+
+/// loads `.len` files as their length in bytes, without a `finish` of its own
+struct LengthLoader;
+
+impl DecodeLoader for LengthLoader {
+    type Asset = usize;
+
+    fn extensions(&self) -> &[&str] {
+        &["len"]
+    }
+
+    fn decode(&self, _path: &Path, bytes: Vec<u8>) -> Result<usize, String> {
+        Ok(bytes.len())
+    }
+}
+
+/// an empty folder for the files of one test
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("puddle_assets_{}", name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// a server for `dir` with a `TextLoader`, and how often the loader finished an asset
+fn server(dir: &Path) -> (AssetServer, Arc<AtomicUsize>) {
+    let server = AssetServer::new(dir);
+    let loader = TextLoader::default();
+    let finished = loader.finished.clone();
+    server.register_loader(loader);
+    (server, finished)
+}
+
+/// runs `finish_loads` like the `AssetPlugin` does every frame until `done`
+fn wait(server: &AssetServer, done: impl Fn() -> bool) {
+    let resources = legion::Resources::default();
+    let start = Instant::now();
+    while !done() {
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "the asset took too long to load"
+        );
+        server.finish_loads(&resources);
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+fn wait_until_loaded(server: &AssetServer, handle: &Handle<String>) {
+    wait(server, || !matches!(handle.state(), LoadState::Loading));
+}
+
+#[test]
+fn loads_files_with_the_loader_of_their_extension() {
+    let dir = temp_dir("load");
+    fs::write(dir.join("a.txt"), "hello").unwrap();
+    let (server, _) = server(&dir);
+
+    let handle: Handle<String> = server.load("a.txt");
+    wait_until_loaded(&server, &handle);
+
+    assert_eq!(handle.get().as_deref().map(String::as_str), Some("hello"));
+    assert_eq!(handle.version(), 1);
+
+    let missing: Handle<String> = server.load("missing.txt");
+    wait_until_loaded(&server, &missing);
+    assert!(matches!(missing.state(), LoadState::Failed(_)));
+
+    let unknown: Handle<String> = server.load("a.png");
+    assert!(matches!(unknown.state(), LoadState::Failed(_)));
+}
+
+#[test]
+fn every_spelling_of_a_path_shares_a_handle() {
+    let dir = temp_dir("dedup");
+    fs::create_dir_all(dir.join("sub")).unwrap();
+    fs::write(dir.join("sub/a.txt"), "a").unwrap();
+    let (server, finished) = server(&dir);
+
+    let first: Handle<String> = server.load("sub/a.txt");
+    let second: Handle<String> = server.load("./sub/../sub/a.txt");
+    let absolute: Handle<String> = server.load(dir.join("sub/a.txt"));
+
+    assert_eq!(first, second);
+    assert_eq!(first, absolute);
+    assert_eq!(
+        server.get_handle::<String>("sub/a.txt"),
+        Some(first.clone())
+    );
+
+    wait_until_loaded(&server, &first);
+    assert_eq!(finished.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn dropping_the_last_handle_frees_the_asset() {
+    let dir = temp_dir("drop");
+    fs::write(dir.join("a.txt"), "a").unwrap();
+    let (server, finished) = server(&dir);
+
+    let handle: Handle<String> = server.load("a.txt");
+    wait_until_loaded(&server, &handle);
+
+    let asset: Weak<String> = Arc::downgrade(&handle.get().unwrap());
+    let clone = handle.clone();
+    drop(handle);
+    assert!(asset.upgrade().is_some());
+
+    drop(clone);
+    assert!(asset.upgrade().is_none());
+    assert_eq!(server.get_handle::<String>("a.txt"), None);
+
+    // loaded again from the file
+    let handle: Handle<String> = server.load("a.txt");
+    wait_until_loaded(&server, &handle);
+    assert_eq!(finished.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn reloads_replace_the_asset() {
+    let dir = temp_dir("reload");
+    fs::write(dir.join("a.txt"), "first").unwrap();
+    let (server, _) = server(&dir);
+
+    let handle: Handle<String> = server.load("a.txt");
+    wait_until_loaded(&server, &handle);
+
+    fs::write(dir.join("a.txt"), "second").unwrap();
+    server.reload("a.txt");
+    wait(&server, || handle.version() == 2);
+
+    assert_eq!(handle.get().as_deref().map(String::as_str), Some("second"));
+}
+
+#[test]
+fn a_broken_reload_keeps_the_last_good_asset() {
+    let dir = temp_dir("fail");
+    fs::write(dir.join("a.txt"), "good").unwrap();
+    let (server, finished) = server(&dir);
+
+    let handle: Handle<String> = server.load("a.txt");
+    wait_until_loaded(&server, &handle);
+
+    fs::write(dir.join("a.txt"), "broken").unwrap();
+    server.reload("a.txt");
+    wait(&server, || finished.load(Ordering::SeqCst) == 2);
+
+    assert!(matches!(handle.state(), LoadState::Loaded));
+    assert_eq!(handle.get().as_deref().map(String::as_str), Some("good"));
+    assert_eq!(handle.version(), 1);
+}
+
+#[test]
+fn only_the_newest_reload_lands() {
+    let dir = temp_dir("generation");
+    fs::write(dir.join("a.txt"), "first").unwrap();
+    let (server, finished) = server(&dir);
+
+    let handle: Handle<String> = server.load("a.txt");
+    wait_until_loaded(&server, &handle);
+
+    fs::write(dir.join("a.txt"), "second").unwrap();
+    server.reload("a.txt");
+    fs::write(dir.join("a.txt"), "third").unwrap();
+    server.reload("a.txt");
+
+    wait(&server, || handle.version() == 2);
+    // the older reload is dropped even if it finishes later
+    let start = Instant::now();
+    wait(&server, || start.elapsed() > Duration::from_millis(50));
+
+    assert_eq!(handle.get().as_deref().map(String::as_str), Some("third"));
+    assert_eq!(handle.version(), 2);
+    assert_eq!(finished.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn a_broken_first_load_fails() {
+    let dir = temp_dir("broken");
+    fs::write(dir.join("a.txt"), "broken").unwrap();
+    let (server, _) = server(&dir);
+
+    let handle: Handle<String> = server.load("a.txt");
+    wait_until_loaded(&server, &handle);
+
+    assert!(matches!(handle.state(), LoadState::Failed(_)));
+    assert_eq!(handle.get(), None);
+}
+
+#[test]
+fn decode_loaders_are_done_once_the_file_is_decoded() {
+    let dir = temp_dir("decode_loader");
+    fs::write(dir.join("a.len"), "four").unwrap();
+    let server = AssetServer::new(&dir);
+    server.register_loader(LengthLoader);
+
+    let handle: Handle<usize> = server.load("a.len");
+    wait(&server, || !matches!(handle.state(), LoadState::Loading));
+
+    assert_eq!(handle.get().as_deref(), Some(&4));
+}
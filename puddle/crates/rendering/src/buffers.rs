@@ -0,0 +1,314 @@
+use std::num::NonZeroU64;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+use crate::material_params::MaterialResource;
+use crate::PuddleBindGroupEntry;
+
+/// every host shareable wgsl type is made of 4 byte scalars.
+/// the alignment of vectors and structs is checked against the shader when the material is built
+fn check_layout<T>(kind: &str) {
+    let size = std::mem::size_of::<T>();
+    assert!(
+        size > 0 && size.is_multiple_of(4),
+        "{} of {} has a size of {} bytes, wgsl types are a multiple of 4 bytes",
+        kind,
+        std::any::type_name::<T>(),
+        size
+    );
+}
+
+/// a value in a uniform buffer, changes through `DerefMut` are uploaded by the next `upload`.
+/// `T` has to match the wgsl struct byte for byte, a `vec3` needs a padding field after it
+pub struct Uniform<T: bytemuck::Pod> {
+    value: T,
+    buffer: Arc<wgpu::Buffer>,
+    dirty: bool,
+}
+
+impl<T: bytemuck::Pod> Uniform<T> {
+    pub fn new(device: &wgpu::Device, label: &str, value: T) -> Self {
+        check_layout::<T>("a uniform");
+
+        // uniform structs are padded to 16 bytes
+        let size = std::mem::size_of::<T>().next_multiple_of(16);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: size as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: true,
+        });
+        buffer.slice(..).get_mapped_range_mut()[..std::mem::size_of::<T>()]
+            .copy_from_slice(bytemuck::bytes_of(&value));
+        buffer.unmap();
+
+        Self {
+            value,
+            buffer: Arc::new(buffer),
+            dirty: false,
+        }
+    }
+
+    pub fn set(&mut self, value: T) {
+        self.value = value;
+        self.dirty = true;
+    }
+
+    pub fn buffer(&self) -> &Arc<wgpu::Buffer> {
+        &self.buffer
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// writes the value to the gpu if it changed, returns whether it did
+    pub fn upload(&mut self, queue: &wgpu::Queue) -> bool {
+        if !self.dirty {
+            return false;
+        }
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&self.value));
+        self.dirty = false;
+        true
+    }
+
+    /// binds the buffer, a shader struct that doesn't fit fails when the material is built
+    pub fn entry(&self, name: &str, visibility: wgpu::ShaderStages) -> PuddleBindGroupEntry {
+        PuddleBindGroupEntry {
+            name: name.to_string(),
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: NonZeroU64::new(std::mem::size_of::<T>() as u64),
+            },
+            visibility,
+            resource: MaterialResource::Buffer(self.buffer.clone()),
+        }
+    }
+}
+
+impl<T: bytemuck::Pod> Deref for Uniform<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: bytemuck::Pod> DerefMut for Uniform<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.dirty = true;
+        &mut self.value
+    }
+}
+
+/// what `StorageVec::upload` has to do with the buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Upload {
+    /// nothing changed since the last upload
+    Skip,
+    /// the elements fit, `stale` elements behind them were removed since the last upload
+    Write { stale: usize },
+    /// the elements don't fit, the buffer is recreated with room for this many
+    Grow(usize),
+}
+
+fn plan_upload(len: usize, capacity: usize, uploaded: usize, dirty: bool) -> Upload {
+    if len > capacity {
+        return Upload::Grow(len.next_power_of_two());
+    }
+    if !dirty {
+        return Upload::Skip;
+    }
+    Upload::Write {
+        stale: uploaded.saturating_sub(len),
+    }
+}
+
+/// a growable array in a storage buffer, bound as `array<T>` in wgsl.
+/// the buffer is recreated when the elements don't fit anymore, rebind it with `Material::set_buffer`.
+/// the whole buffer stays visible to shaders, removed elements are zeroed
+pub struct StorageVec<T: bytemuck::Pod> {
+    data: Vec<T>,
+    buffer: Arc<wgpu::Buffer>,
+    /// how many elements fit into the buffer
+    capacity: usize,
+    /// how many elements the buffer holds since the last upload
+    uploaded: usize,
+    label: String,
+    dirty: bool,
+}
+
+impl<T: bytemuck::Pod> StorageVec<T> {
+    pub fn new(device: &wgpu::Device, label: &str, data: Vec<T>) -> Self {
+        Self::with_data(device, label, data, 1)
+    }
+
+    pub fn with_capacity(device: &wgpu::Device, label: &str, capacity: usize) -> Self {
+        Self::with_data(device, label, Vec::with_capacity(capacity), capacity)
+    }
+
+    fn with_data(device: &wgpu::Device, label: &str, data: Vec<T>, capacity: usize) -> Self {
+        check_layout::<T>("a storage array");
+
+        // empty bindings aren't allowed
+        let capacity = data.len().max(capacity).max(1);
+        let buffer = Self::create_buffer(device, label, capacity, &data);
+
+        Self {
+            uploaded: data.len(),
+            data,
+            buffer,
+            capacity,
+            label: label.to_string(),
+            dirty: false,
+        }
+    }
+
+    fn create_buffer(
+        device: &wgpu::Device,
+        label: &str,
+        capacity: usize,
+        data: &[T],
+    ) -> Arc<wgpu::Buffer> {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: (capacity * std::mem::size_of::<T>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: true,
+        });
+        let bytes: &[u8] = bytemuck::cast_slice(data);
+        buffer.slice(..).get_mapped_range_mut()[..bytes.len()].copy_from_slice(bytes);
+        buffer.unmap();
+        Arc::new(buffer)
+    }
+
+    pub fn buffer(&self) -> &Arc<wgpu::Buffer> {
+        &self.buffer
+    }
+
+    /// how many elements fit without recreating the buffer
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// writes the elements to the gpu if they changed.
+    /// returns true if the buffer was recreated and has to be bound again
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> bool {
+        let upload = plan_upload(self.data.len(), self.capacity, self.uploaded, self.dirty);
+        self.dirty = false;
+        self.uploaded = self.data.len();
+
+        match upload {
+            Upload::Skip => false,
+            Upload::Write { stale } => {
+                if !self.data.is_empty() {
+                    queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&self.data));
+                }
+                if stale > 0 {
+                    let size = std::mem::size_of::<T>();
+                    let offset = (self.data.len() * size) as wgpu::BufferAddress;
+                    queue.write_buffer(&self.buffer, offset, &vec![0u8; stale * size]);
+                }
+                false
+            }
+            Upload::Grow(capacity) => {
+                self.capacity = capacity;
+                self.buffer = Self::create_buffer(device, &self.label, self.capacity, &self.data);
+                true
+            }
+        }
+    }
+
+    /// binds the buffer read only, shaders can't write storage buffers in the vertex stage
+    pub fn entry(&self, name: &str, visibility: wgpu::ShaderStages) -> PuddleBindGroupEntry {
+        self.entry_with_access(name, visibility, true)
+    }
+
+    /// binds the buffer so compute and fragment shaders can write into it
+    pub fn entry_read_write(
+        &self,
+        name: &str,
+        visibility: wgpu::ShaderStages,
+    ) -> PuddleBindGroupEntry {
+        self.entry_with_access(name, visibility, false)
+    }
+
+    fn entry_with_access(
+        &self,
+        name: &str,
+        visibility: wgpu::ShaderStages,
+        read_only: bool,
+    ) -> PuddleBindGroupEntry {
+        PuddleBindGroupEntry {
+            name: name.to_string(),
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: NonZeroU64::new(std::mem::size_of::<T>() as u64),
+            },
+            visibility,
+            resource: MaterialResource::Buffer(self.buffer.clone()),
+        }
+    }
+}
+
+impl<T: bytemuck::Pod> Deref for StorageVec<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Vec<T> {
+        &self.data
+    }
+}
+
+impl<T: bytemuck::Pod> DerefMut for StorageVec<T> {
+    fn deref_mut(&mut self) -> &mut Vec<T> {
+        self.dirty = true;
+        &mut self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_clean_data() {
+        assert_eq!(plan_upload(3, 4, 3, false), Upload::Skip);
+        assert_eq!(plan_upload(0, 1, 0, false), Upload::Skip);
+    }
+
+    #[test]
+    fn writes_changed_data_that_fits() {
+        assert_eq!(plan_upload(3, 4, 3, true), Upload::Write { stale: 0 });
+        assert_eq!(plan_upload(4, 4, 2, true), Upload::Write { stale: 0 });
+    }
+
+    #[test]
+    fn zeroes_removed_elements() {
+        assert_eq!(plan_upload(1, 4, 3, true), Upload::Write { stale: 2 });
+        // cleared to empty
+        assert_eq!(plan_upload(0, 4, 4, true), Upload::Write { stale: 4 });
+    }
+
+    #[test]
+    fn grows_to_the_next_power_of_two() {
+        assert_eq!(plan_upload(5, 4, 4, true), Upload::Grow(8));
+        assert_eq!(plan_upload(8, 1, 1, true), Upload::Grow(8));
+        assert_eq!(plan_upload(2, 1, 0, true), Upload::Grow(2));
+    }
+
+    #[test]
+    fn checks_scalar_sizes() {
+        check_layout::<[f32; 3]>("a test");
+        check_layout::<u32>("a test");
+        assert!(std::panic::catch_unwind(|| check_layout::<u16>("a test")).is_err());
+        assert!(std::panic::catch_unwind(|| check_layout::<()>("a test")).is_err());
+    }
+}
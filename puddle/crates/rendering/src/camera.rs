@@ -16,6 +16,14 @@ impl CameraUniform {
         }
     }
 
+    /// a uniform for a matrix that doesn't come from a `Camera`, like the view of a light
+    pub fn from_view_proj(view_proj: cgmath::Matrix4<f32>, eye: cgmath::Point3<f32>) -> Self {
+        Self {
+            view_proj: view_proj.into(),
+            camera_eye: [eye.x, eye.y, eye.z, 1.0],
+        }
+    }
+
     pub fn update_view_proj(&mut self, camera: &Camera) {
         self.view_proj = camera.build_view_projection_matrix().into();
         self.camera_eye = [camera.eye.x, camera.eye.y, camera.eye.z, 1.0].into();
@@ -29,25 +37,166 @@ use winit::{event::*, keyboard::PhysicalKey};
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
     0.0, 1.0, 0.0, 0.0,
-    0.0, 0.0, 0.5, 0.5,
-    0.0, 0.0, 0.0, 1.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
 );
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    Perspective,
+    /// `height` is the size of the view volume in world units, the width follows the aspect
+    Orthographic { height: f32 },
+    /// perspective without a far plane, depth goes from 1 at `znear` to 0 at infinity.
+    /// materials compare with `GreaterEqual` and the depth buffer is cleared to 0
+    InfiniteReverseZ,
+}
+
+impl Projection {
+    pub fn is_reverse_z(&self) -> bool {
+        matches!(self, Projection::InfiniteReverseZ)
+    }
+
+    pub fn depth_compare(&self) -> wgpu::CompareFunction {
+        if self.is_reverse_z() {
+            wgpu::CompareFunction::GreaterEqual
+        } else {
+            wgpu::CompareFunction::LessEqual
+        }
+    }
+
+    pub fn depth_clear_value(&self) -> f32 {
+        if self.is_reverse_z() {
+            0.0
+        } else {
+            1.0
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ray {
+    pub origin: cgmath::Point3<f32>,
+    pub direction: cgmath::Vector3<f32>,
+}
+
+#[derive(Debug, Clone, Copy)]
 pub struct Camera {
     pub eye: cgmath::Point3<f32>,
     pub target: cgmath::Point3<f32>,
-    up: cgmath::Vector3<f32>,
-    aspect: f32,
-    fovy: f32,
-    znear: f32,
-    zfar: f32,
+    pub up: cgmath::Vector3<f32>,
+    pub projection: Projection,
+    pub aspect: f32,
+    /// vertical field of view in degrees, unused by orthographic cameras
+    pub fovy: f32,
+    pub znear: f32,
+    /// unused by `Projection::InfiniteReverseZ`
+    pub zfar: f32,
+    /// update `aspect` when the window is resized
+    pub auto_aspect: bool,
 }
 
 impl Camera {
+    pub fn build_view_matrix(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up)
+    }
+
+    /// the projection in wgpu clip space (depth from 0 to 1)
+    pub fn build_projection_matrix(&self) -> cgmath::Matrix4<f32> {
+        match self.projection {
+            Projection::Perspective => {
+                let proj =
+                    cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
+                OPENGL_TO_WGPU_MATRIX * proj
+            }
+            Projection::Orthographic { height } => {
+                let half_h = height / 2.0;
+                let half_w = half_h * self.aspect;
+                let proj = cgmath::ortho(-half_w, half_w, -half_h, half_h, self.znear, self.zfar);
+                OPENGL_TO_WGPU_MATRIX * proj
+            }
+            Projection::InfiniteReverseZ => {
+                let f = 1.0 / (cgmath::Rad::from(cgmath::Deg(self.fovy)).0 / 2.0).tan();
+
+                #[rustfmt::skip]
+                let proj = cgmath::Matrix4::new(
+                    f / self.aspect, 0.0, 0.0, 0.0,
+                    0.0, f, 0.0, 0.0,
+                    0.0, 0.0, 0.0, -1.0,
+                    0.0, 0.0, self.znear, 0.0,
+                );
+                proj
+            }
+        }
+    }
+
     pub fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
-        let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
-        let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
-        OPENGL_TO_WGPU_MATRIX * proj * view
+        self.build_projection_matrix() * self.build_view_matrix()
+    }
+
+    /// the ray through a pixel, `screen_pos` and `screen_size` are in pixels with the origin in the top left
+    pub fn screen_to_world_ray(
+        &self,
+        screen_pos: cgmath::Vector2<f32>,
+        screen_size: cgmath::Vector2<f32>,
+    ) -> Option<Ray> {
+        let inverse = self.build_view_projection_matrix().invert()?;
+
+        let ndc_x = screen_pos.x / screen_size.x * 2.0 - 1.0;
+        let ndc_y = 1.0 - screen_pos.y / screen_size.y * 2.0;
+
+        // reverse z has its far plane at infinity, so take a point in between instead
+        let (near_depth, far_depth) = if self.projection.is_reverse_z() {
+            (1.0, 0.5)
+        } else {
+            (0.0, 1.0)
+        };
+
+        let unproject = |depth: f32| {
+            let p = inverse * cgmath::Vector4::new(ndc_x, ndc_y, depth, 1.0);
+            cgmath::Point3::new(p.x / p.w, p.y / p.w, p.z / p.w)
+        };
+
+        let near = unproject(near_depth);
+        let far = unproject(far_depth);
+
+        Some(Ray {
+            origin: near,
+            direction: (far - near).normalize(),
+        })
+    }
+
+    /// the pixel a world position ends up on, `None` if it is behind the camera
+    pub fn world_to_screen(
+        &self,
+        position: cgmath::Point3<f32>,
+        screen_size: cgmath::Vector2<f32>,
+    ) -> Option<cgmath::Point2<f32>> {
+        let clip = self.build_view_projection_matrix() * position.to_homogeneous();
+
+        if clip.w <= 0.0 {
+            return None;
+        }
+
+        let ndc_x = clip.x / clip.w;
+        let ndc_y = clip.y / clip.w;
+
+        Some(cgmath::Point2::new(
+            (ndc_x + 1.0) / 2.0 * screen_size.x,
+            (1.0 - ndc_y) / 2.0 * screen_size.y,
+        ))
+    }
+
+    /// a camera looking down the -z axis of `transform`, the way gltf cameras are oriented
+    pub fn from_transform(transform: &crate::ModelMatrix, aspect: f32) -> Self {
+        let eye = cgmath::Point3::from_vec(transform.position);
+        let forward = transform.rotation * Vector3::new(0.0, 0.0, -1.0);
+
+        Camera {
+            target: eye + forward,
+            up: transform.rotation * Vector3::unit_y(),
+            eye,
+            ..Self::default(aspect)
+        }
     }
 
     pub fn default(aspect: f32) -> Self {
@@ -55,9 +204,11 @@ impl Camera {
             eye: (2.0, 1.0, 0.1).into(),
             target: (0.0, 0.0, 0.0).into(),
             up: cgmath::Vector3::unit_y(),
+            projection: Projection::Perspective,
             fovy: 60.0,
             znear: 0.1,
-            zfar: 100.0,
+            zfar: 1000.0,
+            auto_aspect: true,
             aspect,
         }
     }
@@ -0,0 +1,209 @@
+use std::sync::Arc;
+
+use wgpu::util::DeviceExt;
+
+use crate::{Camera, CameraBindGroupLayout, CameraUniform, Renderer};
+
+/// a rect in normalized target coordinates, (0, 0) is the top left
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Viewport {
+    pub const FULL: Viewport = Viewport {
+        x: 0.0,
+        y: 0.0,
+        width: 1.0,
+        height: 1.0,
+    };
+
+    /// the viewport in pixels as (x, y, width, height)
+    pub fn to_pixels(&self, target_width: u32, target_height: u32) -> [f32; 4] {
+        [
+            self.x * target_width as f32,
+            self.y * target_height as f32,
+            self.width * target_width as f32,
+            self.height * target_height as f32,
+        ]
+    }
+}
+
+/// a bit mask of up to 32 layers. a camera draws a material if they share a layer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RenderLayers(pub u32);
+
+impl RenderLayers {
+    /// the layer materials and cameras start on
+    pub const DEFAULT: RenderLayers = RenderLayers(1);
+    pub const ALL: RenderLayers = RenderLayers(u32::MAX);
+    pub const NONE: RenderLayers = RenderLayers(0);
+
+    /// only the layer `layer`, from 0 to 31
+    pub const fn layer(layer: u32) -> Self {
+        RenderLayers(1 << layer)
+    }
+
+    /// these layers and `layer`
+    pub const fn with(self, layer: u32) -> Self {
+        RenderLayers(self.0 | (1 << layer))
+    }
+
+    /// these layers without `layer`
+    pub const fn without(self, layer: u32) -> Self {
+        RenderLayers(self.0 & !(1 << layer))
+    }
+
+    pub const fn intersects(self, other: RenderLayers) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl Default for RenderLayers {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+pub enum RenderTarget {
+    Swapchain,
+    /// an offscreen texture, other materials can bind its `view` and `sampler`
+    Texture(Arc<texture::Texture>),
+}
+
+impl RenderTarget {
+    /// creates an offscreen target with the format the material pipelines are built for
+    pub fn texture(renderer: &Renderer, width: u32, height: u32) -> Arc<texture::Texture> {
+        Arc::new(texture::Texture::create_render_target(
+            &renderer.device,
+            width,
+            height,
+            Renderer::HDR_FORMAT,
+            "render target",
+        ))
+    }
+
+    pub fn size(&self, surface_config: &wgpu::SurfaceConfiguration) -> (u32, u32) {
+        match self {
+            RenderTarget::Swapchain => (surface_config.width, surface_config.height),
+            RenderTarget::Texture(texture) => {
+                let size = texture.texture.size();
+                (size.width, size.height)
+            }
+        }
+    }
+}
+
+/// a camera component, rendered in addition to the `Camera` resource.
+/// offscreen targets are drawn first, then everything by ascending `priority`
+pub struct CameraView {
+    pub camera: Camera,
+    pub viewport: Viewport,
+    pub priority: i32,
+    pub target: RenderTarget,
+    /// used by the first camera that draws to a target each frame
+    pub clear_color: wgpu::Color,
+    /// the layers of the materials this camera draws. keep materials that sample
+    /// an offscreen target off the layers of the cameras drawing into it
+    pub layers: RenderLayers,
+
+    pub(crate) uniform: CameraUniform,
+    pub(crate) buffer: wgpu::Buffer,
+    pub(crate) bind_group: wgpu::BindGroup,
+    /// offscreen targets get their own depth buffer, the swapchain one is shared.
+    /// materials sample its copy in bind group 2 like the one of the swapchain
+    pub(crate) depth: Option<crate::depth::DepthTargets>,
+    /// multisampled color of offscreen targets, resolved into the target texture
+    pub(crate) msaa_color: Option<texture::Texture>,
+    /// the sample count the attachments were created with
+    pub(crate) sample_count: u32,
+}
+
+impl CameraView {
+    pub fn new(
+        renderer: &Renderer,
+        camera_bind_group_layout: &CameraBindGroupLayout,
+        camera: Camera,
+        target: RenderTarget,
+    ) -> Self {
+        let mut uniform = CameraUniform::new();
+        uniform.update_view_proj(&camera);
+
+        let buffer = renderer
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Camera View Buffer"),
+                contents: bytemuck::cast_slice(&[uniform]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+
+        let bind_group = renderer
+            .device
+            .create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &camera_bind_group_layout.0,
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                }],
+                label: Some("camera_view_bind_group"),
+            });
+
+        let mut view = Self {
+            camera,
+            viewport: Viewport::FULL,
+            priority: 0,
+            target,
+            clear_color: wgpu::Color {
+                b: 0.01,
+                ..wgpu::Color::BLACK
+            },
+            layers: RenderLayers::DEFAULT,
+            uniform,
+            buffer,
+            bind_group,
+            depth: None,
+            msaa_color: None,
+            sample_count: renderer.sample_count,
+        };
+        view.create_attachments(renderer);
+        view
+    }
+
+    /// recreates the depth buffer and msaa color of offscreen targets with the sample count of the renderer
+    pub(crate) fn create_attachments(&mut self, renderer: &Renderer) {
+        self.sample_count = renderer.sample_count;
+        if let RenderTarget::Swapchain = self.target {
+            self.depth = None;
+            self.msaa_color = None;
+            return;
+        }
+
+        let (width, height) = self.target.size(&renderer.surface_config);
+        self.depth = Some(crate::depth::DepthTargets::with_layout(
+            &renderer.device,
+            renderer.depth.bind_group_layout.clone(),
+            width,
+            height,
+            renderer.sample_count,
+        ));
+        self.msaa_color = crate::msaa::create_msaa_color(&renderer.device, width, height, renderer.sample_count);
+    }
+
+    pub fn with_viewport(mut self, viewport: Viewport) -> Self {
+        self.viewport = viewport;
+        self
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_layers(mut self, layers: RenderLayers) -> Self {
+        self.layers = layers;
+        self
+    }
+}
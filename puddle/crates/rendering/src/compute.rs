@@ -0,0 +1,342 @@
+use std::sync::Arc;
+
+use legion::{Entity, EntityStore, IntoQuery};
+
+use crate::material_params::{self, create_bind_group, layout_entries, MaterialBinding};
+use crate::pipeline_cache::{SharedLayout, SharedShader};
+use crate::reflection;
+use crate::shader_assets::{self, ShaderError};
+use crate::{
+    MaterialResource, NodeContext, NodeDesc, ParamError, ParamValue, PuddleBindGroupEntry,
+    RenderNode, Renderer,
+};
+
+/// the bind group the resources of a compute material are bound to, compute shaders see no camera
+pub const COMPUTE_GROUP: u32 = 0;
+
+/// when the compute pass runs a material
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dispatch {
+    #[default]
+    EveryFrame,
+    /// runs in the next frame that is submitted, then turns into `Paused`.
+    /// the dispatch is on the queue once the material is paused
+    Once,
+    Paused,
+}
+
+/// a compute shader with its resources, dispatched by the `ComputePassNode` before anything is drawn
+pub struct ComputeMaterial {
+    pub pipeline: Arc<wgpu::ComputePipeline>,
+    /// rebuilt before the next frame when a resource is replaced with `ComputeMaterial::set_resource`
+    pub bind_group: Arc<wgpu::BindGroup>,
+    pub(crate) bindings: Vec<MaterialBinding>,
+    pub(crate) bind_group_dirty: bool,
+    bind_group_layout: SharedLayout,
+    shader: SharedShader,
+    device: Arc<wgpu::Device>,
+
+    /// the `@workgroup_size` of the entry point
+    workgroup_size: [u32; 3],
+    /// how many workgroups are dispatched, nothing runs while one of them is 0
+    pub workgroups: [u32; 3],
+    pub dispatch: Dispatch,
+    /// lower runs first, materials with the same order run in the order of the query
+    pub order: i32,
+}
+
+impl ComputeMaterial {
+    /// compute wgsl that runs through the shader preprocessor of the renderer
+    pub fn from_wgsl(file: impl Into<String>, source: impl Into<String>) -> ComputeMaterialBuilder {
+        ComputeMaterialBuilder {
+            file: file.into(),
+            source: source.into(),
+            entries: vec![],
+            entry_point: "cs_main".to_string(),
+            label: None,
+            workgroups: [0; 3],
+            dispatch: Dispatch::EveryFrame,
+            order: 0,
+        }
+    }
+
+    pub fn workgroup_size(&self) -> [u32; 3] {
+        self.workgroup_size
+    }
+
+    /// dispatches enough workgroups for one invocation per thread, like one per texel of a texture
+    pub fn dispatch_threads(&mut self, threads: [u32; 3]) {
+        for (i, threads) in threads.iter().enumerate() {
+            self.workgroups[i] = threads.div_ceil(self.workgroup_size[i]);
+        }
+    }
+
+    /// changes a field of a `MaterialResource::Uniforms` binding, it is uploaded before the next frame
+    pub fn set_param(&mut self, name: &str, value: ParamValue) -> Result<(), ParamError> {
+        material_params::set_param(&mut self.bindings, name, value)
+    }
+
+    pub fn param(&self, name: &str) -> Option<ParamValue> {
+        material_params::param(&self.bindings, name)
+    }
+
+    /// replaces the resource of the binding called `name`, the bind group is rebuilt before the next frame
+    pub fn set_resource(
+        &mut self,
+        name: &str,
+        resource: MaterialResource,
+    ) -> Result<(), ParamError> {
+        material_params::set_resource(&self.device, &mut self.bindings, name, resource)?;
+        self.bind_group_dirty = true;
+        Ok(())
+    }
+
+    pub fn set_texture(
+        &mut self,
+        name: &str,
+        texture: Arc<texture::Texture>,
+    ) -> Result<(), ParamError> {
+        self.set_resource(name, MaterialResource::Texture(texture))
+    }
+
+    pub fn set_buffer(&mut self, name: &str, buffer: Arc<wgpu::Buffer>) -> Result<(), ParamError> {
+        self.set_resource(name, MaterialResource::Buffer(buffer))
+    }
+
+    pub(crate) fn update_bindings(&mut self, queue: &wgpu::Queue) {
+        material_params::upload_uniforms(&mut self.bindings, queue);
+        if material_params::refresh_assets(&mut self.bindings) {
+            self.bind_group_dirty = true;
+        }
+
+        if self.bind_group_dirty {
+            self.bind_group = Arc::new(create_bind_group(
+                &self.device,
+                &self.bind_group_layout.layout,
+                &self.bindings,
+            ));
+            self.bind_group_dirty = false;
+        }
+    }
+}
+
+/// builds a `ComputeMaterial`, start with `ComputeMaterial::from_wgsl`
+pub struct ComputeMaterialBuilder {
+    file: String,
+    source: String,
+    entries: Vec<PuddleBindGroupEntry>,
+    entry_point: String,
+    label: Option<String>,
+    workgroups: [u32; 3],
+    dispatch: Dispatch,
+    order: i32,
+}
+
+impl ComputeMaterialBuilder {
+    /// the resources of group 0, matched with the wgsl variables of the same name
+    pub fn entries(mut self, entries: Vec<PuddleBindGroupEntry>) -> Self {
+        self.entries = entries;
+        self
+    }
+
+    pub fn entry(mut self, entry: PuddleBindGroupEntry) -> Self {
+        self.entries.push(entry);
+        self
+    }
+
+    /// `cs_main` by default
+    pub fn entry_point(mut self, entry_point: impl Into<String>) -> Self {
+        self.entry_point = entry_point.into();
+        self
+    }
+
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn workgroups(mut self, x: u32, y: u32, z: u32) -> Self {
+        self.workgroups = [x, y, z];
+        self
+    }
+
+    pub fn dispatch(mut self, dispatch: Dispatch) -> Self {
+        self.dispatch = dispatch;
+        self
+    }
+
+    pub fn order(mut self, order: i32) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn build(self, renderer: &mut Renderer) -> Result<ComputeMaterial, ShaderError> {
+        let source = renderer
+            .shader_preprocessor
+            .process(&self.file, &self.source)?;
+        let (module, info) = shader_assets::validate_preprocessed_with_info(&source)?;
+
+        let workgroup_size = match module
+            .entry_points
+            .iter()
+            .find(|x| x.stage == naga::ShaderStage::Compute && x.name == self.entry_point)
+        {
+            Some(r) => r.workgroup_size,
+            None => {
+                return Err(ShaderError::Wgpu {
+                    file: source.file.clone(),
+                    message: format!("the shader has no compute entry point {}", self.entry_point),
+                });
+            }
+        };
+
+        let mut bindings: Vec<MaterialBinding> = self
+            .entries
+            .into_iter()
+            .enumerate()
+            .map(|(i, x)| MaterialBinding::new(&renderer.device, i as u32, x))
+            .collect();
+
+        let reflected = reflection::reflect_bindings(&module, &info, COMPUTE_GROUP);
+        let given: Vec<_> = bindings.iter().map(MaterialBinding::given).collect();
+        let resolved =
+            reflection::resolve_bindings(&source.file, COMPUTE_GROUP, &reflected, &given)?;
+        for (binding, resolved) in bindings.iter_mut().zip(resolved) {
+            binding.resolve(resolved);
+        }
+
+        let device = renderer.device.clone();
+        let shader = renderer
+            .pipeline_cache
+            .shader(&device, &source.file, &source.source, module)?;
+        let bind_group_layout = renderer.pipeline_cache.bind_group_layout(
+            &device,
+            &layout_entries(bindings.iter().map(|x| (x.binding, x.visibility, x.ty))),
+        );
+
+        let label = self.label.as_deref().unwrap_or("Compute Pipeline");
+        let (pipeline, bind_group) =
+            shader_assets::catch_wgpu_errors(&device, &source.file, || {
+                let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("compute_pipeline_layout"),
+                    bind_group_layouts: &[&bind_group_layout.layout],
+                    push_constant_ranges: &[],
+                });
+                let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some(label),
+                    layout: Some(&layout),
+                    module: &shader.module,
+                    entry_point: self.entry_point.as_str(),
+                });
+
+                (
+                    pipeline,
+                    create_bind_group(&device, &bind_group_layout.layout, &bindings),
+                )
+            })?;
+
+        Ok(ComputeMaterial {
+            pipeline: Arc::new(pipeline),
+            bind_group: Arc::new(bind_group),
+            bindings,
+            bind_group_dirty: false,
+            bind_group_layout,
+            shader,
+            device,
+            workgroup_size,
+            workgroups: self.workgroups,
+            dispatch: self.dispatch,
+            order: self.order,
+        })
+    }
+}
+
+/// dispatches every `ComputeMaterial`.
+/// it touches no slots, the `RenderPlugin` adds it first so it runs before the draws
+pub struct ComputePassNode {
+    /// the materials to dispatch this frame, in order
+    materials: Vec<Entity>,
+}
+
+impl ComputePassNode {
+    pub const NAME: &'static str = "compute";
+
+    pub fn new() -> Self {
+        Self { materials: vec![] }
+    }
+}
+
+impl Default for ComputePassNode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RenderNode for ComputePassNode {
+    fn desc(&self) -> NodeDesc {
+        NodeDesc::new(Self::NAME)
+    }
+
+    fn prepare(
+        &mut self,
+        world: &mut legion::World,
+        resources: &legion::Resources,
+        renderer: &mut Renderer,
+    ) {
+        let mut materials: Vec<(i32, Entity)> = vec![];
+
+        for (entity, material) in <(Entity, &mut ComputeMaterial)>::query().iter_mut(world) {
+            if material.dispatch == Dispatch::Paused || material.workgroups.contains(&0) {
+                continue;
+            }
+            materials.push((material.order, *entity));
+        }
+
+        // stable, so the query order stays for the same order
+        materials.sort_by_key(|x| x.0);
+        self.materials = materials.into_iter().map(|(_, entity)| entity).collect();
+    }
+
+    fn record(&self, encoder: &mut wgpu::CommandEncoder, ctx: &NodeContext) {
+        if self.materials.is_empty() {
+            return;
+        }
+
+        let materials: Vec<&ComputeMaterial> = self
+            .materials
+            .iter()
+            .filter_map(|entity| {
+                ctx.world
+                    .entry_ref(*entity)
+                    .ok()
+                    .and_then(|x| x.into_component::<ComputeMaterial>().ok())
+            })
+            .collect();
+
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some(Self::NAME),
+            timestamp_writes: None,
+        });
+
+        for material in materials {
+            let [x, y, z] = material.workgroups;
+            cpass.set_pipeline(&material.pipeline);
+            cpass.set_bind_group(COMPUTE_GROUP, &material.bind_group, &[]);
+            cpass.dispatch_workgroups(x, y, z);
+        }
+    }
+
+    fn finish(&mut self, world: &mut legion::World) {
+        for entity in self.materials.iter() {
+            let mut entry = match world.entry_mut(*entity) {
+                Ok(r) => r,
+                Err(_) => continue,
+            };
+            if let Ok(material) = entry.get_component_mut::<ComputeMaterial>() {
+                if material.dispatch == Dispatch::Once {
+                    material.dispatch = Dispatch::Paused;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use cgmath::prelude::*;
+use cgmath::{Matrix4, Vector3, Vector4};
+
+use crate::{InstanceRaw, ModelMatrix};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    /// the smallest box around all points, `None` if there are none
+    pub fn from_points(points: impl IntoIterator<Item = [f32; 3]>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first: Vector3<f32> = points.next()?.into();
+
+        Some(points.fold(Self::new(first, first), |aabb, p| {
+            aabb.union(&Self::new(p.into(), p.into()))
+        }))
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Vector3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: Vector3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    pub fn center(&self) -> Vector3<f32> {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn half_extents(&self) -> Vector3<f32> {
+        (self.max - self.min) / 2.0
+    }
+
+    /// the box around this box after transforming it, stays axis aligned
+    pub fn transformed(&self, matrix: &Matrix4<f32>) -> Aabb {
+        let center = *matrix * self.center().extend(1.0);
+        let extents = self.half_extents();
+
+        // the extents along each world axis are the absolute rotated and scaled extents
+        let abs_extents = Vector3::new(
+            matrix.x.x.abs() * extents.x + matrix.y.x.abs() * extents.y + matrix.z.x.abs() * extents.z,
+            matrix.x.y.abs() * extents.x + matrix.y.y.abs() * extents.y + matrix.z.y.abs() * extents.z,
+            matrix.x.z.abs() * extents.x + matrix.y.z.abs() * extents.y + matrix.z.z.abs() * extents.z,
+        );
+
+        Aabb {
+            min: center.truncate() - abs_extents,
+            max: center.truncate() + abs_extents,
+        }
+    }
+
+    pub fn bounding_sphere(&self) -> BoundingSphere {
+        BoundingSphere {
+            center: self.center(),
+            radius: self.half_extents().magnitude(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vector3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    pub fn transformed(&self, transform: &ModelMatrix) -> BoundingSphere {
+        let scale = transform
+            .scale
+            .x
+            .abs()
+            .max(transform.scale.y.abs())
+            .max(transform.scale.z.abs());
+
+        BoundingSphere {
+            center: (transform.to_matrix() * self.center.extend(1.0)).truncate(),
+            radius: self.radius * scale,
+        }
+    }
+}
+
+/// the six planes of a view volume, pointing inwards
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// extracts the planes from a wgpu clip space matrix (depth from 0 to 1),
+    /// like `Camera::build_view_projection_matrix`
+    pub fn from_view_projection(matrix: &Matrix4<f32>) -> Self {
+        let r0 = matrix.row(0);
+        let r1 = matrix.row(1);
+        let r2 = matrix.row(2);
+        let r3 = matrix.row(3);
+
+        let planes = [
+            r3 + r0, // left
+            r3 - r0, // right
+            r3 + r1, // bottom
+            r3 - r1, // top
+            r2,      // near, z >= 0
+            r3 - r2, // far, z <= w
+        ];
+
+        Self {
+            planes: planes.map(|plane| {
+                let length = plane.truncate().magnitude();
+                if length > 0.0 {
+                    plane / length
+                } else {
+                    plane
+                }
+            }),
+        }
+    }
+
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // the corner furthest along the plane normal
+            let corner = Vector3::new(
+                if plane.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if plane.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if plane.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            );
+            plane.truncate().dot(corner) + plane.w >= 0.0
+        })
+    }
+
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.truncate().dot(sphere.center) + plane.w >= -sphere.radius)
+    }
+}
+
+/// appends the instances whose `bounds` touch the frustum to `out`,
+/// every instance is kept when there are no bounds
+pub fn cull_instances(
+    frustum: &Frustum,
+    bounds: Option<&Aabb>,
+    instances: &[ModelMatrix],
+    out: &mut Vec<InstanceRaw>,
+) {
+    let bounds = match bounds {
+        Some(r) => r,
+        None => {
+            out.extend(instances.iter().map(|x| x.to_raw()));
+            return;
+        }
+    };
+
+    for instance in instances {
+        let matrix = instance.to_matrix();
+        if frustum.intersects_aabb(&bounds.transformed(&matrix)) {
+            out.push(InstanceRaw::from(matrix));
+        }
+    }
+}
+
+/// like `cull_instances`, but keeps the world space center of every visible instance for sorting.
+/// without bounds the position of the instance is used
+pub fn cull_instances_with_centers(
+    frustum: &Frustum,
+    bounds: Option<&Aabb>,
+    instances: &[ModelMatrix],
+    out: &mut Vec<(Vector3<f32>, InstanceRaw)>,
+) {
+    for instance in instances {
+        let matrix = instance.to_matrix();
+        let bounds = match bounds {
+            Some(r) => r.transformed(&matrix),
+            None => {
+                out.push((instance.position, InstanceRaw::from(matrix)));
+                continue;
+            }
+        };
+
+        if frustum.intersects_aabb(&bounds) {
+            out.push((bounds.center(), InstanceRaw::from(matrix)));
+        }
+    }
+}
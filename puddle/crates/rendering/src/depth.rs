@@ -0,0 +1,300 @@
+use std::sync::Arc;
+
+use crate::shader_preprocessor::ShaderPreprocessor;
+
+/// a depth buffer and a copy of it that materials can sample,
+/// the swapchain and every offscreen `CameraView` have their own.
+///
+/// materials read the copy of the camera they are drawn by through bind group 2:
+/// `@group(2) @binding(0) var depth_texture: texture_depth_2d;`
+/// `@group(2) @binding(1) var depth_sampler: sampler;`
+/// with the depth prepass enabled it holds the depth of the current frame, otherwise the last one
+pub(crate) struct DepthTargets {
+    /// has the sample count of the renderer and a stencil aspect
+    pub depth_texture: texture::Texture,
+    pub sampled_depth: texture::Texture,
+    pub bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    pub bind_group: wgpu::BindGroup,
+    resolve: DepthResolve,
+}
+
+/// the depth stencil buffer can't be copied into the sampled depth, this draws its depth into it instead.
+/// with msaa the first sample of every pixel is used
+struct DepthResolve {
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+}
+
+impl DepthResolve {
+    /// has to be recreated with the depth texture
+    fn new(device: &wgpu::Device, depth_texture: &texture::Texture, sample_count: u32) -> Self {
+        let multisampled = sample_count > 1;
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("depth_resolve_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Depth,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled,
+                },
+                count: None,
+            }],
+        });
+
+        let view = depth_texture.texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("depth_resolve_view"),
+            aspect: wgpu::TextureAspect::DepthOnly,
+            ..Default::default()
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("depth_resolve_bind_group"),
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            }],
+        });
+
+        let mut preprocessor = ShaderPreprocessor::empty();
+        if multisampled {
+            preprocessor.define("MULTISAMPLED", "");
+        }
+        let source = preprocessor
+            .process("depth_resolve.wgsl", include_str!("shaders/depth_resolve.wgsl"))
+            .expect("the depth resolve shader is broken");
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("depth_resolve.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(source.source.into()),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("depth_resolve_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Depth Resolve Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        Self {
+            pipeline,
+            bind_group,
+        }
+    }
+}
+
+/// clears the depth of one camera in the pass the cameras of the swapchain share,
+/// a load op would clear the cameras drawn before it too
+pub(crate) struct ViewportClear {
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl ViewportClear {
+    /// has to be recreated when the sample count changes
+    pub fn new(device: &wgpu::Device, sample_count: u32) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("depth_clear.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/depth_clear.wgsl").into()),
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("depth_clear_pipeline_layout"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+
+        // the stencil is replaced with the reference, which is set to 0 before the draw
+        let stencil = wgpu::StencilFaceState {
+            compare: wgpu::CompareFunction::Always,
+            fail_op: wgpu::StencilOperation::Replace,
+            depth_fail_op: wgpu::StencilOperation::Replace,
+            pass_op: wgpu::StencilOperation::Replace,
+        };
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Depth Clear Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: crate::Renderer::HDR_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::empty(),
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_STENCIL_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState {
+                    front: stencil,
+                    back: stencil,
+                    read_mask: 0xff,
+                    write_mask: 0xff,
+                },
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+        });
+
+        Self { pipeline }
+    }
+
+    /// sets the depth inside `viewport` (x, y, width, height in pixels) to `value` and the stencil to 0.
+    /// `rpass` has to draw into the hdr target with a depth buffer and the sample count of the renderer
+    pub fn clear<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>, viewport: [f32; 4], value: f32) {
+        let [x, y, width, height] = viewport;
+        rpass.set_viewport(x, y, width, height, value, value);
+        rpass.set_pipeline(&self.pipeline);
+        rpass.set_stencil_reference(0);
+        rpass.draw(0..3, 0..1);
+    }
+}
+
+impl DepthTargets {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, sample_count: u32) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("depth_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
+                    count: None,
+                },
+            ],
+        });
+
+        Self::with_layout(
+            device,
+            Arc::new(bind_group_layout),
+            config.width,
+            config.height,
+            sample_count,
+        )
+    }
+
+    /// the depth of an offscreen target, `layout` is the one of the swapchain targets that pipelines are built with
+    pub fn with_layout(
+        device: &wgpu::Device,
+        layout: Arc<wgpu::BindGroupLayout>,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+    ) -> Self {
+        let depth_texture = texture::Texture::create_depth_texture_multisampled(device, width, height, sample_count);
+        let sampled_depth =
+            texture::Texture::create_depth_texture_non_comparison_sampler_sized(device, width, height, "sampled depth");
+        let bind_group = Self::create_bind_group(device, &layout, &sampled_depth);
+
+        let resolve = DepthResolve::new(device, &depth_texture, sample_count);
+
+        Self {
+            depth_texture,
+            sampled_depth,
+            bind_group_layout: layout,
+            bind_group,
+            resolve,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        sampled_depth: &texture::Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("depth_bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&sampled_depth.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampled_depth.sampler),
+                },
+            ],
+        })
+    }
+
+    /// recreates the textures in the new surface size or sample count,
+    /// only call this on resize or when the sample count changed
+    pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, sample_count: u32) {
+        *self = Self::with_layout(
+            device,
+            self.bind_group_layout.clone(),
+            config.width,
+            config.height,
+            sample_count,
+        );
+    }
+
+    pub fn copy_to_sampled(&self, encoder: &mut wgpu::CommandEncoder) {
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("depth resolve"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.sampled_depth.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        rpass.set_pipeline(&self.resolve.pipeline);
+        rpass.set_bind_group(0, &self.resolve.bind_group, &[]);
+        rpass.draw(0..3, 0..1);
+    }
+}
@@ -1,44 +1,9 @@
-use std::time::Instant;
-use std::u16;
-
-use crate::{Camera, CameraBindGroup, Material, RenderCamera, Vertex};
-
-use super::{render_context::RenderContext, Mesh, Renderer};
-use application::log::{error, warn};
-use legion::{system, IntoQuery};
-use wgpu::util::RenderEncoder;
-
-pub struct CustomDepthBuffer(pub wgpu::Texture, pub wgpu::TextureView);
-
-
-fn clear_screen(context: &mut RenderContext) {
-    context
-        .command_encoder
-        .begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: None,
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &context.view.clone(),
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color {
-                        b: 0.01,
-                        ..wgpu::Color::BLACK
-                    }),
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
-            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: &context.depth_texture.view,
-                depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(1.0),
-                    store: wgpu::StoreOp::Store,
-                }),
-                stencil_ops: None,
-            }),
-            timestamp_writes: None,
-            occlusion_query_set: None,
-        });
-}
+use legion::IntoQuery;
+
+use crate::{Camera, RenderGraph};
+
+use super::{render_context::RenderContext, Renderer};
+use application::log::error;
 
 pub fn draw(world: &mut legion::World, resources: &mut legion::Resources) {
     let mut renderer = match resources.get_mut::<Renderer>() {
@@ -47,25 +12,15 @@ pub fn draw(world: &mut legion::World, resources: &mut legion::Resources) {
             return;
         }
     };
-    let mut camera_bind_group = match resources.get_mut::<CameraBindGroup>() {
-        Some(r) => r,
-        None => {
-            return;
-        }
-    };
-    let mut camera_buffer = match resources.get_mut::<RenderCamera>() {
-        Some(r) => r,
-        None => {
-            return;
-        }
-    };
-    let mut camera = match resources.get_mut::<Camera>() {
+    let mut render_graph = match resources.get_mut::<RenderGraph>() {
         Some(r) => r,
         None => {
             return;
         }
     };
 
+    // borrow fields of the renderer separately instead of the whole guard
+    let renderer = &mut *renderer;
 
     {
         use std::sync::{Arc, Mutex};
@@ -93,6 +48,21 @@ pub fn draw(world: &mut legion::World, resources: &mut legion::Resources) {
             renderer
                 .surface
                 .configure(&renderer.device, &renderer.surface_config);
+            renderer.resize_attachments();
+
+            let aspect =
+                renderer.surface_config.width as f32 / renderer.surface_config.height as f32;
+            if let Some(mut camera) = resources.get_mut::<Camera>() {
+                if camera.auto_aspect {
+                    camera.aspect = aspect;
+                }
+            }
+            // camera components, like the ones of a gltf scene. `CameraView`s follow their viewport instead
+            for camera in <&mut Camera>::query().iter_mut(world) {
+                if camera.auto_aspect {
+                    camera.aspect = aspect;
+                }
+            }
 
             locked.resized = None;
         }
@@ -109,68 +79,26 @@ pub fn draw(world: &mut legion::World, resources: &mut legion::Resources) {
         }
     };
 
-    let view = render_context.view.clone();
-
-    camera_buffer.uniform.update_view_proj(&camera);
-    renderer.queue.write_buffer(
-        &camera_buffer.buffer,
-        0,
-        bytemuck::cast_slice(&[camera_buffer.uniform]),
-    );
-
-    clear_screen(&mut render_context);
-
-    let time = Instant::now();
-
-    let mut num = 0;
-
-    for texture in <&CustomDepthBuffer>::query().iter(world) {
+    crate::shader_assets::reload_shaders(world, resources, renderer);
+    crate::material_params::update_material_params(world, renderer);
+    crate::lights::upload_lights(world, resources, renderer);
 
+    if let Err(e) = render_graph.prepare(world, resources, renderer) {
+        error!("{}", e);
+        return;
     }
 
-
-    for material in <&Material>::query().iter(world) {
-        num += 1;
-
-        let mut rpass =
-            render_context
-                .command_encoder
-                .begin_render_pass(&wgpu::RenderPassDescriptor {
-                    label: None,
-                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                        view: &view,
-                        resolve_target: None,
-                        ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Load,
-                            store: wgpu::StoreOp::Store,
-                        },
-                    })],
-                    depth_stencil_attachment: if material.uses_depth_buffer {
-                        Some(wgpu::RenderPassDepthStencilAttachment {
-                        view: &render_context.depth_texture.view,
-                        depth_ops: Some(wgpu::Operations {
-                            load: wgpu::LoadOp::Load,
-                            store: wgpu::StoreOp::Store,
-                        }),
-                        stencil_ops: None,
-                    }) } else {None},
-                    timestamp_writes: None,
-                    occlusion_query_set: None,
-                });
-
-        rpass.set_vertex_buffer(0, material.vertex_buffer.slice(..));
-        rpass.set_vertex_buffer(1, material.instance_buffer.slice(..));
-        rpass.set_index_buffer(material.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-        rpass.set_pipeline(&material.pipeline);
-        rpass.set_bind_group(0, &camera_bind_group.0, &[]);
-        rpass.set_bind_group(1, &material.bind_groups, &[]);
-
-        rpass.draw_indexed(
-            0..material.indecies.len() as u32,
-            0,
-            0..material.instances.len() as u32,
-        );
+    if let Err(e) = render_graph.execute(
+        world,
+        resources,
+        renderer,
+        (&render_context.frame.texture, &*render_context.view),
+        &mut render_context.command_encoder,
+    ) {
+        error!("{}", e);
+        return;
     }
 
     render_context.execute(&mut renderer.queue);
+    render_graph.finish(world);
 }
@@ -0,0 +1,7 @@
+use legion::Entity;
+
+pub struct Name(pub String);
+
+pub struct Parent(pub Entity);
+
+pub struct Children(pub Vec<Entity>);
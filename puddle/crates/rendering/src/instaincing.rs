@@ -4,24 +4,28 @@
 pub struct ModelMatrix {
     pub position: cgmath::Vector3<f32>,
     pub rotation: cgmath::Quaternion<f32>,
+    pub scale: cgmath::Vector3<f32>,
 }
 
 impl Default for ModelMatrix {
     fn default() -> Self {
         Self {
             position: [0.0, 0.0, 0.0].into(),
-            rotation: cgmath::Quaternion::new(0.0, 0.0, 0.0, 0.0),
+            rotation: cgmath::One::one(),
+            scale: [1.0, 1.0, 1.0].into(),
         }
     }
 }
 
 impl ModelMatrix {
+    pub fn to_matrix(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::from_translation(self.position)
+            * cgmath::Matrix4::from(self.rotation)
+            * cgmath::Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+
     pub fn to_raw(&self) -> InstanceRaw {
-        InstanceRaw {
-            model: (cgmath::Matrix4::from_translation(self.position)
-                * cgmath::Matrix4::from(self.rotation))
-            .into(),
-        }
+        InstanceRaw::from(self.to_matrix())
     }
 }
 
@@ -31,6 +35,14 @@ pub struct InstanceRaw {
     model: [[f32; 4]; 4],
 }
 
+impl From<cgmath::Matrix4<f32>> for InstanceRaw {
+    fn from(value: cgmath::Matrix4<f32>) -> Self {
+        Self {
+            model: value.into(),
+        }
+    }
+}
+
 impl InstanceRaw {
     pub fn desc() -> wgpu::VertexBufferLayout<'static> {
         use std::mem;
@@ -1,19 +1,65 @@
 #![allow(unused, dead_code)]
+mod buffers;
 mod draw;
 mod instaincing;
 mod event_listener;
+mod hierarchy;
+mod lights;
+mod lit_material;
+mod loaders;
+mod main_pass;
 mod camera;
+mod camera_view;
+mod compute;
+mod culling;
+mod depth;
+mod material_descriptor;
+mod material_params;
 mod materials;
 mod meshes;
+mod msaa;
+mod pipeline_cache;
+mod post_process;
+mod reflection;
 mod render_context;
+mod render_graph;
+mod render_queue;
+mod shader_assets;
+mod shader_preprocessor;
+mod shadows;
+mod skybox;
 
-pub use camera::{Camera, CameraUniform};
+pub use buffers::{StorageVec, Uniform};
+pub use camera::{Camera, CameraUniform, Projection, Ray};
+pub use camera_view::{CameraView, RenderLayers, RenderTarget, Viewport};
+pub use compute::{ComputeMaterial, ComputeMaterialBuilder, ComputePassNode, Dispatch, COMPUTE_GROUP};
+pub use culling::*;
+pub use hierarchy::*;
 pub use instaincing::*;
+pub use lights::{AmbientLight, DirectionalLight, PointLight, SpotLight};
+pub use lit_material::LitMaterial;
+pub use loaders::*;
+pub use main_pass::{view_clears, MainPassNode, ViewClear, ViewTarget};
+pub use material_descriptor::{BlendMode, MaterialBuilder, MaterialDescriptor};
+pub use material_params::{MaterialResource, ParamError, ParamValue};
 pub use materials::*;
 pub use meshes::*;
+pub use msaa::{check_sample_count, supported_sample_counts, SampleCountError, SAMPLE_COUNTS};
+pub use pipeline_cache::PipelineCacheStats;
+pub use post_process::{CustomEffect, PostEffect, PostProcessNode, PostProcessStack, Tonemapper};
+pub use render_graph::*;
+pub use reflection::{
+    reflect_bindings, resolve_bindings, GivenBinding, ResolvedBinding, ShaderBinding,
+};
+pub use render_queue::{queue_draws, DrawCall, QueueItem, RenderQueue, SortOrder, SortView};
+pub use shader_assets::{
+    create_shader_module, load_wgsl, preprocess_wgsl, validate_preprocessed, validate_wgsl, ShaderError, WgslFile,
+};
+pub use shader_preprocessor::{PreprocessedShader, ShaderPreprocessor, ENGINE_SHADER_MODULES};
+pub use shadows::{ShadowPassNode, ShadowSettings, MAX_CASCADES, MAX_SPOT_SHADOWS};
+pub use skybox::Skybox;
 pub use wgpu;
 pub struct RenderPlugin;
-pub use draw::CustomDepthBuffer;
 
 use application::Plugin;
 use std::sync::Arc;
@@ -24,9 +70,43 @@ pub struct Renderer {
     pub surface: wgpu::Surface<'static>,
     pub surface_config: wgpu::SurfaceConfiguration,
     pub adapter: wgpu::Adapter,
+    /// depth compare of new pipelines, follows the projection of the `Camera` resource.
+    /// materials get more pipelines for `CameraView`s with a projection that compares differently
+    pub(crate) depth_compare: wgpu::CompareFunction,
+    /// the visible instances of every material and camera, refilled each frame
+    pub(crate) instance_buffer: wgpu::Buffer,
+    pub(crate) instance_capacity: usize,
+    /// the swapchain depth buffer, recreated on resize
+    pub(crate) depth: depth::DepthTargets,
+    /// clears the depth of the swapchain cameras that share the hdr pass with cameras drawn before them
+    pub(crate) viewport_depth_clear: depth::ViewportClear,
+    /// samples per pixel of the camera attachments, change it with `set_sample_count`
+    pub(crate) sample_count: u32,
+    /// the multisampled color the main camera draws into, `None` without msaa
+    pub(crate) msaa_color: Option<texture::Texture>,
+    /// draws the depth of every material before the color passes,
+    /// materials then see the depth of the current frame in bind group 2.
+    /// with msaa that copy isn't a real resolve, it holds the first sample of every pixel
+    pub depth_prepass: bool,
+    /// draws the `Camera` resource full screen before the `CameraView`s of the swapchain,
+    /// turn it off when the views cover the screen themselves, like for split screen
+    pub main_camera: bool,
+    /// the layers of the materials the `Camera` resource draws
+    pub main_camera_layers: RenderLayers,
+    /// every light component, bound as group 3
+    pub(crate) lights: lights::LightBuffer,
+    /// the shadow maps of the lights, bound as group 3 next to them
+    pub(crate) shadows: shadows::ShadowMaps,
+    /// loads the files of materials built with `Material::from_file`, so they are reloaded when they change
+    pub(crate) assets: Option<assets::AssetServer>,
+    /// runs on every shader loaded with `Material::from_file` or `Material::from_wgsl`,
+    /// add your own modules and defines to it
+    pub shader_preprocessor: ShaderPreprocessor,
+    /// shader modules, bind group layouts and pipelines shared between materials
+    pub(crate) pipeline_cache: pipeline_cache::PipelineCache,
 }
 
-pub struct CameraBindGroup(pub wgpu::BindGroup);
+pub struct CameraBindGroup(pub Arc<wgpu::BindGroup>);
 pub struct CameraBindGroupLayout(pub Arc<wgpu::BindGroupLayout>);
 
 struct RenderCamera {
@@ -82,7 +162,9 @@ impl Plugin for RenderPlugin {
 
         let (device, queue) = application::async_std::task::block_on(
             adapter.request_device(&wgpu::DeviceDescriptor {
-                required_features : wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+                // wireframe and point materials only work where the adapter supports them
+                required_features : wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+                    | (adapter.features() & (wgpu::Features::POLYGON_MODE_LINE | wgpu::Features::POLYGON_MODE_POINT)),
                 ..Default::default()
             }, None),
         )
@@ -108,6 +190,8 @@ impl Plugin for RenderPlugin {
         use application::Scheddules;
         app.scheddules
             .add_non_parralel(Scheddules::Update, draw::draw);
+        app.scheddules
+            .add_non_parralel(Scheddules::Update, skybox::update_skyboxes);
 
         let render_events = Arc::new(std::sync::Mutex::new(RenderEvents { resized: None }));
 
@@ -160,20 +244,87 @@ impl Plugin for RenderPlugin {
             uniform: camera_uniform,
         };
 
-        app.resources.insert(CameraBindGroup(camera_bind_group));
+        let instance_capacity = 1024;
+        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("instance buffer"),
+            size: (instance_capacity * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let depth = depth::DepthTargets::new(&device, &surface_config, 1);
+        let viewport_depth_clear = depth::ViewportClear::new(&device, 1);
+        let shadow_settings = ShadowSettings::default();
+        let shadows = shadows::ShadowMaps::new(
+            &device,
+            &camera_bind_group_layout,
+            shadow_settings.resolution,
+        );
+        let lights = lights::LightBuffer::new(&device, &shadows);
+
+        // other plugins add their passes to this graph
+        let mut render_graph = RenderGraph::new();
+        // added first, nodes without dependencies run in the order they were added
+        render_graph
+            .add_node(ComputePassNode::new())
+            .expect("render graph is empty");
+        render_graph
+            .add_node(MainPassNode::new())
+            .expect("render graph has no main pass yet");
+        render_graph
+            .add_node(ShadowPassNode::new())
+            .expect("render graph has no shadow pass yet");
+        render_graph
+            .add_node(PostProcessNode::new(&device))
+            .expect("render graph has no post process pass yet");
+
+        app.resources.insert(render_graph);
+        app.resources.insert(CameraBindGroup(Arc::new(camera_bind_group)));
         app.resources
             .insert(CameraBindGroupLayout(Arc::new(camera_bind_group_layout)));
         app.resources.insert(cam_buffers);
         app.resources.insert(cam);
         app.resources.insert(render_events);
+        let asset_server = app.resources.get::<assets::AssetServer>().map(|x| x.clone());
         app.resources.insert(Renderer {
             surface,
             device : device.into(),
             queue,
             surface_config,
             adapter,
+            depth_compare: cam.projection.depth_compare(),
+            instance_buffer,
+            instance_capacity,
+            depth,
+            viewport_depth_clear,
+            sample_count: 1,
+            msaa_color: None,
+            depth_prepass: false,
+            main_camera: true,
+            main_camera_layers: RenderLayers::DEFAULT,
+            lights,
+            shadows,
+            assets: asset_server,
+            shader_preprocessor: ShaderPreprocessor::new(),
+            pipeline_cache: pipeline_cache::PipelineCache::new(),
         });
+        // textures need the renderer, so they finish on the main thread.
+        // materials bound with `MaterialResource::TextureAsset` follow reloads of the file,
+        // materials made with `Material::from_file` follow reloads of their `WgslFile`s
+        if let Some(server) = app.resources.get::<assets::AssetServer>() {
+            server.register_loader(TextureLoader::default());
+            server.register_loader(ImageLoader);
+            server.register_loader(ObjLoader);
+            server.register_loader(shader_assets::WgslLoader);
+        }
+
+        app.resources.insert(AmbientLight::default());
+        app.resources.insert(shadow_settings);
+        app.resources.insert(PostProcessStack::default());
     }
 }
 
-impl Renderer {}
+impl Renderer {
+    /// the format every material renders into, the post process stack maps it to the swapchain
+    pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+}
@@ -0,0 +1,528 @@
+use std::sync::Arc;
+
+use cgmath::{Deg, InnerSpace, Matrix4, Vector3};
+use legion::IntoQuery;
+
+use crate::shadows::{self, ShadowMaps, ShadowSettings, MAX_CASCADES, MAX_SPOT_SHADOWS};
+use crate::{Camera, ModelMatrix, Renderer};
+
+/// light coming from one direction everywhere, like the sun
+#[derive(Debug, Clone, Copy)]
+pub struct DirectionalLight {
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// the direction the light travels in
+    pub direction: Vector3<f32>,
+    /// only the first directional light casting shadows gets them
+    pub cast_shadows: bool,
+}
+
+/// light from the position of the entity's `ModelMatrix` in every direction
+#[derive(Debug, Clone, Copy)]
+pub struct PointLight {
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// the distance at which the light fades out
+    pub range: f32,
+}
+
+/// light from the position of the entity's `ModelMatrix` inside a cone
+#[derive(Debug, Clone, Copy)]
+pub struct SpotLight {
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub range: f32,
+    /// the direction the cone points at, in world space
+    pub direction: Vector3<f32>,
+    /// full brightness inside this angle from the direction
+    pub inner_angle: Deg<f32>,
+    /// no light outside this angle
+    pub outer_angle: Deg<f32>,
+    /// the first `MAX_SPOT_SHADOWS` spot lights casting shadows get them
+    pub cast_shadows: bool,
+}
+
+/// light added to every lit surface, a resource
+#[derive(Debug, Clone, Copy)]
+pub struct AmbientLight {
+    pub color: [f32; 3],
+    pub intensity: f32,
+}
+
+impl Default for AmbientLight {
+    fn default() -> Self {
+        Self {
+            color: [1.0, 1.0, 1.0],
+            intensity: 0.05,
+        }
+    }
+}
+
+pub(crate) const LIGHT_DIRECTIONAL: f32 = 0.0;
+pub(crate) const LIGHT_POINT: f32 = 1.0;
+pub(crate) const LIGHT_SPOT: f32 = 2.0;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct LightRaw {
+    /// xyz position, w is the kind of light
+    position: [f32; 4],
+    /// xyz direction, w is the range
+    direction: [f32; 4],
+    /// rgb color, w is the intensity
+    color: [f32; 4],
+    /// cos of the inner and outer spot angle, the first shadow map layer or -1
+    cone: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightsHeader {
+    ambient: [f32; 4],
+    count: u32,
+    _padding: [u32; 3],
+}
+
+/// the lights of the world in a storage buffer, bound as group 3 of every material:
+/// ```wgsl
+/// struct Light {
+///     position: vec4<f32>,  // xyz, w = 0 directional, 1 point, 2 spot
+///     direction: vec4<f32>, // xyz, w = range
+///     color: vec4<f32>,     // rgb, w = intensity
+///     cone: vec4<f32>,      // cos inner angle, cos outer angle, shadow layer or -1
+/// };
+/// struct Lights {
+///     ambient: vec4<f32>,   // rgb * intensity
+///     count: u32,
+///     lights: array<Light>,
+/// };
+/// @group(3) @binding(0) var<storage, read> lights: Lights;
+/// ```
+/// the shadow maps are bound next to it, see `ShadowMaps`
+pub(crate) struct LightBuffer {
+    pub buffer: wgpu::Buffer,
+    /// how many lights fit into `buffer`
+    pub capacity: usize,
+    pub bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl LightBuffer {
+    pub fn new(device: &wgpu::Device, shadows: &ShadowMaps) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("lights_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+            ],
+        });
+
+        let capacity = 16;
+        let buffer = Self::create_buffer(device, capacity);
+        let bind_group = Self::create_bind_group(device, &bind_group_layout, &buffer, shadows);
+
+        Self {
+            buffer,
+            capacity,
+            bind_group_layout: Arc::new(bind_group_layout),
+            bind_group,
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("light buffer"),
+            size: (std::mem::size_of::<LightsHeader>() + capacity * std::mem::size_of::<LightRaw>())
+                as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        buffer: &wgpu::Buffer,
+        shadows: &ShadowMaps,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("lights_bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: shadows.uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&shadows.texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&shadows.texture.sampler),
+                },
+            ],
+        })
+    }
+}
+
+/// collects every light component and writes them into the light buffer of the renderer,
+/// also picks the lights that get shadow maps and computes their matrices
+/// the lights of the world as they are written to the `LightBuffer` and the `ShadowMaps`
+struct PackedLights {
+    header: LightsHeader,
+    lights: Vec<LightRaw>,
+    shadow_layers: Vec<(usize, Matrix4<f32>)>,
+    cascade_splits: [f32; 4],
+    cascade_count: usize,
+}
+
+fn pack_lights(
+    world: &legion::World,
+    ambient: AmbientLight,
+    camera: Option<Camera>,
+    settings: &ShadowSettings,
+) -> PackedLights {
+    let mut lights: Vec<LightRaw> = vec![];
+    let mut shadow_layers = vec![];
+    let mut cascade_splits = [0.0; 4];
+    let mut cascade_count = 0;
+
+    for light in <&DirectionalLight>::query().iter(world) {
+        let direction = light.direction.normalize();
+        let mut shadow_layer = -1.0;
+
+        let camera = match &camera {
+            Some(camera) if light.cast_shadows && cascade_count == 0 => Some(camera),
+            _ => None,
+        };
+
+        if let Some(camera) = camera {
+            let splits = shadows::cascade_splits(camera, settings);
+            let mut near = camera.znear;
+            for (i, far) in splits.iter().enumerate() {
+                shadow_layers.push((i, shadows::cascade_matrix(camera, direction, near, *far, settings)));
+                cascade_splits[i] = *far;
+                near = *far;
+            }
+            cascade_count = splits.len();
+            shadow_layer = 0.0;
+        }
+
+        lights.push(LightRaw {
+            position: [0.0, 0.0, 0.0, LIGHT_DIRECTIONAL],
+            direction: [direction.x, direction.y, direction.z, 0.0],
+            color: [light.color[0], light.color[1], light.color[2], light.intensity],
+            cone: [0.0, 0.0, shadow_layer, 0.0],
+        });
+    }
+
+    for (light, transform) in <(&PointLight, &ModelMatrix)>::query().iter(world) {
+        let position = transform.position;
+        lights.push(LightRaw {
+            position: [position.x, position.y, position.z, LIGHT_POINT],
+            direction: [0.0, 0.0, 0.0, light.range],
+            color: [light.color[0], light.color[1], light.color[2], light.intensity],
+            cone: [0.0, 0.0, -1.0, 0.0],
+        });
+    }
+
+    let mut spot_shadows = 0;
+    for (light, transform) in <(&SpotLight, &ModelMatrix)>::query().iter(world) {
+        let position = transform.position;
+        let direction = light.direction.normalize();
+        let mut shadow_layer = -1.0;
+
+        if light.cast_shadows && spot_shadows < MAX_SPOT_SHADOWS {
+            let layer = MAX_CASCADES + spot_shadows;
+            shadow_layers.push((
+                layer,
+                shadows::spot_matrix(position, direction, light.outer_angle, light.range),
+            ));
+            shadow_layer = layer as f32;
+            spot_shadows += 1;
+        }
+        lights.push(LightRaw {
+            position: [position.x, position.y, position.z, LIGHT_SPOT],
+            direction: [direction.x, direction.y, direction.z, light.range],
+            color: [light.color[0], light.color[1], light.color[2], light.intensity],
+            cone: [
+                cgmath::Rad::from(light.inner_angle).0.cos(),
+                cgmath::Rad::from(light.outer_angle).0.cos(),
+                shadow_layer,
+                0.0,
+            ],
+        });
+    }
+
+    let header = LightsHeader {
+        ambient: [
+            ambient.color[0] * ambient.intensity,
+            ambient.color[1] * ambient.intensity,
+            ambient.color[2] * ambient.intensity,
+            1.0,
+        ],
+        count: lights.len() as u32,
+        _padding: [0; 3],
+    };
+
+    PackedLights {
+        header,
+        lights,
+        shadow_layers,
+        cascade_splits,
+        cascade_count,
+    }
+}
+
+pub(crate) fn upload_lights(
+    world: &legion::World,
+    resources: &legion::Resources,
+    renderer: &mut Renderer,
+) {
+    let settings = resources
+        .get::<ShadowSettings>()
+        .map(|x| *x)
+        .unwrap_or_default();
+    let camera = resources.get::<Camera>().map(|x| *x);
+    let ambient = resources
+        .get::<AmbientLight>()
+        .map(|x| *x)
+        .unwrap_or_default();
+
+    let PackedLights {
+        header,
+        lights,
+        shadow_layers,
+        cascade_splits,
+        cascade_count,
+    } = pack_lights(world, ambient, camera, &settings);
+
+    let resized_shadows = renderer
+        .shadows
+        .set_resolution(&renderer.device, settings.resolution);
+    renderer.shadows.update(
+        &renderer.queue,
+        shadow_layers,
+        cascade_splits,
+        cascade_count,
+        &settings,
+    );
+
+    let light_buffer = &mut renderer.lights;
+    let grow = lights.len() > light_buffer.capacity;
+    if grow {
+        light_buffer.capacity = lights.len().next_power_of_two();
+        light_buffer.buffer = LightBuffer::create_buffer(&renderer.device, light_buffer.capacity);
+    }
+    if grow || resized_shadows {
+        light_buffer.bind_group = LightBuffer::create_bind_group(
+            &renderer.device,
+            &light_buffer.bind_group_layout,
+            &light_buffer.buffer,
+            &renderer.shadows,
+        );
+    }
+
+    renderer
+        .queue
+        .write_buffer(&light_buffer.buffer, 0, bytemuck::cast_slice(&[header]));
+    if !lights.is_empty() {
+        renderer.queue.write_buffer(
+            &light_buffer.buffer,
+            std::mem::size_of::<LightsHeader>() as wgpu::BufferAddress,
+            bytemuck::cast_slice(&lights),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Projection;
+
+    fn camera() -> Camera {
+        Camera {
+            eye: (0.0, 2.0, 5.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: Vector3::unit_y(),
+            projection: Projection::Perspective,
+            aspect: 1.0,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 50.0,
+            auto_aspect: false,
+        }
+    }
+
+    fn sun(cast_shadows: bool) -> DirectionalLight {
+        DirectionalLight {
+            color: [1.0, 0.5, 0.25],
+            intensity: 3.0,
+            direction: Vector3::new(0.0, -2.0, 0.0),
+            cast_shadows,
+        }
+    }
+
+    fn spot(cast_shadows: bool) -> (SpotLight, ModelMatrix) {
+        let light = SpotLight {
+            color: [1.0, 1.0, 1.0],
+            intensity: 1.0,
+            range: 10.0,
+            direction: Vector3::new(0.0, 0.0, -4.0),
+            inner_angle: Deg(0.0),
+            outer_angle: Deg(60.0),
+            cast_shadows,
+        };
+        let transform = ModelMatrix {
+            position: (1.0, 2.0, 3.0).into(),
+            ..Default::default()
+        };
+        (light, transform)
+    }
+
+    #[test]
+    fn matches_the_wgsl_layout() {
+        // `lights: array<Light>` starts at the 16 byte aligned offset after `count`
+        assert_eq!(std::mem::size_of::<LightsHeader>(), 32);
+        assert_eq!(std::mem::size_of::<LightRaw>(), 64);
+    }
+
+    #[test]
+    fn packs_the_kind_and_parameters_of_every_light() {
+        let mut world = legion::World::default();
+        world.push((sun(false),));
+        world.push((
+            PointLight {
+                color: [0.0, 1.0, 0.0],
+                intensity: 2.0,
+                range: 7.0,
+            },
+            ModelMatrix {
+                position: (4.0, 5.0, 6.0).into(),
+                ..Default::default()
+            },
+        ));
+        world.push(spot(false));
+        let ambient = AmbientLight {
+            color: [1.0, 0.5, 0.0],
+            intensity: 0.5,
+        };
+
+        let packed = pack_lights(&world, ambient, None, &ShadowSettings::default());
+
+        assert_eq!(packed.header.count, 3);
+        assert_eq!(packed.header.ambient, [0.5, 0.25, 0.0, 1.0]);
+
+        let [directional, point, spot] = packed.lights[..] else {
+            panic!("expected three lights, got {}", packed.lights.len());
+        };
+        assert_eq!(directional.position[3], LIGHT_DIRECTIONAL);
+        assert_eq!(directional.direction, [0.0, -1.0, 0.0, 0.0]);
+        assert_eq!(directional.color, [1.0, 0.5, 0.25, 3.0]);
+
+        assert_eq!(point.position, [4.0, 5.0, 6.0, LIGHT_POINT]);
+        assert_eq!(point.direction[3], 7.0);
+        assert_eq!(point.color, [0.0, 1.0, 0.0, 2.0]);
+
+        assert_eq!(spot.position, [1.0, 2.0, 3.0, LIGHT_SPOT]);
+        assert_eq!(spot.direction, [0.0, 0.0, -1.0, 10.0]);
+        assert_eq!(spot.cone[0], 1.0);
+        assert!((spot.cone[1] - 0.5).abs() < 1e-6);
+
+        assert!(packed.lights.iter().all(|x| x.cone[2] == -1.0));
+        assert!(packed.shadow_layers.is_empty());
+        assert_eq!(packed.cascade_count, 0);
+    }
+
+    #[test]
+    fn only_the_first_sun_casting_shadows_gets_the_cascades() {
+        let mut world = legion::World::default();
+        world.push((sun(false),));
+        world.push((sun(true),));
+        world.push((sun(true),));
+        let settings = ShadowSettings {
+            cascades: 3,
+            ..Default::default()
+        };
+
+        let packed = pack_lights(&world, AmbientLight::default(), Some(camera()), &settings);
+
+        let layers: Vec<_> = packed.lights.iter().map(|x| x.cone[2]).collect();
+        assert_eq!(layers, vec![-1.0, 0.0, -1.0]);
+        assert_eq!(packed.cascade_count, 3);
+        let used: Vec<_> = packed.shadow_layers.iter().map(|(layer, _)| *layer).collect();
+        assert_eq!(used, vec![0, 1, 2]);
+        assert_eq!(packed.cascade_splits[..3], shadows::cascade_splits(&camera(), &settings)[..]);
+        assert_eq!(packed.cascade_splits[3], 0.0);
+    }
+
+    #[test]
+    fn suns_need_a_camera_for_their_cascades() {
+        let mut world = legion::World::default();
+        world.push((sun(true),));
+
+        let packed = pack_lights(&world, AmbientLight::default(), None, &ShadowSettings::default());
+
+        assert_eq!(packed.lights[0].cone[2], -1.0);
+        assert!(packed.shadow_layers.is_empty());
+        assert_eq!(packed.cascade_count, 0);
+    }
+
+    #[test]
+    fn spot_shadows_follow_the_cascades_until_they_run_out() {
+        let mut world = legion::World::default();
+        world.push(spot(false));
+        for _ in 0..MAX_SPOT_SHADOWS + 1 {
+            world.push(spot(true));
+        }
+
+        let packed = pack_lights(&world, AmbientLight::default(), None, &ShadowSettings::default());
+
+        let layers: Vec<_> = packed.lights.iter().map(|x| x.cone[2]).collect();
+        let mut expected = vec![-1.0];
+        expected.extend((0..MAX_SPOT_SHADOWS).map(|i| (MAX_CASCADES + i) as f32));
+        expected.push(-1.0);
+        assert_eq!(layers, expected);
+        let used: Vec<_> = packed.shadow_layers.iter().map(|(layer, _)| *layer).collect();
+        assert_eq!(used, (MAX_CASCADES..MAX_CASCADES + MAX_SPOT_SHADOWS).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,82 @@
+use std::sync::Arc;
+
+use crate::{
+    CameraBindGroupLayout, Material, MaterialResource, ParamValue, PuddleBindGroupEntry, Renderer,
+    ShaderError,
+};
+
+/// the built in blinn-phong material, lit by every light component.
+/// meshes need normals, the instances come from `Material::add_mesh` like any other material
+#[derive(Debug, Clone, Copy)]
+pub struct LitMaterial {
+    /// multiplied with the texture
+    pub base_color: [f32; 4],
+    pub specular: [f32; 3],
+    pub shininess: f32,
+}
+
+impl Default for LitMaterial {
+    fn default() -> Self {
+        Self {
+            base_color: [1.0, 1.0, 1.0, 1.0],
+            specular: [0.5, 0.5, 0.5],
+            shininess: 32.0,
+        }
+    }
+}
+
+impl LitMaterial {
+    /// creates the `Material`, `texture` is the base color.
+    /// change the values later with `set_param("base_color", ..)` and `set_param("specular", ..)`,
+    /// the w of `specular` is the shininess. the texture is called `base_color_texture`
+    pub fn build(
+        &self,
+        renderer: &mut Renderer,
+        camera_bind_group: &CameraBindGroupLayout,
+        texture: Arc<texture::Texture>,
+    ) -> Result<Material, ShaderError> {
+        let entries = vec![
+            PuddleBindGroupEntry {
+                name: "base_color_texture".to_string(),
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                resource: MaterialResource::Texture(texture.clone()),
+            },
+            PuddleBindGroupEntry {
+                name: "base_color_sampler".to_string(),
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                resource: MaterialResource::TextureSampler(texture),
+            },
+            PuddleBindGroupEntry {
+                name: "material".to_string(),
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                resource: MaterialResource::uniforms([
+                    ("base_color", ParamValue::Vec4(self.base_color)),
+                    (
+                        "specular",
+                        ParamValue::Vec4([
+                            self.specular[0],
+                            self.specular[1],
+                            self.specular[2],
+                            self.shininess,
+                        ]),
+                    ),
+                ]),
+            },
+        ];
+
+        Material::from_wgsl("lit.wgsl", include_str!("shaders/lit.wgsl"))
+            .entries(entries)
+            .build(renderer, camera_bind_group)
+    }
+}
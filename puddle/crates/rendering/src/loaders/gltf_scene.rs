@@ -0,0 +1,402 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use application::log::warn;
+use cgmath::{ElementWise, Quaternion};
+use legion::{systems::CommandBuffer, Entity};
+
+use crate::{
+    Camera, CameraBindGroupLayout, Children, LitMaterial, ModelMatrix, Name, Parent, Projection,
+    Renderer, ShaderError, Vertex,
+};
+
+#[derive(Debug)]
+pub enum GltfError {
+    Import(gltf::Error),
+    UnsupportedImage {
+        index: usize,
+        format: gltf::image::Format,
+    },
+    TooManyVertices {
+        mesh: String,
+    },
+    Texture(String),
+    Material(ShaderError),
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GltfError::Import(e) => write!(f, "failed to import gltf : {}", e),
+            GltfError::UnsupportedImage { index, format } => {
+                write!(f, "image {} has unsupported format {:?}", index, format)
+            }
+            GltfError::TooManyVertices { mesh } => write!(
+                f,
+                "mesh `{}` has more than {} vertices",
+                mesh,
+                u16::MAX as usize + 1
+            ),
+            GltfError::Texture(e) => write!(f, "failed to create texture : {}", e),
+            GltfError::Material(e) => write!(f, "failed to build material : {}", e),
+        }
+    }
+}
+
+impl std::error::Error for GltfError {}
+
+impl From<gltf::Error> for GltfError {
+    fn from(value: gltf::Error) -> Self {
+        GltfError::Import(value)
+    }
+}
+
+/// the entities created by `spawn_gltf`
+pub struct GltfScene {
+    /// nodes without a parent
+    pub roots: Vec<Entity>,
+    pub nodes: Vec<Entity>,
+    /// nodes that got a `Camera` component
+    pub cameras: Vec<Entity>,
+}
+
+/// a gltf file read by `load_gltf`, nothing is on the gpu yet
+pub struct GltfModel {
+    /// parents come before their children
+    pub nodes: Vec<GltfNode>,
+    pub images: Vec<gltf::image::Data>,
+}
+
+pub struct GltfNode {
+    pub name: String,
+    /// index into `GltfModel::nodes`
+    pub parent: Option<usize>,
+    /// world space
+    pub transform: ModelMatrix,
+    pub primitives: Vec<GltfPrimitive>,
+    /// the aspect of cameras with `auto_aspect` is set by `spawn_gltf` and follows the window when it is resized
+    pub camera: Option<Camera>,
+}
+
+/// a triangle list with the lit material settings of its gltf material
+pub struct GltfPrimitive {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u16>,
+    pub base_color: [f32; 4],
+    pub roughness: f32,
+    /// index into `GltfModel::images`
+    pub base_color_image: Option<usize>,
+}
+
+/// reads the default scene of a `.gltf` or `.glb` file
+pub fn load_gltf(path: impl AsRef<Path>) -> Result<GltfModel, GltfError> {
+    let (document, buffers, images) = gltf::import(path.as_ref())?;
+
+    let mut model = GltfModel {
+        nodes: vec![],
+        images,
+    };
+
+    let scene = match document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        Some(r) => r,
+        None => {
+            warn!("{} contains no scene", path.as_ref().display());
+            return Ok(model);
+        }
+    };
+
+    for node in scene.nodes() {
+        read_node(&node, None, &buffers, &mut model.nodes)?;
+    }
+
+    Ok(model)
+}
+
+fn read_node(
+    node: &gltf::Node,
+    parent: Option<usize>,
+    buffers: &[gltf::buffer::Data],
+    nodes: &mut Vec<GltfNode>,
+) -> Result<(), GltfError> {
+    let (translation, rotation, scale) = node.transform().decomposed();
+    let local = ModelMatrix {
+        position: translation.into(),
+        rotation: Quaternion::new(rotation[3], rotation[0], rotation[1], rotation[2]),
+        scale: scale.into(),
+    };
+
+    let transform = match parent {
+        Some(parent) => combine(&nodes[parent].transform, &local),
+        None => local,
+    };
+
+    let name = node
+        .name()
+        .map(|x| x.to_string())
+        .unwrap_or_else(|| format!("node {}", node.index()));
+
+    let mut primitives = vec![];
+    if let Some(mesh) = node.mesh() {
+        for primitive in mesh.primitives() {
+            if let Some(primitive) = read_primitive(&name, &primitive, buffers)? {
+                primitives.push(primitive);
+            }
+        }
+    }
+
+    let camera = node.camera().map(|camera| {
+        let mut cam = Camera::from_transform(&transform, 1.0);
+
+        match camera.projection() {
+            gltf::camera::Projection::Perspective(p) => {
+                cam.fovy = p.yfov().to_degrees();
+                cam.znear = p.znear();
+                match p.zfar() {
+                    Some(zfar) => cam.zfar = zfar,
+                    None => cam.projection = Projection::InfiniteReverseZ,
+                }
+                if let Some(aspect) = p.aspect_ratio() {
+                    cam.aspect = aspect;
+                    cam.auto_aspect = false;
+                }
+            }
+            gltf::camera::Projection::Orthographic(o) => {
+                cam.projection = Projection::Orthographic {
+                    height: o.ymag() * 2.0,
+                };
+                cam.znear = o.znear();
+                cam.zfar = o.zfar();
+                cam.aspect = o.xmag() / o.ymag();
+                cam.auto_aspect = false;
+            }
+        }
+
+        cam
+    });
+
+    let index = nodes.len();
+    nodes.push(GltfNode {
+        name,
+        parent,
+        transform,
+        primitives,
+        camera,
+    });
+
+    for child in node.children() {
+        read_node(&child, Some(index), buffers, nodes)?;
+    }
+
+    Ok(())
+}
+
+fn read_primitive(
+    mesh_name: &str,
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+) -> Result<Option<GltfPrimitive>, GltfError> {
+    if primitive.mode() != gltf::mesh::Mode::Triangles {
+        warn!(
+            "skipping primitive of `{}`, only triangle lists are supported",
+            mesh_name
+        );
+        return Ok(None);
+    }
+
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+    let positions: Vec<[f32; 3]> = match reader.read_positions() {
+        Some(r) => r.collect(),
+        None => {
+            warn!("skipping primitive of `{}` without positions", mesh_name);
+            return Ok(None);
+        }
+    };
+
+    if positions.len() > u16::MAX as usize + 1 {
+        return Err(GltfError::TooManyVertices {
+            mesh: mesh_name.to_string(),
+        });
+    }
+
+    let normals: Vec<[f32; 3]> = match reader.read_normals() {
+        Some(r) => r.collect(),
+        None => vec![[0.0; 3]; positions.len()],
+    };
+    let uvs: Vec<[f32; 2]> = match reader.read_tex_coords(0) {
+        Some(r) => r.into_f32().collect(),
+        None => vec![[0.0; 2]; positions.len()],
+    };
+    let indices: Vec<u16> = match reader.read_indices() {
+        Some(r) => r.into_u32().map(|x| x as u16).collect(),
+        None => (0..positions.len() as u16).collect(),
+    };
+
+    let vertices: Vec<Vertex> = positions
+        .iter()
+        .zip(normals.iter())
+        .zip(uvs.iter())
+        .map(|((position, normal), uv)| Vertex {
+            position: *position,
+            uv: *uv,
+            normal: *normal,
+        })
+        .collect();
+
+    let pbr = primitive.material().pbr_metallic_roughness();
+
+    Ok(Some(GltfPrimitive {
+        vertices,
+        indices,
+        base_color: pbr.base_color_factor(),
+        roughness: pbr.roughness_factor(),
+        base_color_image: pbr
+            .base_color_texture()
+            .map(|x| x.texture().source().index()),
+    }))
+}
+
+/// loads a `.gltf` or `.glb` file and spawns its default scene.
+///
+/// every node becomes an entity with a `Name`, a world space `ModelMatrix` and
+/// `Parent`/`Children` links. each mesh primitive becomes a child entity with its own lit `Material`
+pub fn spawn_gltf(
+    path: impl AsRef<Path>,
+    commands: &mut CommandBuffer,
+    renderer: &mut Renderer,
+    camera_bind_group: &CameraBindGroupLayout,
+) -> Result<GltfScene, GltfError> {
+    let model = load_gltf(path)?;
+
+    // shared by every material that uses them
+    let mut textures = HashMap::new();
+    for (index, image) in model.images.iter().enumerate() {
+        textures.insert(index, Arc::new(load_image(renderer, index, image)?));
+    }
+    // used by materials without a base color texture
+    let mut white: Option<Arc<texture::Texture>> = None;
+
+    let mut scene = GltfScene {
+        roots: vec![],
+        nodes: vec![],
+        cameras: vec![],
+    };
+    let mut children: Vec<Vec<Entity>> = vec![];
+
+    for node in model.nodes {
+        let entity = commands.push(());
+        commands.add_component(entity, Name(node.name));
+        commands.add_component(entity, node.transform);
+        match node.parent {
+            Some(parent) => {
+                commands.add_component(entity, Parent(scene.nodes[parent]));
+                children[parent].push(entity);
+            }
+            None => scene.roots.push(entity),
+        }
+        scene.nodes.push(entity);
+        children.push(vec![]);
+
+        for primitive in node.primitives {
+            let texture = match primitive.base_color_image {
+                Some(index) => textures[&index].clone(),
+                None => match &white {
+                    Some(r) => r.clone(),
+                    None => {
+                        let image = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+                            1,
+                            1,
+                            image::Rgba([255, 255, 255, 255]),
+                        ));
+                        let texture = Arc::new(
+                            texture::Texture::from_image(&renderer.device, &renderer.queue, &image)
+                                .map_err(GltfError::Texture)?,
+                        );
+                        white = Some(texture.clone());
+                        texture
+                    }
+                },
+            };
+
+            let mut material = LitMaterial {
+                base_color: primitive.base_color,
+                // rough surfaces get a small and dull highlight
+                specular: [1.0 - primitive.roughness; 3],
+                shininess: 2.0 + (1.0 - primitive.roughness) * 126.0,
+            }
+            .build(renderer, camera_bind_group, texture)
+            .map_err(GltfError::Material)?;
+            material.add_mesh(primitive.vertices, primitive.indices, node.transform);
+
+            let child = commands.push(());
+            commands.add_component(child, material);
+            commands.add_component(child, Parent(entity));
+            children[scene.nodes.len() - 1].push(child);
+        }
+
+        if let Some(mut camera) = node.camera {
+            if camera.auto_aspect {
+                let config = &renderer.surface_config;
+                camera.aspect = config.width as f32 / config.height as f32;
+            }
+            commands.add_component(entity, camera);
+            scene.cameras.push(entity);
+        }
+    }
+
+    for (entity, children) in scene.nodes.iter().zip(children) {
+        if !children.is_empty() {
+            commands.add_component(*entity, Children(children));
+        }
+    }
+
+    Ok(scene)
+}
+
+fn load_image(
+    renderer: &Renderer,
+    index: usize,
+    data: &gltf::image::Data,
+) -> Result<texture::Texture, GltfError> {
+    use gltf::image::Format;
+
+    let pixels = data.pixels.clone();
+    let image = match data.format {
+        Format::R8G8B8A8 => image::RgbaImage::from_raw(data.width, data.height, pixels)
+            .map(image::DynamicImage::ImageRgba8),
+        Format::R8G8B8 => image::RgbImage::from_raw(data.width, data.height, pixels)
+            .map(image::DynamicImage::ImageRgb8),
+        Format::R8G8 => image::GrayAlphaImage::from_raw(data.width, data.height, pixels)
+            .map(image::DynamicImage::ImageLumaA8),
+        Format::R8 => image::GrayImage::from_raw(data.width, data.height, pixels)
+            .map(image::DynamicImage::ImageLuma8),
+        format => return Err(GltfError::UnsupportedImage { index, format }),
+    };
+
+    let image = match image {
+        Some(r) => r,
+        None => {
+            return Err(GltfError::Texture(format!(
+                "image {} has less pixels than its size",
+                index
+            )))
+        }
+    };
+
+    texture::Texture::from_image(&renderer.device, &renderer.queue, &image)
+        .map_err(GltfError::Texture)
+}
+
+/// applies `local` on top of `parent`, shear from non uniform parent scales is lost
+fn combine(parent: &ModelMatrix, local: &ModelMatrix) -> ModelMatrix {
+    ModelMatrix {
+        position: parent.position + parent.rotation * local.position.mul_element_wise(parent.scale),
+        rotation: parent.rotation * local.rotation,
+        scale: parent.scale.mul_element_wise(local.scale),
+    }
+}
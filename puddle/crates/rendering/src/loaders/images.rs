@@ -0,0 +1,98 @@
+use std::path::Path;
+
+use assets::{AssetLoader, DecodeLoader};
+
+use crate::Renderer;
+
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "bmp", "tga", "gif", "hdr"];
+
+/// loads `Handle<texture::Texture>`s, the texture is freed with the last handle.
+/// register another one to change the settings, the last registered loader wins
+#[derive(Default)]
+pub struct TextureLoader {
+    pub settings: texture::TextureSettings,
+}
+
+impl AssetLoader for TextureLoader {
+    type Asset = texture::Texture;
+    type Decoded = image::DynamicImage;
+
+    fn extensions(&self) -> &[&str] {
+        IMAGE_EXTENSIONS
+    }
+
+    fn decode(&self, path: &Path, bytes: Vec<u8>) -> Result<Self::Decoded, String> {
+        image::load_from_memory(&bytes).map_err(|e| e.to_string())
+    }
+
+    fn finish(
+        &self,
+        image: Self::Decoded,
+        resources: &legion::Resources,
+    ) -> Result<Self::Asset, String> {
+        let renderer = match resources.get::<Renderer>() {
+            Some(r) => r,
+            None => {
+                return Err("the renderer is not set up".to_string());
+            }
+        };
+
+        texture::Texture::from_image_with(&renderer.device, &renderer.queue, &image, &self.settings)
+    }
+}
+
+/// loads `Handle<image::DynamicImage>`s for images that stay on the cpu
+pub struct ImageLoader;
+
+impl DecodeLoader for ImageLoader {
+    type Asset = image::DynamicImage;
+
+    fn extensions(&self) -> &[&str] {
+        IMAGE_EXTENSIONS
+    }
+
+    fn decode(&self, path: &Path, bytes: Vec<u8>) -> Result<Self::Asset, String> {
+        image::load_from_memory(&bytes).map_err(|e| e.to_string())
+    }
+}
+
+/// loads cube textures for `AssetServer::load_with`, the layout is picked by the size of the image:
+/// twice as wide as high is an equirectangular panorama, 4 by 3 faces a horizontal cross
+#[derive(Default)]
+pub struct CubeTextureLoader {
+    pub settings: texture::TextureSettings,
+}
+
+impl AssetLoader for CubeTextureLoader {
+    type Asset = texture::Texture;
+    type Decoded = texture::CubeFaces;
+
+    fn extensions(&self) -> &[&str] {
+        IMAGE_EXTENSIONS
+    }
+
+    fn decode(&self, path: &Path, bytes: Vec<u8>) -> Result<Self::Decoded, String> {
+        let image = image::load_from_memory(&bytes).map_err(|e| e.to_string())?;
+
+        if image.width() == image.height() * 2 {
+            texture::CubeFaces::from_equirectangular(&image, image.height() / 2)
+        } else {
+            texture::CubeFaces::from_cross(&image)
+        }
+    }
+
+    fn finish(
+        &self,
+        faces: Self::Decoded,
+        resources: &legion::Resources,
+    ) -> Result<Self::Asset, String> {
+        let renderer = match resources.get::<Renderer>() {
+            Some(r) => r,
+            None => {
+                return Err("the renderer is not set up".to_string());
+            }
+        };
+
+        texture::Texture::from_cube_faces(&renderer.device, &renderer.queue, &faces, &self.settings)
+    }
+}
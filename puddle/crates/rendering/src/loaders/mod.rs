@@ -0,0 +1,7 @@
+mod gltf_scene;
+mod images;
+mod obj;
+
+pub use gltf_scene::*;
+pub use images::{CubeTextureLoader, ImageLoader, TextureLoader};
+pub use obj::*;
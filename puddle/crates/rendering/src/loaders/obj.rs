@@ -0,0 +1,526 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use assets::{DecodeLoader, Handle};
+
+use crate::Vertex;
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(PathBuf, std::io::Error),
+    Parse {
+        file: String,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io(path, e) => write!(f, "cant read {} : {}", path.display(), e),
+            ObjError::Parse {
+                file,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", file, line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+/// one `o`/`g`/`usemtl` block of an obj file, ready for `Material::add_mesh`
+#[derive(Debug, Clone)]
+pub struct ObjMesh {
+    pub name: String,
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u16>,
+    pub material: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjMaterial {
+    pub name: String,
+    pub ambient: [f32; 3],
+    pub diffuse: [f32; 3],
+    pub specular: [f32; 3],
+    pub shininess: f32,
+    pub dissolve: f32,
+
+    pub diffuse_texture: Option<PathBuf>,
+    pub specular_texture: Option<PathBuf>,
+    pub normal_texture: Option<PathBuf>,
+}
+
+impl ObjMaterial {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ambient: [0.0; 3],
+            diffuse: [1.0; 3],
+            specular: [0.0; 3],
+            shininess: 0.0,
+            dissolve: 1.0,
+            diffuse_texture: None,
+            specular_texture: None,
+            normal_texture: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ObjModel {
+    pub meshes: Vec<ObjMesh>,
+    pub materials: Vec<ObjMaterial>,
+    /// the `mtllib` files referenced by the obj, relative to it
+    pub material_libs: Vec<String>,
+}
+
+impl ObjModel {
+    pub fn material(&self, name: &str) -> Option<&ObjMaterial> {
+        self.materials.iter().find(|x| x.name == name)
+    }
+}
+
+/// loads an obj file and every mtl file it references
+pub fn load_obj(path: impl AsRef<Path>) -> Result<ObjModel, ObjError> {
+    let path = path.as_ref();
+    let source = read_file(path)?;
+    let mut model = parse_obj(&source, &path.display().to_string())?;
+    load_material_libs(&mut model, path)?;
+
+    Ok(model)
+}
+
+/// loads the `mtllib` files of a model parsed from `path`
+fn load_material_libs(model: &mut ObjModel, path: &Path) -> Result<(), ObjError> {
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+
+    for lib in model.material_libs.iter() {
+        let mtl_path = base_dir.join(lib);
+        let source = read_file(&mtl_path)?;
+        let materials = parse_mtl(
+            &source,
+            &mtl_path.display().to_string(),
+            mtl_path.parent().unwrap_or(Path::new("")),
+        )?;
+        model.materials.extend(materials);
+    }
+
+    Ok(())
+}
+
+/// loads `Handle<ObjModel>`s with their mtl files, only changes to the obj file reload them
+pub struct ObjLoader;
+
+impl DecodeLoader for ObjLoader {
+    type Asset = ObjModel;
+
+    fn extensions(&self) -> &[&str] {
+        &["obj"]
+    }
+
+    fn decode(&self, path: &Path, bytes: Vec<u8>) -> Result<Self::Asset, String> {
+        let source = String::from_utf8(bytes).map_err(|e| e.to_string())?;
+        let mut model = parse_obj(&source, &path.display().to_string()).map_err(|e| e.to_string())?;
+        load_material_libs(&mut model, path).map_err(|e| e.to_string())?;
+
+        Ok(model)
+    }
+}
+
+/// one mesh of an obj model from the `AssetServer`, a material follows it with `Material::set_mesh_asset`
+pub struct ObjMeshAsset {
+    handle: Handle<ObjModel>,
+    /// the index in `ObjModel::meshes`
+    mesh: usize,
+    /// the `Handle::version` the mesh was last taken from
+    version: u32,
+}
+
+impl ObjMeshAsset {
+    pub fn new(model: Handle<ObjModel>, mesh: usize) -> Self {
+        Self {
+            handle: model,
+            mesh,
+            version: 0,
+        }
+    }
+
+    pub fn handle(&self) -> &Handle<ObjModel> {
+        &self.handle
+    }
+
+    /// the mesh if the model was loaded or reloaded since the last call.
+    /// a model without the mesh is an error once per version
+    pub fn take_changed(&mut self) -> Option<Result<ObjMesh, String>> {
+        let version = self.handle.version();
+        if version == self.version {
+            return None;
+        }
+        let model = self.handle.get()?;
+        self.version = version;
+
+        Some(model.meshes.get(self.mesh).cloned().ok_or_else(|| {
+            format!(
+                "{} has {} meshes, there is no mesh {}",
+                self.handle.path().display(),
+                model.meshes.len(),
+                self.mesh
+            )
+        }))
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    std::fs::read_to_string(path).map_err(|e| ObjError::Io(path.to_path_buf(), e))
+}
+
+/// parses the contents of an obj file, `file` is only used for error messages.
+/// `mtllib` entries are collected but not loaded, use `load_obj` for that
+pub fn parse_obj(source: &str, file: &str) -> Result<ObjModel, ObjError> {
+    let mut parser = ObjParser::default();
+
+    for (i, line) in source.lines().enumerate() {
+        parser.line(line).map_err(|message| ObjError::Parse {
+            file: file.to_string(),
+            line: i + 1,
+            message,
+        })?;
+    }
+
+    parser.finish_mesh();
+
+    Ok(ObjModel {
+        meshes: parser.meshes,
+        materials: vec![],
+        material_libs: parser.material_libs,
+    })
+}
+
+/// parses the contents of a mtl file, texture paths are resolved relative to `base_dir`
+pub fn parse_mtl(source: &str, file: &str, base_dir: &Path) -> Result<Vec<ObjMaterial>, ObjError> {
+    let mut materials: Vec<ObjMaterial> = vec![];
+
+    for (i, line) in source.lines().enumerate() {
+        parse_mtl_line(line, base_dir, &mut materials).map_err(|message| ObjError::Parse {
+            file: file.to_string(),
+            line: i + 1,
+            message,
+        })?;
+    }
+
+    Ok(materials)
+}
+
+fn parse_mtl_line(
+    line: &str,
+    base_dir: &Path,
+    materials: &mut Vec<ObjMaterial>,
+) -> Result<(), String> {
+    let mut tokens = line.split_whitespace();
+
+    let keyword = match tokens.next() {
+        Some(r) if !r.starts_with('#') => r,
+        _ => return Ok(()),
+    };
+    let args: Vec<&str> = tokens.collect();
+
+    if keyword == "newmtl" {
+        if args.is_empty() {
+            return Err("`newmtl` without a name".into());
+        }
+        materials.push(ObjMaterial::new(&args.join(" ")));
+        return Ok(());
+    }
+
+    let material = match materials.last_mut() {
+        Some(r) => r,
+        None => return Err(format!("`{}` before `newmtl`", keyword)),
+    };
+
+    match keyword {
+        "Ka" => material.ambient = parse_color(&args)?,
+        "Kd" => material.diffuse = parse_color(&args)?,
+        "Ks" => material.specular = parse_color(&args)?,
+        "Ns" => material.shininess = parse_floats(&args, 1, 1)?[0],
+        "d" => material.dissolve = parse_floats(&args, 1, 1)?[0],
+        "Tr" => material.dissolve = 1.0 - parse_floats(&args, 1, 1)?[0],
+        "map_Kd" => material.diffuse_texture = Some(parse_texture_path(&args, base_dir)?),
+        "map_Ks" => material.specular_texture = Some(parse_texture_path(&args, base_dir)?),
+        "map_Bump" | "map_bump" | "bump" | "norm" => {
+            material.normal_texture = Some(parse_texture_path(&args, base_dir)?)
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn parse_color(args: &[&str]) -> Result<[f32; 3], String> {
+    let values = parse_floats(args, 1, 3)?;
+
+    // a single value means grayscale
+    if values.len() == 1 {
+        return Ok([values[0]; 3]);
+    }
+    if values.len() != 3 {
+        return Err(format!("expected 1 or 3 color values, got {}", values.len()));
+    }
+    Ok([values[0], values[1], values[2]])
+}
+
+fn parse_texture_path(args: &[&str], base_dir: &Path) -> Result<PathBuf, String> {
+    // texture options like `-bm 1.0` come before the file name
+    match args.last() {
+        Some(r) => Ok(base_dir.join(r)),
+        None => Err("texture map without a file name".into()),
+    }
+}
+
+fn parse_floats(args: &[&str], min: usize, max: usize) -> Result<Vec<f32>, String> {
+    if args.len() < min || args.len() > max {
+        return Err(format!(
+            "expected {} to {} numbers, got {}",
+            min,
+            max,
+            args.len()
+        ));
+    }
+
+    args.iter()
+        .map(|x| {
+            x.parse::<f32>()
+                .map_err(|_| format!("`{}` is not a number", x))
+        })
+        .collect()
+}
+
+/// (position, uv, normal) indices of a face corner
+type CornerKey = (usize, Option<usize>, Option<usize>);
+
+#[derive(Default)]
+struct MeshBuilder {
+    name: String,
+    material: Option<String>,
+    vertices: Vec<Vertex>,
+    indices: Vec<u16>,
+    lookup: HashMap<CornerKey, u16>,
+    /// vertices without a `vn`, they get the averaged face normals
+    missing_normals: Vec<bool>,
+}
+
+#[derive(Default)]
+struct ObjParser {
+    positions: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    normals: Vec<[f32; 3]>,
+
+    current: MeshBuilder,
+    meshes: Vec<ObjMesh>,
+    material_libs: Vec<String>,
+}
+
+impl ObjParser {
+    fn line(&mut self, line: &str) -> Result<(), String> {
+        let mut tokens = line.split_whitespace();
+
+        let keyword = match tokens.next() {
+            Some(r) if !r.starts_with('#') => r,
+            _ => return Ok(()),
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => {
+                let v = parse_floats(&args, 3, 4)?;
+                self.positions.push([v[0], v[1], v[2]]);
+            }
+            "vt" => {
+                let v = parse_floats(&args, 1, 3)?;
+                // obj has the origin in the bottom left, wgpu in the top left
+                self.uvs.push([v[0], 1.0 - v.get(1).unwrap_or(&0.0)]);
+            }
+            "vn" => {
+                let v = parse_floats(&args, 3, 3)?;
+                self.normals.push([v[0], v[1], v[2]]);
+            }
+            "f" => self.face(&args)?,
+            "o" | "g" => {
+                let material = self.current.material.clone();
+                self.finish_mesh();
+                self.current.name = args.join(" ");
+                self.current.material = material;
+            }
+            "usemtl" => {
+                if args.is_empty() {
+                    return Err("`usemtl` without a name".into());
+                }
+                let name = self.current.name.clone();
+                self.finish_mesh();
+                self.current.name = name;
+                self.current.material = Some(args.join(" "));
+            }
+            "mtllib" => {
+                if args.is_empty() {
+                    return Err("`mtllib` without a file name".into());
+                }
+                self.material_libs.push(args.join(" "));
+            }
+            // smoothing groups, lines, points... are not supported and skipped
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn face(&mut self, args: &[&str]) -> Result<(), String> {
+        if args.len() < 3 {
+            return Err(format!(
+                "a face needs at least 3 vertices, got {}",
+                args.len()
+            ));
+        }
+
+        let mut corners = Vec::with_capacity(args.len());
+        for arg in args {
+            corners.push(self.corner(arg)?);
+        }
+
+        let a = self.positions[corners[0].0 .0];
+        let b = self.positions[corners[1].0 .0];
+        let c = self.positions[corners[2].0 .0];
+        let face_normal = cross(sub(b, a), sub(c, a));
+
+        for (key, index) in corners.iter() {
+            if key.2.is_none() {
+                let normal = &mut self.current.vertices[*index as usize].normal;
+                *normal = add(*normal, face_normal);
+            }
+        }
+
+        // polygons are triangulated as a fan around the first corner
+        for i in 1..corners.len() - 1 {
+            self.current.indices.push(corners[0].1);
+            self.current.indices.push(corners[i].1);
+            self.current.indices.push(corners[i + 1].1);
+        }
+
+        Ok(())
+    }
+
+    /// resolves a `v/vt/vn` triple into a vertex index of the current mesh
+    fn corner(&mut self, arg: &str) -> Result<(CornerKey, u16), String> {
+        let mut parts = arg.split('/');
+
+        let position = resolve_index(parts.next().unwrap_or(""), self.positions.len(), "v")?;
+        let uv = match parts.next() {
+            Some(r) if !r.is_empty() => Some(resolve_index(r, self.uvs.len(), "vt")?),
+            _ => None,
+        };
+        let normal = match parts.next() {
+            Some(r) if !r.is_empty() => Some(resolve_index(r, self.normals.len(), "vn")?),
+            _ => None,
+        };
+
+        let key = (position, uv, normal);
+
+        if let Some(index) = self.current.lookup.get(&key) {
+            return Ok((key, *index));
+        }
+
+        if self.current.vertices.len() > u16::MAX as usize {
+            return Err(format!(
+                "mesh `{}` has more than {} vertices, split it into several objects",
+                self.current.name,
+                u16::MAX as usize + 1
+            ));
+        }
+
+        let vertex = Vertex {
+            position: self.positions[position],
+            uv: uv.map(|x| self.uvs[x]).unwrap_or([0.0, 0.0]),
+            normal: normal.map(|x| self.normals[x]).unwrap_or([0.0, 0.0, 0.0]),
+        };
+
+        let index = self.current.vertices.len() as u16;
+        self.current.vertices.push(vertex);
+        self.current.missing_normals.push(normal.is_none());
+        self.current.lookup.insert(key, index);
+
+        Ok((key, index))
+    }
+
+    fn finish_mesh(&mut self) {
+        let mut builder = std::mem::take(&mut self.current);
+
+        if builder.indices.is_empty() {
+            return;
+        }
+
+        for (vertex, missing) in builder
+            .vertices
+            .iter_mut()
+            .zip(builder.missing_normals.iter())
+        {
+            if *missing {
+                vertex.normal = normalize(vertex.normal);
+            }
+        }
+
+        self.meshes.push(ObjMesh {
+            name: builder.name,
+            vertices: builder.vertices,
+            indices: builder.indices,
+            material: builder.material,
+        });
+    }
+}
+
+/// obj indices start at 1, negative ones count back from the last element
+fn resolve_index(value: &str, len: usize, kind: &str) -> Result<usize, String> {
+    let index: i64 = value
+        .parse()
+        .map_err(|_| format!("`{}` is not a valid {} index", value, kind))?;
+
+    let resolved = if index > 0 {
+        index - 1
+    } else {
+        len as i64 + index
+    };
+
+    if index == 0 || resolved < 0 || resolved >= len as i64 {
+        return Err(format!(
+            "{} index {} is out of range, only {} defined so far",
+            kind, index, len
+        ));
+    }
+
+    Ok(resolved as usize)
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn add(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(a: [f32; 3]) -> [f32; 3] {
+    let len = (a[0] * a[0] + a[1] * a[1] + a[2] * a[2]).sqrt();
+    if len == 0.0 {
+        return a;
+    }
+    [a[0] / len, a[1] / len, a[2] / len]
+}
@@ -0,0 +1,559 @@
+use std::sync::Arc;
+
+use legion::{Entity, EntityStore, IntoQuery};
+
+use crate::render_graph::{
+    NodeContext, NodeDesc, RenderNode, TransientTexture, HDR_TARGET, MAIN_DEPTH, MSAA_COLOR,
+    SHADOW_MAP,
+};
+use crate::render_queue::{queue_draws, DrawCall, QueueItem, SortView};
+use crate::{
+    Camera, CameraBindGroup, CameraBindGroupLayout, CameraView, InstanceRaw, Material,
+    RenderCamera, RenderTarget, Renderer, Viewport,
+};
+
+/// a camera as `view_clears` sees it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewTarget {
+    /// cameras with the same id draw into the same texture, `ViewTarget::SWAPCHAIN` for the swapchain
+    pub target: usize,
+    /// (x, y, width, height) in pixels
+    pub viewport: [f32; 4],
+    pub clear_color: wgpu::Color,
+    pub depth_clear_value: f32,
+}
+
+impl ViewTarget {
+    pub const SWAPCHAIN: usize = 0;
+}
+
+/// how a camera starts drawing into its target
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewClear {
+    /// only the first camera drawing to a target clears its color
+    pub color: Option<wgpu::Color>,
+    pub depth: f32,
+    /// the depth is cleared by drawing over the viewport instead of by the load op of the pass.
+    /// the cameras of the swapchain share one pass, a load op would clear the cameras drawn before too
+    pub depth_in_viewport: bool,
+}
+
+/// the clears of cameras in the order they are drawn. every camera clears the depth it draws into,
+/// so a camera never tests against the depth of the cameras drawn before it, even where their viewports overlap.
+/// offscreen cameras have a depth buffer of their own and clear it with their pass
+pub fn view_clears(views: &[ViewTarget]) -> Vec<ViewClear> {
+    views
+        .iter()
+        .enumerate()
+        .map(|(i, view)| {
+            let first = !views[..i].iter().any(|x| x.target == view.target);
+            ViewClear {
+                color: first.then_some(view.clear_color),
+                depth: view.depth_clear_value,
+                depth_in_viewport: !first && view.target == ViewTarget::SWAPCHAIN,
+            }
+        })
+        .collect()
+}
+
+/// a camera that gets rendered this frame, either the `Camera` resource or a `CameraView`
+struct PreparedView {
+    /// `None` for the `Camera` resource
+    entity: Option<Entity>,
+    target: ViewTarget,
+    priority: i32,
+    /// follows the projection, materials have pipelines for every compare of this frame's cameras
+    depth_compare: wgpu::CompareFunction,
+    /// set by `view_clears` once the views are sorted
+    clear: ViewClear,
+    /// the visible instances of `MainPassNode::materials`, in the order of their render queues
+    draws: Vec<DrawCall>,
+}
+
+impl PreparedView {
+    fn offscreen(&self) -> bool {
+        self.target.target != ViewTarget::SWAPCHAIN
+    }
+}
+
+/// draws the `Material`s on the layers of the `Camera` resource and of every `CameraView`, ordered by their `RenderQueue`.
+///
+/// the cameras drawing to the swapchain share the hdr pass the render graph opens. it is cleared with the
+/// colors of the first of them, the others clear the depth of their viewport before they draw,
+/// so a minimap can overlap the camera drawn before it. the depth prepass only covers the first of them.
+/// offscreen views get passes of their own
+pub struct MainPassNode {
+    views: Vec<PreparedView>,
+    materials: Vec<Entity>,
+    /// the bind group of the `Camera` resource
+    camera_bind_group: Option<Arc<wgpu::BindGroup>>,
+    clear_color: wgpu::Color,
+    depth_clear_value: f32,
+    depth_prepass: bool,
+    msaa: bool,
+}
+
+/// replaced by `view_clears` before anything is drawn
+const NO_CLEAR: ViewClear = ViewClear {
+    color: None,
+    depth: 1.0,
+    depth_in_viewport: false,
+};
+
+impl MainPassNode {
+    pub const NAME: &'static str = "main";
+
+    pub fn new() -> Self {
+        Self {
+            views: vec![],
+            materials: vec![],
+            camera_bind_group: None,
+            clear_color: wgpu::Color::BLACK,
+            depth_clear_value: 1.0,
+            depth_prepass: false,
+            msaa: false,
+        }
+    }
+
+    /// the materials of `self.materials`, `None` for the ones removed since `prepare`
+    fn materials<'a>(&self, world: &'a legion::World) -> Vec<Option<&'a Material>> {
+        self.materials
+            .iter()
+            .map(|entity| {
+                world
+                    .entry_ref(*entity)
+                    .ok()
+                    .and_then(|x| x.into_component::<Material>().ok())
+            })
+            .collect()
+    }
+
+    fn camera_view<'a>(world: &'a legion::World, prepared: &PreparedView) -> Option<&'a CameraView> {
+        world
+            .entry_ref(prepared.entity?)
+            .ok()
+            .and_then(|x| x.into_component::<CameraView>().ok())
+    }
+
+    /// the bind groups of a camera drawing to the swapchain
+    fn swapchain_bind_groups<'a>(
+        &'a self,
+        world: &'a legion::World,
+        prepared: &PreparedView,
+        renderer: &'a Renderer,
+    ) -> Option<ViewBindGroups<'a>> {
+        let camera = match prepared.entity {
+            None => self.camera_bind_group.as_deref()?,
+            Some(_) => &Self::camera_view(world, prepared)?.bind_group,
+        };
+        Some(ViewBindGroups {
+            camera,
+            depth: &renderer.depth.bind_group,
+        })
+    }
+}
+
+impl Default for MainPassNode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn begin_depth_prepass<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    depth: &'a wgpu::TextureView,
+    clear_value: f32,
+) -> wgpu::RenderPass<'a> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("depth prepass"),
+        color_attachments: &[],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+            view: depth,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(clear_value),
+                store: wgpu::StoreOp::Store,
+            }),
+            stencil_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(0),
+                store: wgpu::StoreOp::Store,
+            }),
+        }),
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    })
+}
+
+/// the bind groups of a camera, its uniform in group 0 and the depth of its target in group 2
+struct ViewBindGroups<'a> {
+    camera: &'a wgpu::BindGroup,
+    depth: &'a wgpu::BindGroup,
+}
+
+/// binds what every material of a camera uses
+fn bind_view<'a>(
+    rpass: &mut wgpu::RenderPass<'a>,
+    prepared: &PreparedView,
+    bind_groups: &ViewBindGroups<'a>,
+    renderer: &'a Renderer,
+) {
+    let [x, y, width, height] = prepared.target.viewport;
+    rpass.set_viewport(x, y, width, height, 0.0, 1.0);
+    rpass.set_vertex_buffer(1, renderer.instance_buffer.slice(..));
+    rpass.set_bind_group(0, bind_groups.camera, &[]);
+    rpass.set_bind_group(2, bind_groups.depth, &[]);
+    rpass.set_bind_group(3, &renderer.lights.bind_group, &[]);
+}
+
+fn draw_depth<'a>(
+    rpass: &mut wgpu::RenderPass<'a>,
+    prepared: &PreparedView,
+    bind_groups: &ViewBindGroups<'a>,
+    materials: &[Option<&'a Material>],
+    renderer: &'a Renderer,
+) {
+    bind_view(rpass, prepared, bind_groups, renderer);
+
+    for draw in prepared.draws.iter() {
+        let (material, pipeline) = match materials[draw.material] {
+            Some(material) => match &material.pipelines_for(prepared.depth_compare).depth {
+                Some(pipeline) => (material, pipeline),
+                None => continue,
+            },
+            None => continue,
+        };
+
+        rpass.set_pipeline(pipeline);
+        rpass.set_vertex_buffer(0, material.vertex_buffer.slice(..));
+        rpass.set_index_buffer(material.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        rpass.set_bind_group(1, &material.bind_groups, &[]);
+        rpass.draw_indexed(
+            0..material.indecies.len() as u32,
+            0,
+            draw.instances.clone(),
+        );
+    }
+}
+
+fn draw_colors<'a>(
+    rpass: &mut wgpu::RenderPass<'a>,
+    prepared: &PreparedView,
+    bind_groups: &ViewBindGroups<'a>,
+    materials: &[Option<&'a Material>],
+    renderer: &'a Renderer,
+) {
+    bind_view(rpass, prepared, bind_groups, renderer);
+
+    for draw in prepared.draws.iter() {
+        let material = match materials[draw.material] {
+            Some(r) => r,
+            None => continue,
+        };
+
+        rpass.set_vertex_buffer(0, material.vertex_buffer.slice(..));
+        rpass.set_index_buffer(material.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        rpass.set_pipeline(&material.pipelines_for(prepared.depth_compare).color);
+        if material.descriptor.depth_test {
+            rpass.set_stencil_reference(material.descriptor.stencil_reference);
+        }
+        rpass.set_bind_group(1, &material.bind_groups, &[]);
+
+        rpass.draw_indexed(
+            0..material.indecies.len() as u32,
+            0,
+            draw.instances.clone(),
+        );
+    }
+}
+
+impl RenderNode for MainPassNode {
+    fn desc(&self) -> NodeDesc {
+        let desc = NodeDesc::new(Self::NAME)
+            .reads(SHADOW_MAP)
+            .transient(TransientTexture::new(HDR_TARGET, Renderer::HDR_FORMAT));
+
+        let color = wgpu::LoadOp::Clear(self.clear_color);
+        let desc = if self.msaa {
+            desc.resolved_color_attachment(MSAA_COLOR, HDR_TARGET, color)
+        } else {
+            desc.color_attachment(HDR_TARGET, color)
+        };
+
+        // the depth prepass clears the depth before the pass is opened
+        let depth = if self.depth_prepass {
+            wgpu::LoadOp::Load
+        } else {
+            wgpu::LoadOp::Clear(self.depth_clear_value)
+        };
+        desc.depth_attachment(MAIN_DEPTH, depth)
+    }
+
+    fn prepare(
+        &mut self,
+        world: &mut legion::World,
+        resources: &legion::Resources,
+        renderer: &mut Renderer,
+    ) {
+        self.views.clear();
+        self.materials.clear();
+        self.camera_bind_group = resources.get::<CameraBindGroup>().map(|x| x.0.clone());
+        self.depth_prepass = renderer.depth_prepass;
+        self.msaa = renderer.msaa_color.is_some();
+
+        let mut camera_buffer = match resources.get_mut::<RenderCamera>() {
+            Some(r) => r,
+            None => {
+                return;
+            }
+        };
+        let camera = match resources.get::<Camera>() {
+            Some(r) => r,
+            None => {
+                return;
+            }
+        };
+
+        camera_buffer.uniform.update_view_proj(&camera);
+        renderer.queue.write_buffer(
+            &camera_buffer.buffer,
+            0,
+            bytemuck::cast_slice(&[camera_buffer.uniform]),
+        );
+
+        // materials built before the projection changed are rebuilt below
+        renderer.depth_compare = camera.projection.depth_compare();
+
+        // views with a projection that compares depth differently get pipelines of their own
+        let mut depth_compares = vec![];
+        if renderer.main_camera {
+            depth_compares.push(renderer.depth_compare);
+        }
+        for view in <&CameraView>::query().iter(world) {
+            let depth_compare = view.camera.projection.depth_compare();
+            if !depth_compares.contains(&depth_compare) {
+                depth_compares.push(depth_compare);
+            }
+        }
+
+        // pipelines and attachments follow the sample count and depth compare of the renderer
+        if let Some(camera_layout) = resources.get::<CameraBindGroupLayout>() {
+            for material in <&mut Material>::query().iter_mut(world) {
+                if material.pipelines_outdated(renderer) {
+                    material.rebuild_pipelines(renderer, &camera_layout);
+                }
+                material.prepare_view_pipelines(renderer, &camera_layout, &depth_compares);
+            }
+        }
+
+        let config = &renderer.surface_config;
+        let mut sort_views = vec![];
+
+        if renderer.main_camera {
+            sort_views.push(SortView::new(&camera, renderer.main_camera_layers));
+            self.views.push(PreparedView {
+                entity: None,
+                target: ViewTarget {
+                    target: ViewTarget::SWAPCHAIN,
+                    viewport: Viewport::FULL.to_pixels(config.width, config.height),
+                    clear_color: wgpu::Color {
+                        b: 0.01,
+                        ..wgpu::Color::BLACK
+                    },
+                    depth_clear_value: camera.projection.depth_clear_value(),
+                },
+                priority: 0,
+                depth_compare: renderer.depth_compare,
+                clear: NO_CLEAR,
+                draws: vec![],
+            });
+        }
+
+        for (entity, view) in <(Entity, &mut CameraView)>::query().iter_mut(world) {
+            if view.sample_count != renderer.sample_count {
+                view.create_attachments(renderer);
+            }
+
+            let (width, height) = view.target.size(config);
+            let viewport = view.viewport.to_pixels(width, height);
+
+            if view.camera.auto_aspect && viewport[3] > 0.0 {
+                view.camera.aspect = viewport[2] / viewport[3];
+            }
+
+            view.uniform.update_view_proj(&view.camera);
+            renderer
+                .queue
+                .write_buffer(&view.buffer, 0, bytemuck::cast_slice(&[view.uniform]));
+
+            let target = match (&view.target, &view.depth) {
+                (RenderTarget::Texture(texture), Some(_)) => Arc::as_ptr(texture) as usize,
+                _ => ViewTarget::SWAPCHAIN,
+            };
+
+            sort_views.push(SortView::new(&view.camera, view.layers));
+            self.views.push(PreparedView {
+                entity: Some(*entity),
+                target: ViewTarget {
+                    target,
+                    viewport,
+                    clear_color: view.clear_color,
+                    depth_clear_value: view.camera.projection.depth_clear_value(),
+                },
+                priority: view.priority,
+                depth_compare: view.camera.projection.depth_compare(),
+                clear: NO_CLEAR,
+                draws: vec![],
+            });
+        }
+
+        // materials with a color format of their own are drawn by other nodes
+        let mut materials = <(Entity, &Material)>::query();
+        let materials: Vec<(&Entity, &Material)> = materials
+            .iter(world)
+            .filter(|(_, material)| material.descriptor.color_format() == Renderer::HDR_FORMAT)
+            .collect();
+        self.materials = materials.iter().map(|(entity, _)| **entity).collect();
+
+        // cull and sort every material for every camera and pack the visible instances into one buffer
+        let materials: Vec<QueueItem> = materials
+            .iter()
+            .map(|(_, material)| QueueItem::from(*material))
+            .collect();
+        let mut instances: Vec<InstanceRaw> = vec![];
+
+        for (view, sort_view) in self.views.iter_mut().zip(sort_views.iter()) {
+            view.draws = queue_draws(sort_view, &materials, &mut instances);
+        }
+
+        // offscreen targets first, so they can be sampled by the swapchain cameras of the same frame.
+        // the sort is stable, so the main camera stays in front of views with the same priority
+        self.views.sort_by_key(|x| (!x.offscreen(), x.priority));
+
+        let targets: Vec<ViewTarget> = self.views.iter().map(|x| x.target).collect();
+        for (view, clear) in self.views.iter_mut().zip(view_clears(&targets)) {
+            view.clear = clear;
+        }
+
+        // the pass the graph opens for the swapchain cameras is cleared for the first of them
+        if let Some(first) = self.views.iter().find(|x| !x.offscreen()) {
+            self.clear_color = first.target.clear_color;
+            self.depth_clear_value = first.clear.depth;
+        }
+
+        if instances.len() > renderer.instance_capacity {
+            renderer.instance_capacity = instances.len().next_power_of_two();
+            renderer.instance_buffer = renderer.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("instance buffer"),
+                size: (renderer.instance_capacity * std::mem::size_of::<InstanceRaw>())
+                    as wgpu::BufferAddress,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+        }
+        if !instances.is_empty() {
+            renderer
+                .queue
+                .write_buffer(&renderer.instance_buffer, 0, bytemuck::cast_slice(&instances));
+        }
+    }
+
+    /// the offscreen views and the depth prepass, they run before the graph opens the hdr pass
+    fn record(&self, encoder: &mut wgpu::CommandEncoder, ctx: &NodeContext) {
+        let renderer = ctx.renderer;
+        let materials = self.materials(ctx.world);
+
+        for prepared in self.views.iter().filter(|x| x.offscreen()) {
+            let camera_view = match Self::camera_view(ctx.world, prepared) {
+                Some(r) => r,
+                None => continue,
+            };
+            let (texture, depth) = match (&camera_view.target, &camera_view.depth) {
+                (RenderTarget::Texture(texture), Some(depth)) => (texture, depth),
+                _ => continue,
+            };
+            let (color, resolve) = match &camera_view.msaa_color {
+                Some(msaa) => (&msaa.view, Some(&texture.view)),
+                None => (&texture.view, None),
+            };
+
+            let bind_groups = ViewBindGroups {
+                camera: &camera_view.bind_group,
+                depth: &depth.bind_group,
+            };
+
+            if renderer.depth_prepass {
+                let mut rpass = begin_depth_prepass(encoder, &depth.depth_texture.view, prepared.clear.depth);
+                draw_depth(&mut rpass, prepared, &bind_groups, &materials, renderer);
+            }
+            // like the swapchain, the depth of this frame with the prepass, otherwise the one of the last frame
+            depth.copy_to_sampled(encoder);
+
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("camera view"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: color,
+                    resolve_target: resolve,
+                    ops: wgpu::Operations {
+                        load: match prepared.clear.color {
+                            Some(color) => wgpu::LoadOp::Clear(color),
+                            None => wgpu::LoadOp::Load,
+                        },
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &depth.depth_texture.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: if renderer.depth_prepass {
+                            wgpu::LoadOp::Load
+                        } else {
+                            wgpu::LoadOp::Clear(prepared.clear.depth)
+                        },
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: Some(wgpu::Operations {
+                        load: if renderer.depth_prepass {
+                            wgpu::LoadOp::Load
+                        } else {
+                            wgpu::LoadOp::Clear(0)
+                        },
+                        store: wgpu::StoreOp::Store,
+                    }),
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            draw_colors(&mut rpass, prepared, &bind_groups, &materials, renderer);
+        }
+
+        // the swapchain cameras share the depth buffer, the ones after the first clear their viewport
+        // in the hdr pass, which would throw away what the prepass drew for them
+        let first = self.views.iter().find(|x| !x.offscreen());
+        if let (true, Some(prepared)) = (renderer.depth_prepass, first) {
+            if let (Some(depth), Some(bind_groups)) = (
+                ctx.texture_view(MAIN_DEPTH),
+                self.swapchain_bind_groups(ctx.world, prepared, renderer),
+            ) {
+                let mut rpass = begin_depth_prepass(encoder, depth, prepared.clear.depth);
+                draw_depth(&mut rpass, prepared, &bind_groups, &materials, renderer);
+            }
+        }
+
+        // with the prepass materials sample the depth of this frame, otherwise the one of the last frame
+        renderer.depth.copy_to_sampled(encoder);
+    }
+
+    fn draw<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>, ctx: &NodeContext<'a>) {
+        let materials = self.materials(ctx.world);
+
+        for prepared in self.views.iter().filter(|x| !x.offscreen()) {
+            if let Some(bind_groups) = self.swapchain_bind_groups(ctx.world, prepared, ctx.renderer) {
+                if prepared.clear.depth_in_viewport {
+                    ctx.renderer
+                        .viewport_depth_clear
+                        .clear(rpass, prepared.target.viewport, prepared.clear.depth);
+                }
+                draw_colors(rpass, prepared, &bind_groups, &materials, ctx.renderer);
+            }
+        }
+    }
+}
@@ -0,0 +1,334 @@
+use std::path::PathBuf;
+
+use crate::reflection;
+use crate::render_queue::RenderQueue;
+use crate::shader_assets::{self, ShaderError};
+use crate::{CameraBindGroupLayout, Material, PuddleBindGroupEntry, Renderer, MATERIAL_GROUP};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    /// overwrites the target
+    Opaque,
+    /// blends with the source alpha
+    Alpha,
+    /// the color was already multiplied with its alpha
+    Premultiplied,
+    /// adds the color onto the target, for glow and particles
+    Additive,
+}
+
+impl BlendMode {
+    pub fn state(self) -> Option<wgpu::BlendState> {
+        match self {
+            BlendMode::Opaque => None,
+            BlendMode::Alpha => Some(wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent::OVER,
+            }),
+            BlendMode::Premultiplied => Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+            BlendMode::Additive => Some(wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent::OVER,
+            }),
+        }
+    }
+}
+
+/// the pipeline state of a material
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MaterialDescriptor {
+    pub label: Option<String>,
+    pub blend: BlendMode,
+    pub cull_mode: Option<wgpu::Face>,
+    pub front_face: wgpu::FrontFace,
+    /// `Line` and `Point` need the adapter to support `POLYGON_MODE_LINE` and `POLYGON_MODE_POINT`
+    pub polygon_mode: wgpu::PolygonMode,
+    pub topology: wgpu::PrimitiveTopology,
+    /// ignores the depth buffer when off, it neither tests nor writes it
+    pub depth_test: bool,
+    /// only materials that write depth are drawn in the depth prepass and cast shadows
+    pub depth_write: bool,
+    /// `None` follows the projection of the camera
+    pub depth_compare: Option<wgpu::CompareFunction>,
+    pub stencil: wgpu::StencilState,
+    pub stencil_reference: u32,
+    pub vertex_entry: String,
+    pub fragment_entry: String,
+    /// `None` renders into the hdr target like every camera does.
+    /// materials with another format are skipped by the main pass, draw them in a render node of your own
+    pub color_format: Option<wgpu::TextureFormat>,
+    /// when the main pass draws the material
+    pub queue: RenderQueue,
+    /// lower draws first within the queue, before the distance sorting
+    pub queue_priority: i32,
+}
+
+impl Default for MaterialDescriptor {
+    fn default() -> Self {
+        Self {
+            label: None,
+            blend: BlendMode::Alpha,
+            cull_mode: Some(wgpu::Face::Back),
+            front_face: wgpu::FrontFace::Ccw,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            depth_test: true,
+            depth_write: true,
+            depth_compare: None,
+            stencil: wgpu::StencilState::default(),
+            stencil_reference: 0,
+            vertex_entry: "vs_main".to_string(),
+            fragment_entry: "fs_main".to_string(),
+            color_format: None,
+            queue: RenderQueue::Opaque,
+            queue_priority: 0,
+        }
+    }
+}
+
+impl MaterialDescriptor {
+    pub fn color_format(&self) -> wgpu::TextureFormat {
+        self.color_format.unwrap_or(Renderer::HDR_FORMAT)
+    }
+
+    /// whether the material has a depth prepass and shadow pipeline
+    pub fn writes_depth(&self) -> bool {
+        self.depth_test && self.depth_write
+    }
+
+    pub(crate) fn primitive(&self) -> wgpu::PrimitiveState {
+        let strip_index_format = match self.topology {
+            wgpu::PrimitiveTopology::LineStrip | wgpu::PrimitiveTopology::TriangleStrip => {
+                Some(wgpu::IndexFormat::Uint16)
+            }
+            _ => None,
+        };
+
+        wgpu::PrimitiveState {
+            topology: self.topology,
+            strip_index_format,
+            front_face: self.front_face,
+            cull_mode: self.cull_mode,
+            polygon_mode: self.polygon_mode,
+            ..Default::default()
+        }
+    }
+}
+
+enum MaterialShader<'a> {
+    Module(Box<wgpu::ShaderModuleDescriptor<'a>>),
+    Wgsl { file: String, source: String },
+    File(PathBuf),
+}
+
+/// builds a `Material`, start with `Material::from_module`, `Material::from_wgsl` or `Material::from_file`
+pub struct MaterialBuilder<'a> {
+    shader: MaterialShader<'a>,
+    entries: Vec<PuddleBindGroupEntry>,
+    descriptor: MaterialDescriptor,
+}
+
+impl<'a> MaterialBuilder<'a> {
+    fn new(shader: MaterialShader<'a>) -> Self {
+        Self {
+            shader,
+            entries: vec![],
+            descriptor: MaterialDescriptor::default(),
+        }
+    }
+
+    /// the resources of group 1, matched with the wgsl variables of the same name
+    pub fn entries(mut self, entries: Vec<PuddleBindGroupEntry>) -> Self {
+        self.entries = entries;
+        self
+    }
+
+    pub fn entry(mut self, entry: PuddleBindGroupEntry) -> Self {
+        self.entries.push(entry);
+        self
+    }
+
+    pub fn descriptor(mut self, descriptor: MaterialDescriptor) -> Self {
+        self.descriptor = descriptor;
+        self
+    }
+
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.descriptor.label = Some(label.into());
+        self
+    }
+
+    pub fn blend(mut self, blend: BlendMode) -> Self {
+        self.descriptor.blend = blend;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: Option<wgpu::Face>) -> Self {
+        self.descriptor.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn front_face(mut self, front_face: wgpu::FrontFace) -> Self {
+        self.descriptor.front_face = front_face;
+        self
+    }
+
+    pub fn polygon_mode(mut self, polygon_mode: wgpu::PolygonMode) -> Self {
+        self.descriptor.polygon_mode = polygon_mode;
+        self
+    }
+
+    /// draws the edges of the triangles only
+    pub fn wireframe(self) -> Self {
+        self.polygon_mode(wgpu::PolygonMode::Line)
+    }
+
+    pub fn topology(mut self, topology: wgpu::PrimitiveTopology) -> Self {
+        self.descriptor.topology = topology;
+        self
+    }
+
+    pub fn depth_test(mut self, depth_test: bool) -> Self {
+        self.descriptor.depth_test = depth_test;
+        self
+    }
+
+    pub fn depth_write(mut self, depth_write: bool) -> Self {
+        self.descriptor.depth_write = depth_write;
+        self
+    }
+
+    pub fn depth_compare(mut self, depth_compare: wgpu::CompareFunction) -> Self {
+        self.descriptor.depth_compare = Some(depth_compare);
+        self
+    }
+
+    pub fn stencil(mut self, stencil: wgpu::StencilState, reference: u32) -> Self {
+        self.descriptor.stencil = stencil;
+        self.descriptor.stencil_reference = reference;
+        self
+    }
+
+    pub fn entry_points(mut self, vertex: impl Into<String>, fragment: impl Into<String>) -> Self {
+        self.descriptor.vertex_entry = vertex.into();
+        self.descriptor.fragment_entry = fragment.into();
+        self
+    }
+
+    pub fn color_format(mut self, format: wgpu::TextureFormat) -> Self {
+        self.descriptor.color_format = Some(format);
+        self
+    }
+
+    pub fn queue(mut self, queue: RenderQueue) -> Self {
+        self.descriptor.queue = queue;
+        self
+    }
+
+    pub fn queue_priority(mut self, priority: i32) -> Self {
+        self.descriptor.queue_priority = priority;
+        self
+    }
+
+    /// invalid shaders or pipeline state return an error instead of panicking
+    pub fn build(
+        self,
+        renderer: &mut Renderer,
+        camera_bind_group: &CameraBindGroupLayout,
+    ) -> Result<Material, ShaderError> {
+        let Self {
+            shader,
+            entries,
+            descriptor,
+        } = self;
+
+        match shader {
+            MaterialShader::Module(shader) => {
+                let file = match (&descriptor.label, &shader.label) {
+                    (Some(label), _) => label.clone(),
+                    (None, Some(label)) => label.to_string(),
+                    (None, None) => "material".to_string(),
+                };
+                let device = renderer.device.clone();
+                // only wgsl can be compared with the modules of other materials
+                let wgsl = match &shader.source {
+                    wgpu::ShaderSource::Wgsl(source) => Some(source.to_string()),
+                    _ => None,
+                };
+                let validated = match &wgsl {
+                    Some(source) => Some(shader_assets::validate_wgsl_with_info(&file, source)?),
+                    None => None,
+                };
+                let reflected = validated
+                    .as_ref()
+                    .map(|(module, info)| reflection::reflect_bindings(module, info, MATERIAL_GROUP));
+                let module = match (wgsl, validated) {
+                    (Some(source), Some((module, _))) => {
+                        renderer.pipeline_cache.shader(&device, &file, &source, module)?
+                    }
+                    _ => {
+                        let module = shader_assets::catch_wgpu_errors(&device, &file, || {
+                            device.create_shader_module(*shader)
+                        })?;
+                        renderer.pipeline_cache.unique_shader(module)
+                    }
+                };
+                shader_assets::catch_wgpu_errors(&device, &file, || {
+                    Material::with_module(
+                        renderer,
+                        entries,
+                        camera_bind_group,
+                        module,
+                        &file,
+                        reflected.as_deref(),
+                        descriptor,
+                    )
+                })?
+            }
+            MaterialShader::Wgsl { file, source } => {
+                // validated and reflected by the material
+                let source = renderer.shader_preprocessor.process(&file, &source)?;
+                Material::from_preprocessed(renderer, entries, camera_bind_group, &source, descriptor)
+            }
+            MaterialShader::File(path) => {
+                let source = renderer.shader_preprocessor.process_file(&path)?;
+
+                let mut material =
+                    Material::from_preprocessed(renderer, entries, camera_bind_group, &source, descriptor)?;
+                material.shader_files = shader_assets::watch_files(renderer, &source);
+                material.shader_path = Some(path);
+                Ok(material)
+            }
+        }
+    }
+}
+
+impl Material {
+    /// a material with a shader module that is used as it is, like one from `include_wgsl!`
+    pub fn from_module(shader: wgpu::ShaderModuleDescriptor) -> MaterialBuilder {
+        MaterialBuilder::new(MaterialShader::Module(Box::new(shader)))
+    }
+
+    /// a material with wgsl that runs through the shader preprocessor of the renderer first,
+    /// so it can `#import` the engine modules
+    pub fn from_wgsl<'a>(file: impl Into<String>, source: impl Into<String>) -> MaterialBuilder<'a> {
+        MaterialBuilder::new(MaterialShader::Wgsl {
+            file: file.into(),
+            source: source.into(),
+        })
+    }
+
+    /// a material with a wgsl file that is preprocessed and reloaded when it or its imports change,
+    /// the `AssetPlugin` has to be added with hot reloading for that. a broken shader keeps the last working pipeline
+    pub fn from_file<'a>(path: impl Into<PathBuf>) -> MaterialBuilder<'a> {
+        MaterialBuilder::new(MaterialShader::File(path.into()))
+    }
+}
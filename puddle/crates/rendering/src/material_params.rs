@@ -0,0 +1,623 @@
+use std::fmt;
+use std::sync::Arc;
+
+use assets::Handle;
+use legion::IntoQuery;
+use wgpu::util::DeviceExt;
+
+use crate::reflection::{GivenBinding, ResolvedBinding};
+use crate::{ComputeMaterial, Material, PuddleBindGroupEntry, Renderer};
+
+/// a value of a uniform field, laid out like the wgsl type of the same name
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParamValue {
+    F32(f32),
+    U32(u32),
+    I32(i32),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+    Mat4([[f32; 4]; 4]),
+}
+
+impl ParamValue {
+    /// the wgsl alignment of the type
+    fn align(&self) -> usize {
+        match self {
+            ParamValue::F32(_) | ParamValue::U32(_) | ParamValue::I32(_) => 4,
+            ParamValue::Vec2(_) => 8,
+            ParamValue::Vec3(_) | ParamValue::Vec4(_) | ParamValue::Mat4(_) => 16,
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match self {
+            ParamValue::F32(x) => bytemuck::bytes_of(x),
+            ParamValue::U32(x) => bytemuck::bytes_of(x),
+            ParamValue::I32(x) => bytemuck::bytes_of(x),
+            ParamValue::Vec2(x) => bytemuck::bytes_of(x),
+            ParamValue::Vec3(x) => bytemuck::bytes_of(x),
+            ParamValue::Vec4(x) => bytemuck::bytes_of(x),
+            ParamValue::Mat4(x) => bytemuck::bytes_of(x),
+        }
+    }
+
+    fn same_type(&self, other: &ParamValue) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
+/// what a material binds, owned so it can be swapped after the material was built
+#[derive(Clone)]
+pub enum MaterialResource {
+    Buffer(Arc<wgpu::Buffer>),
+    TextureView(Arc<wgpu::TextureView>),
+    Sampler(Arc<wgpu::Sampler>),
+    /// binds the view of the texture
+    Texture(Arc<texture::Texture>),
+    /// binds the sampler of the texture
+    TextureSampler(Arc<texture::Texture>),
+    /// binds the view of a loaded texture, a blank placeholder until it is loaded.
+    /// the bind group follows when the file is reloaded
+    TextureAsset(Handle<texture::Texture>),
+    /// binds the sampler of a loaded texture
+    TextureAssetSampler(Handle<texture::Texture>),
+    /// a uniform buffer the material creates from named fields, in the order of the wgsl struct.
+    /// change them with `Material::set_param`
+    Uniforms(Vec<(String, ParamValue)>),
+}
+
+impl MaterialResource {
+    /// a uniform block from field names and values
+    pub fn uniforms<'a>(fields: impl IntoIterator<Item = (&'a str, ParamValue)>) -> Self {
+        MaterialResource::Uniforms(
+            fields
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        )
+    }
+
+    fn fits(&self, ty: &wgpu::BindingType) -> bool {
+        matches!(
+            (self, ty),
+            (MaterialResource::Buffer(_), wgpu::BindingType::Buffer { .. })
+                | (
+                    MaterialResource::Uniforms(_),
+                    wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        ..
+                    },
+                )
+                | (
+                    MaterialResource::TextureView(_)
+                        | MaterialResource::Texture(_)
+                        | MaterialResource::TextureAsset(_),
+                    wgpu::BindingType::Texture { .. } | wgpu::BindingType::StorageTexture { .. },
+                )
+                | (
+                    MaterialResource::Sampler(_)
+                        | MaterialResource::TextureSampler(_)
+                        | MaterialResource::TextureAssetSampler(_),
+                    wgpu::BindingType::Sampler(_),
+                )
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamError {
+    /// no binding or uniform field has this name
+    Unknown(String),
+    /// the binding or field has another type
+    WrongType { name: String, expected: String },
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamError::Unknown(name) => write!(f, "the material has no parameter {}", name),
+            ParamError::WrongType { name, expected } => {
+                write!(f, "the material parameter {} is a {}", name, expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParamError {}
+
+/// the named fields of a uniform buffer and their bytes, laid out like a wgsl struct
+struct UniformFields {
+    /// name, value and byte offset
+    fields: Vec<(String, ParamValue, usize)>,
+    data: Vec<u8>,
+}
+
+impl UniformFields {
+    fn new(fields: Vec<(String, ParamValue)>) -> Self {
+        let mut offset = 0usize;
+        let fields: Vec<(String, ParamValue, usize)> = fields
+            .into_iter()
+            .map(|(name, value)| {
+                offset = offset.next_multiple_of(value.align());
+                let field = (name, value, offset);
+                offset += value.bytes().len();
+                field
+            })
+            .collect();
+
+        // structs in uniform buffers are padded to 16 bytes
+        let mut data = vec![0; offset.next_multiple_of(16).max(16)];
+        for (_, value, offset) in fields.iter() {
+            let bytes = value.bytes();
+            data[*offset..*offset + bytes.len()].copy_from_slice(bytes);
+        }
+
+        Self { fields, data }
+    }
+
+    fn set(&mut self, name: &str, value: ParamValue) -> Option<Result<(), ParamError>> {
+        let (_, current, offset) = self.fields.iter_mut().find(|(x, _, _)| x == name)?;
+        if !current.same_type(&value) {
+            return Some(Err(ParamError::WrongType {
+                name: name.to_string(),
+                expected: format!("{:?}", current),
+            }));
+        }
+
+        *current = value;
+        let bytes = value.bytes();
+        self.data[*offset..*offset + bytes.len()].copy_from_slice(bytes);
+        Some(Ok(()))
+    }
+
+    fn get(&self, name: &str) -> Option<ParamValue> {
+        self.fields.iter().find(|(x, _, _)| x == name).map(|(_, value, _)| *value)
+    }
+}
+
+/// a uniform buffer with named fields, written to the gpu before the next frame when a field changed
+pub(crate) struct UniformBlock {
+    fields: UniformFields,
+    pub buffer: wgpu::Buffer,
+    dirty: bool,
+}
+
+impl UniformBlock {
+    fn new(device: &wgpu::Device, name: &str, fields: Vec<(String, ParamValue)>) -> Self {
+        let fields = UniformFields::new(fields);
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(name),
+            contents: &fields.data,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        Self {
+            fields,
+            buffer,
+            dirty: false,
+        }
+    }
+
+    fn set(&mut self, name: &str, value: ParamValue) -> Option<Result<(), ParamError>> {
+        let result = self.fields.set(name, value)?;
+        self.dirty |= result.is_ok();
+        Some(result)
+    }
+
+    fn get(&self, name: &str) -> Option<ParamValue> {
+        self.fields.get(name)
+    }
+}
+
+/// a texture handle and the texture that is bound for it
+pub(crate) struct AssetBinding {
+    handle: Handle<texture::Texture>,
+    /// binds the sampler instead of the view
+    sampler: bool,
+    /// a placeholder until the handle is loaded
+    current: Arc<texture::Texture>,
+    /// the `Handle::version` of `current`
+    version: u32,
+}
+
+pub(crate) enum BoundResource {
+    Resource(MaterialResource),
+    Uniforms(UniformBlock),
+    Asset(AssetBinding),
+}
+
+impl BoundResource {
+    fn new(device: &wgpu::Device, name: &str, resource: MaterialResource) -> Self {
+        let (handle, sampler) = match resource {
+            MaterialResource::Uniforms(fields) => {
+                return BoundResource::Uniforms(UniformBlock::new(device, name, fields));
+            }
+            MaterialResource::TextureAsset(handle) => (handle, false),
+            MaterialResource::TextureAssetSampler(handle) => (handle, true),
+            resource => {
+                return BoundResource::Resource(resource);
+            }
+        };
+
+        let current = match handle.get() {
+            Some(r) => r,
+            None => Arc::new(texture::Texture::create_render_target(
+                device,
+                1,
+                1,
+                wgpu::TextureFormat::Rgba8UnormSrgb,
+                "placeholder",
+            )),
+        };
+
+        BoundResource::Asset(AssetBinding {
+            version: handle.version(),
+            handle,
+            sampler,
+            current,
+        })
+    }
+}
+
+/// one binding of group 1
+pub(crate) struct MaterialBinding {
+    pub name: String,
+    /// the `@binding` of the shader, or the position in the entries if the shader can't be reflected
+    pub binding: u32,
+    pub ty: wgpu::BindingType,
+    pub visibility: wgpu::ShaderStages,
+    pub resource: BoundResource,
+}
+
+impl MaterialBinding {
+    pub fn new(device: &wgpu::Device, binding: u32, entry: PuddleBindGroupEntry) -> Self {
+        let PuddleBindGroupEntry {
+            name,
+            ty,
+            visibility,
+            resource,
+        } = entry;
+
+        let resource = BoundResource::new(device, &name, resource);
+
+        Self {
+            name,
+            binding,
+            ty,
+            visibility,
+            resource,
+        }
+    }
+
+    /// what `reflection::resolve_bindings` checks against the shader
+    pub fn given(&self) -> GivenBinding<'_> {
+        let buffer_size = match &self.resource {
+            BoundResource::Uniforms(block) => Some(block.buffer.size()),
+            BoundResource::Resource(MaterialResource::Buffer(buffer)) => Some(buffer.size()),
+            _ => None,
+        };
+
+        GivenBinding {
+            name: &self.name,
+            ty: self.ty,
+            visibility: self.visibility,
+            buffer_size,
+        }
+    }
+
+    /// takes the place, stages and layout type the shader declares
+    pub fn resolve(&mut self, resolved: ResolvedBinding) {
+        self.binding = resolved.binding;
+        self.visibility = resolved.visibility;
+        self.ty = resolved.ty;
+    }
+
+    fn binding_resource(&self) -> wgpu::BindingResource<'_> {
+        match &self.resource {
+            BoundResource::Uniforms(block) => block.buffer.as_entire_binding(),
+            BoundResource::Asset(asset) if asset.sampler => wgpu::BindingResource::Sampler(&asset.current.sampler),
+            BoundResource::Asset(asset) => wgpu::BindingResource::TextureView(&asset.current.view),
+            BoundResource::Resource(resource) => match resource {
+                MaterialResource::Buffer(buffer) => buffer.as_entire_binding(),
+                MaterialResource::TextureView(view) => wgpu::BindingResource::TextureView(view),
+                MaterialResource::Sampler(sampler) => wgpu::BindingResource::Sampler(sampler),
+                MaterialResource::Texture(texture) => wgpu::BindingResource::TextureView(&texture.view),
+                MaterialResource::TextureSampler(texture) => wgpu::BindingResource::Sampler(&texture.sampler),
+                MaterialResource::Uniforms(_)
+                | MaterialResource::TextureAsset(_)
+                | MaterialResource::TextureAssetSampler(_) => unreachable!("turned into their own bound resource"),
+            },
+        }
+    }
+}
+
+/// sorted by binding, so the same bindings always share a layout
+pub(crate) fn layout_entries(
+    bindings: impl IntoIterator<Item = (u32, wgpu::ShaderStages, wgpu::BindingType)>,
+) -> Vec<wgpu::BindGroupLayoutEntry> {
+    let mut entries: Vec<wgpu::BindGroupLayoutEntry> = bindings
+        .into_iter()
+        .map(|(binding, visibility, ty)| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty,
+            count: None,
+        })
+        .collect();
+    entries.sort_by_key(|x| x.binding);
+    entries
+}
+
+pub(crate) fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    bindings: &[MaterialBinding],
+) -> wgpu::BindGroup {
+    let entries: Vec<wgpu::BindGroupEntry> = bindings
+        .iter()
+        .map(|x| wgpu::BindGroupEntry {
+            binding: x.binding,
+            resource: x.binding_resource(),
+        })
+        .collect();
+
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("bind group"),
+        layout,
+        entries: &entries,
+    })
+}
+
+pub(crate) fn set_param(bindings: &mut [MaterialBinding], name: &str, value: ParamValue) -> Result<(), ParamError> {
+    for binding in bindings.iter_mut() {
+        if let BoundResource::Uniforms(block) = &mut binding.resource {
+            if let Some(result) = block.set(name, value) {
+                return result;
+            }
+        }
+    }
+    Err(ParamError::Unknown(name.to_string()))
+}
+
+pub(crate) fn param(bindings: &[MaterialBinding], name: &str) -> Option<ParamValue> {
+    bindings.iter().find_map(|binding| match &binding.resource {
+        BoundResource::Uniforms(block) => block.get(name),
+        _ => None,
+    })
+}
+
+/// the bind group has to be rebuilt after this
+pub(crate) fn set_resource(
+    device: &wgpu::Device,
+    bindings: &mut [MaterialBinding],
+    name: &str,
+    resource: MaterialResource,
+) -> Result<(), ParamError> {
+    let binding = match bindings.iter_mut().find(|x| x.name == name) {
+        Some(r) => r,
+        None => {
+            return Err(ParamError::Unknown(name.to_string()));
+        }
+    };
+    if !resource.fits(&binding.ty) {
+        return Err(ParamError::WrongType {
+            name: name.to_string(),
+            expected: format!("{:?}", binding.ty),
+        });
+    }
+
+    binding.resource = BoundResource::new(device, name, resource);
+    Ok(())
+}
+
+/// swaps in the textures of handles that were loaded or reloaded, true if the bind group has to be rebuilt
+pub(crate) fn refresh_assets(bindings: &mut [MaterialBinding]) -> bool {
+    let mut changed = false;
+
+    for binding in bindings.iter_mut() {
+        let asset = match &mut binding.resource {
+            BoundResource::Asset(r) => r,
+            _ => continue,
+        };
+        if asset.handle.version() == asset.version {
+            continue;
+        }
+
+        if let Some(texture) = asset.handle.get() {
+            asset.current = texture;
+            asset.version = asset.handle.version();
+            changed = true;
+        }
+    }
+
+    changed
+}
+
+pub(crate) fn upload_uniforms(bindings: &mut [MaterialBinding], queue: &wgpu::Queue) {
+    for binding in bindings.iter_mut() {
+        if let BoundResource::Uniforms(block) = &mut binding.resource {
+            if block.dirty {
+                queue.write_buffer(&block.buffer, 0, &block.fields.data);
+                block.dirty = false;
+            }
+        }
+    }
+}
+
+impl Material {
+    /// changes a field of a `MaterialResource::Uniforms` binding, it is uploaded before the next frame
+    pub fn set_param(&mut self, name: &str, value: ParamValue) -> Result<(), ParamError> {
+        set_param(&mut self.bindings, name, value)
+    }
+
+    pub fn param(&self, name: &str) -> Option<ParamValue> {
+        param(&self.bindings, name)
+    }
+
+    /// replaces the resource of the binding called `name`, the bind group is rebuilt before the next frame
+    pub fn set_resource(&mut self, name: &str, resource: MaterialResource) -> Result<(), ParamError> {
+        set_resource(&self.device, &mut self.bindings, name, resource)?;
+        self.bind_group_dirty = true;
+        Ok(())
+    }
+
+    pub fn set_texture(&mut self, name: &str, texture: Arc<texture::Texture>) -> Result<(), ParamError> {
+        self.set_resource(name, MaterialResource::Texture(texture))
+    }
+
+    pub fn set_sampler(&mut self, name: &str, sampler: Arc<wgpu::Sampler>) -> Result<(), ParamError> {
+        self.set_resource(name, MaterialResource::Sampler(sampler))
+    }
+
+    pub fn set_buffer(&mut self, name: &str, buffer: Arc<wgpu::Buffer>) -> Result<(), ParamError> {
+        self.set_resource(name, MaterialResource::Buffer(buffer))
+    }
+
+    /// binds the view of `name` to a loaded texture, it follows when the file is reloaded
+    pub fn set_texture_asset(&mut self, name: &str, texture: Handle<texture::Texture>) -> Result<(), ParamError> {
+        self.set_resource(name, MaterialResource::TextureAsset(texture))
+    }
+
+    /// uploads changed uniforms and rebuilds the bind group if a resource was replaced or a texture was reloaded
+    pub(crate) fn update_bindings(&mut self, queue: &wgpu::Queue) {
+        upload_uniforms(&mut self.bindings, queue);
+        if refresh_assets(&mut self.bindings) {
+            self.bind_group_dirty = true;
+        }
+
+        if self.bind_group_dirty {
+            self.bind_groups = Arc::new(create_bind_group(
+                &self.device,
+                &self.bind_group_layout.layout,
+                &self.bindings,
+            ));
+            self.bind_group_dirty = false;
+        }
+    }
+}
+
+/// applies the parameter changes of every material, runs before the render graph
+pub(crate) fn update_material_params(world: &mut legion::World, renderer: &Renderer) {
+    for material in <&mut Material>::query().iter_mut(world) {
+        material.update_mesh_asset();
+        material.update_bindings(&renderer.queue);
+    }
+    for material in <&mut ComputeMaterial>::query().iter_mut(world) {
+        material.update_bindings(&renderer.queue);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(fields: &[(&str, ParamValue)]) -> UniformFields {
+        UniformFields::new(
+            fields
+                .iter()
+                .map(|(name, value)| (name.to_string(), *value))
+                .collect(),
+        )
+    }
+
+    /// the member offsets and the size of `struct Params` as naga lays it out
+    fn wgsl_layout(members: &str) -> (Vec<u32>, u32) {
+        let source = format!(
+            "struct Params {{ {} }}\n@group(0) @binding(0) var<uniform> params: Params;",
+            members
+        );
+        let module = naga::front::wgsl::parse_str(&source).unwrap();
+        let (_, ty) = module
+            .types
+            .iter()
+            .find(|(_, ty)| ty.name.as_deref() == Some("Params"))
+            .unwrap();
+        match &ty.inner {
+            naga::TypeInner::Struct { members, span } => {
+                (members.iter().map(|x| x.offset).collect(), *span)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn offsets(block: &UniformFields) -> Vec<u32> {
+        block.fields.iter().map(|(_, _, offset)| *offset as u32).collect()
+    }
+
+    #[test]
+    fn scalars_fill_the_end_of_a_vec3() {
+        let block = fields(&[
+            ("color", ParamValue::Vec3([1.0, 0.5, 0.25])),
+            ("intensity", ParamValue::F32(2.0)),
+            ("uv", ParamValue::Vec2([0.0; 2])),
+            ("normal", ParamValue::Vec3([0.0; 3])),
+        ]);
+        let (expected, size) =
+            wgsl_layout("color: vec3<f32>, intensity: f32, uv: vec2<f32>, normal: vec3<f32>");
+
+        assert_eq!(offsets(&block), expected);
+        assert_eq!(block.data.len() as u32, size);
+        assert_eq!(&block.data[12..16], bytemuck::bytes_of(&2.0f32));
+    }
+
+    #[test]
+    fn matrices_start_on_16_bytes() {
+        let mut matrix = [[0.0; 4]; 4];
+        matrix[3] = [1.0, 2.0, 3.0, 1.0];
+        let block = fields(&[
+            ("time", ParamValue::F32(0.0)),
+            ("model", ParamValue::Mat4(matrix)),
+            ("frame", ParamValue::U32(7)),
+        ]);
+        let (expected, size) = wgsl_layout("time: f32, model: mat4x4<f32>, frame: u32");
+
+        assert_eq!(offsets(&block), expected);
+        assert_eq!(block.data.len() as u32, size);
+        assert_eq!(&block.data[64..80], bytemuck::bytes_of(&matrix[3]));
+    }
+
+    #[test]
+    fn blocks_are_padded_like_an_element_of_a_uniform_array() {
+        // uniform arrays need a stride that is a multiple of 16, so the block can be one of their elements
+        for (block, members) in [
+            (fields(&[("a", ParamValue::F32(0.0))]), "a: f32"),
+            (
+                fields(&[("a", ParamValue::Vec4([0.0; 4])), ("b", ParamValue::I32(0))]),
+                "a: vec4<f32>, b: i32",
+            ),
+        ] {
+            let (_, size) = wgsl_layout(members);
+            assert_eq!(block.data.len() as u32, size.next_multiple_of(16));
+        }
+    }
+
+    #[test]
+    fn set_writes_the_bytes_of_the_field() {
+        let mut block = fields(&[
+            ("color", ParamValue::Vec3([0.0; 3])),
+            ("intensity", ParamValue::F32(1.0)),
+        ]);
+
+        assert_eq!(block.set("intensity", ParamValue::F32(4.0)), Some(Ok(())));
+        assert_eq!(block.get("intensity"), Some(ParamValue::F32(4.0)));
+        assert_eq!(&block.data[12..16], bytemuck::bytes_of(&4.0f32));
+    }
+
+    #[test]
+    fn set_refuses_other_types_and_unknown_fields() {
+        let mut block = fields(&[("intensity", ParamValue::F32(1.0))]);
+        let data = block.data.clone();
+
+        assert_eq!(
+            block.set("intensity", ParamValue::U32(4)),
+            Some(Err(ParamError::WrongType {
+                name: "intensity".to_string(),
+                expected: "F32(1.0)".to_string(),
+            }))
+        );
+        assert_eq!(block.set("color", ParamValue::F32(4.0)), None);
+        assert_eq!(block.get("intensity"), Some(ParamValue::F32(1.0)));
+        assert_eq!(block.data, data);
+    }
+}
@@ -1,38 +1,100 @@
+use std::path::PathBuf;
 use std::sync::Arc;
 use super::instaincing::*;
+use crate::material_descriptor::MaterialDescriptor;
+use crate::material_params::{create_bind_group, layout_entries, MaterialBinding, MaterialResource};
+use crate::pipeline_cache::{PipelineKey, SharedLayout, SharedShader};
+use crate::reflection::{self, ShaderBinding};
+use crate::shader_assets::{self, ShaderError};
+use crate::shader_preprocessor::PreprocessedShader;
+use application::log::{error, warn};
 use wgpu::util::DeviceExt;
 
 pub struct Material {
-    pub pipeline: wgpu::RenderPipeline,
+    /// shared with every material that has the same shader, bindings and pipeline state
+    pub pipelines: Arc<MaterialPipelines>,
+    /// rebuilt before the next frame when a resource is replaced with `Material::set_resource`
     pub bind_groups: Arc<wgpu::BindGroup>,
+    pub(crate) bindings: Vec<MaterialBinding>,
+    pub(crate) bind_group_dirty: bool,
 
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
-    pub instance_buffer: wgpu::Buffer,
 
     pub(crate) vertecies : Vec<Vertex>,
     pub(crate) indecies : Vec<u16>,
 
     pub instances : Vec<ModelMatrix>,
 
-    device : Arc<wgpu::Device>,
-    pub(crate) uses_depth_buffer : bool,
+    /// local bounds of the mesh, used for frustum culling
+    pub bounds : Option<crate::Aabb>,
+    /// turn this off for shaders that ignore the instance transform, like fullscreen quads
+    pub frustum_culling : bool,
+    /// only cameras sharing one of these layers draw the material
+    pub layers : crate::RenderLayers,
+
+    pub(crate) device : Arc<wgpu::Device>,
+    /// the pipeline state the material was built with
+    pub(crate) descriptor : MaterialDescriptor,
+
+    /// kept to rebuild the pipelines when the sample count of the renderer changes
+    shader : SharedShader,
+    pub(crate) bind_group_layout : SharedLayout,
+    /// the sample count the pipelines were built for
+    pub(crate) sample_count : u32,
+    /// the depth compare of the renderer the pipelines were built with
+    pub(crate) depth_compare : wgpu::CompareFunction,
+    /// `pipelines` for the cameras whose projection compares depth differently than the `Camera` resource
+    pub(crate) view_pipelines : Vec<(wgpu::CompareFunction, Arc<MaterialPipelines>)>,
+    /// set for materials made with `Material::from_file`, they are rebuilt when the file changes
+    pub(crate) shader_path : Option<PathBuf>,
+    /// the shader file and every file it imports, with the version the pipelines were built from
+    pub(crate) shader_files : Vec<(assets::Handle<shader_assets::WgslFile>, u32)>,
+    /// the mesh is uploaded again when the model is reloaded, see `Material::set_mesh_asset`
+    pub(crate) mesh_asset : Option<crate::ObjMeshAsset>,
 }
 
-pub struct PuddleBindGroupEntry<'a> {
+/// the bind group the resources of a material are bound to
+pub const MATERIAL_GROUP: u32 = 1;
+
+pub struct PuddleBindGroupEntry {
+    /// the name of the wgsl variable, `Material::set_resource` finds the binding by it too
+    pub name: String,
+    /// checked against the shader, which can't tell filterable textures and samplers apart
     pub ty: wgpu::BindingType,
+    /// only used for shaders that can't be reflected, the stages of wgsl come from its entry points
     pub visibility: wgpu::ShaderStages,
-    pub resource: wgpu::BindingResource<'a>,
+    pub resource: MaterialResource,
 }
 
 impl Material {
-    pub fn new(
+    pub(crate) fn from_preprocessed(
+        renderer: &mut crate::Renderer,
+        entries: Vec<PuddleBindGroupEntry>,
+        camera_bind_group: &CameraBindGroupLayout,
+        source: &PreprocessedShader,
+        descriptor: MaterialDescriptor,
+    ) -> Result<Self, ShaderError> {
+        let (module, info) = shader_assets::validate_preprocessed_with_info(source)?;
+        let reflected = reflection::reflect_bindings(&module, &info, MATERIAL_GROUP);
+
+        let device = renderer.device.clone();
+        let shader = renderer.pipeline_cache.shader(&device, &source.file, &source.source, module)?;
+        shader_assets::catch_wgpu_errors(&device, &source.file, || {
+            Self::with_module(renderer, entries, camera_bind_group, shader, &source.file, Some(reflected.as_slice()), descriptor)
+        })?
+    }
+
+    /// without `reflected` the entries are bound in order, with their own visibility
+    pub(crate) fn with_module(
         renderer: &mut crate::Renderer,
         entries: Vec<PuddleBindGroupEntry>,
         camera_bind_group: &CameraBindGroupLayout,
-        shader: wgpu::ShaderModuleDescriptor,
-        uses_depth_buffer : bool,
-    ) -> Self {
+        shader: SharedShader,
+        file: &str,
+        reflected: Option<&[ShaderBinding]>,
+        descriptor: MaterialDescriptor,
+    ) -> Result<Self, ShaderError> {
         let vertex_buffer = renderer
             .device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -49,71 +111,225 @@ impl Material {
                 contents: &[],
             });
 
-        let instance_buffer = renderer
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("vertex buffer"),
-                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::VERTEX,
-                contents: &[],
-            });
-
 
-        let layout_entries: Vec<wgpu::BindGroupLayoutEntry> = entries
-            .iter()
-            .enumerate()
-            .map(|(i, x)| wgpu::BindGroupLayoutEntry {
-                binding: i as u32,
-                visibility: x.visibility,
-                ty: x.ty,
-                count: None, // TODO what is this   looks interesting
-            })
-            .collect();
-
-        let entries: Vec<wgpu::BindGroupEntry> = entries
+        let mut bindings: Vec<MaterialBinding> = entries
             .into_iter()
             .enumerate()
-            .map(|(i, x)| wgpu::BindGroupEntry {
-                binding: i as u32,
-                resource: x.resource,
-            })
+            .map(|(i, x)| MaterialBinding::new(&renderer.device, i as u32, x))
             .collect();
 
-        let bindgroup_layout =
-            renderer
-                .device
-                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                    label: None,
-                    entries: &layout_entries,
-                });
+        if let Some(reflected) = reflected {
+            let given: Vec<_> = bindings.iter().map(MaterialBinding::given).collect();
+            let resolved = reflection::resolve_bindings(file, MATERIAL_GROUP, reflected, &given)?;
+            // the layout is what the shader declares, not what the entries guessed
+            for (binding, resolved) in bindings.iter_mut().zip(resolved) {
+                binding.resolve(resolved);
+            }
+        }
 
-        let bind_groups = renderer
-            .device
-            .create_bind_group(&wgpu::BindGroupDescriptor {
-                label: Some("bind group"),
-                layout: &bindgroup_layout,
-                entries: &entries,
-            });
+        let layout_entries = layout_entries(bindings.iter().map(|x| (x.binding, x.visibility, x.ty)));
+
+        // materials with the same bindings share the layout, and with it their pipelines
+        let bindgroup_layout = renderer
+            .pipeline_cache
+            .bind_group_layout(&renderer.device, &layout_entries);
+
+        let bind_groups = create_bind_group(&renderer.device, &bindgroup_layout.layout, &bindings);
 
-        let pipeline = load_pipeline(renderer, camera_bind_group, shader, &bindgroup_layout, uses_depth_buffer);
+        let pipelines = create_pipelines(renderer, camera_bind_group, &shader, &bindgroup_layout, &descriptor, renderer.depth_compare);
 
-        Self {
+        Ok(Self {
+            pipelines,
             bind_groups: bind_groups.into(),
+            bindings,
+            bind_group_dirty : false,
             vertex_buffer,
             index_buffer,
-            instance_buffer,
-            pipeline,
             device : renderer.device.clone(),
             vertecies : vec![],
             indecies : vec![],
             instances : vec![],
-            uses_depth_buffer,
+            bounds : None,
+            frustum_culling : true,
+            layers : crate::RenderLayers::DEFAULT,
+            descriptor,
+            shader,
+            bind_group_layout : bindgroup_layout,
+            sample_count : renderer.sample_count,
+            depth_compare : renderer.depth_compare,
+            view_pipelines : vec![],
+            shader_path : None,
+            shader_files : vec![],
+            mesh_asset : None,
+        })
+    }
+
+    /// reads the shader file again and rebuilds the pipelines, nothing changes if that fails
+    pub(crate) fn reload_shader(&mut self, renderer : &mut crate::Renderer, camera_bind_group : &CameraBindGroupLayout) -> Result<(), ShaderError> {
+        let path = match &self.shader_path {
+            Some(r) => r,
+            None => {
+                return Ok(());
+            }
+        };
+        let source = renderer.shader_preprocessor.process_file(path)?;
+        let (module, info) = shader_assets::validate_preprocessed_with_info(&source)?;
+
+        // the bindings may have moved, the resources have to stay the same
+        let reflected = reflection::reflect_bindings(&module, &info, MATERIAL_GROUP);
+        let given: Vec<_> = self.bindings.iter().map(MaterialBinding::given).collect();
+        let resolved = reflection::resolve_bindings(&source.file, MATERIAL_GROUP, &reflected, &given)?;
+        let moved = self
+            .bindings
+            .iter()
+            .zip(&resolved)
+            .any(|(x, resolved)| {
+                x.binding != resolved.binding || x.visibility != resolved.visibility || x.ty != resolved.ty
+            });
+
+        let layout = if moved {
+            let entries = layout_entries(resolved.iter().map(|x| (x.binding, x.visibility, x.ty)));
+            renderer.pipeline_cache.bind_group_layout(&renderer.device, &entries)
+        } else {
+            self.bind_group_layout.clone()
+        };
+
+        let shader = renderer.pipeline_cache.shader(&renderer.device, &source.file, &source.source, module)?;
+        let pipelines = shader_assets::catch_wgpu_errors(&renderer.device, &source.file, || {
+            create_pipelines(
+                &*renderer,
+                camera_bind_group,
+                &shader,
+                &layout,
+                &self.descriptor,
+                renderer.depth_compare,
+            )
+        })?;
+
+        if moved {
+            for (binding, resolved) in self.bindings.iter_mut().zip(resolved) {
+                binding.resolve(resolved);
+            }
+            self.bind_group_layout = layout;
+            self.bind_groups = Arc::new(create_bind_group(&renderer.device, &self.bind_group_layout.layout, &self.bindings));
+        }
+
+        // imports might have changed
+        self.shader_files = shader_assets::watch_files(renderer, &source);
+        self.shader = shader;
+        self.pipelines = pipelines;
+        // built again by the main pass for the cameras that need them
+        self.view_pipelines.clear();
+        self.sample_count = renderer.sample_count;
+        self.depth_compare = renderer.depth_compare;
+        Ok(())
+    }
+
+    /// the sample count or the depth compare of the renderer changed since the pipelines were built.
+    /// materials with a depth compare of their own keep it
+    pub(crate) fn pipelines_outdated(&self, renderer : &crate::Renderer) -> bool {
+        self.sample_count != renderer.sample_count
+            || (self.descriptor.depth_compare.is_none() && self.depth_compare != renderer.depth_compare)
+    }
+
+    /// recreates the pipelines with the current sample count and depth compare of the renderer
+    pub(crate) fn rebuild_pipelines(&mut self, renderer : &crate::Renderer, camera_bind_group : &CameraBindGroupLayout) {
+        let pipelines = create_pipelines(
+            renderer,
+            camera_bind_group,
+            &self.shader,
+            &self.bind_group_layout,
+            &self.descriptor,
+            renderer.depth_compare,
+        );
+
+        self.pipelines = pipelines;
+        self.view_pipelines.clear();
+        self.sample_count = renderer.sample_count;
+        self.depth_compare = renderer.depth_compare;
+    }
+
+    /// builds the pipelines for the depth compares of this frame's cameras that `pipelines` wasn't built with,
+    /// and drops the ones no camera uses anymore
+    pub(crate) fn prepare_view_pipelines(
+        &mut self,
+        renderer : &crate::Renderer,
+        camera_bind_group : &CameraBindGroupLayout,
+        depth_compares : &[wgpu::CompareFunction],
+    ) {
+        if self.descriptor.depth_compare.is_some() {
+            return;
         }
+
+        let main = self.depth_compare;
+        self.view_pipelines.retain(|(x, _)| *x != main && depth_compares.contains(x));
+
+        for depth_compare in depth_compares {
+            if *depth_compare == main || self.view_pipelines.iter().any(|(x, _)| x == depth_compare) {
+                continue;
+            }
+            let pipelines = create_pipelines(
+                renderer,
+                camera_bind_group,
+                &self.shader,
+                &self.bind_group_layout,
+                &self.descriptor,
+                *depth_compare,
+            );
+            self.view_pipelines.push((*depth_compare, pipelines));
+        }
+    }
+
+    /// the pipelines a camera with `depth_compare` draws the material with
+    pub(crate) fn pipelines_for(&self, depth_compare : wgpu::CompareFunction) -> &MaterialPipelines {
+        self.view_pipelines
+            .iter()
+            .find(|(x, _)| *x == depth_compare)
+            .map_or(&*self.pipelines, |(_, pipelines)| &**pipelines)
+    }
+
+    pub fn descriptor(&self) -> &MaterialDescriptor {
+        &self.descriptor
     }
 
     pub fn add_mesh(&mut self, mut vertecies : Vec<Vertex>, mut indecies : Vec<u16>, position : ModelMatrix) {
         self.vertecies.extend(vertecies.drain(..));
         self.indecies.extend(indecies.drain(..));
         self.instances.push(position);
+        self.upload_mesh();
+    }
+
+    /// replaces the mesh every instance draws
+    pub fn set_mesh(&mut self, vertecies : Vec<Vertex>, indecies : Vec<u16>) {
+        self.vertecies = vertecies;
+        self.indecies = indecies;
+        self.upload_mesh();
+    }
+
+    /// draws mesh `mesh` of a loaded obj model for every instance, nothing is drawn until it is loaded.
+    /// the mesh is uploaded again whenever the file of the model is reloaded
+    pub fn set_mesh_asset(&mut self, model : assets::Handle<crate::ObjModel>, mesh : usize) {
+        self.mesh_asset = Some(crate::ObjMeshAsset::new(model, mesh));
+        self.update_mesh_asset();
+    }
+
+    /// takes the mesh of the model if it was loaded or reloaded since the last frame
+    pub(crate) fn update_mesh_asset(&mut self) {
+        let changed = match self.mesh_asset.as_mut().and_then(|x| x.take_changed()) {
+            Some(r) => r,
+            None => {
+                return;
+            }
+        };
+
+        match changed {
+            Ok(mesh) => self.set_mesh(mesh.vertices, mesh.indices),
+            Err(e) => error!("{}", e),
+        }
+    }
+
+    fn upload_mesh(&mut self) {
+        self.bounds = crate::Aabb::from_points(self.vertecies.iter().map(|x| x.position));
 
         let vertex_buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Vertex Buffer"),
@@ -127,17 +343,7 @@ impl Material {
             usage: wgpu::BufferUsages::INDEX,
         });
 
-        let raw_data : Vec<_> = self.instances.iter().map(|x| x.to_raw()).collect();
-
-        let instance_buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(&raw_data),
-            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::VERTEX,
-        });
-
-
         self.vertex_buffer = vertex_buffer;
-        self.instance_buffer = instance_buffer;
         self.index_buffer = index_buffer;
     }
 }
@@ -153,74 +359,227 @@ impl Material {
 
 use super::*;
 
+pub struct MaterialPipelines {
+    pub color: wgpu::RenderPipeline,
+    /// vertex only, for the depth prepass
+    pub depth: Option<wgpu::RenderPipeline>,
+    /// vertex only and always `LessEqual`, for the shadow maps of the sun.
+    /// `None` for materials whose vertex shader uses bind groups the shadow pass doesn't bind
+    pub shadow: Option<wgpu::RenderPipeline>,
+    /// `shadow` with a slope scaled depth bias, for the perspective shadow maps of spot lights
+    pub spot_shadow: Option<wgpu::RenderPipeline>,
+}
+
+/// builds pipelines for a material outside of the cache
 pub fn load_pipeline(
     renderer: &mut crate::Renderer,
     camera_bind_group: &CameraBindGroupLayout,
     shader: wgpu::ShaderModuleDescriptor,
     bind_group : &wgpu::BindGroupLayout,
-    uses_depth_buffer : bool,
-) -> wgpu::RenderPipeline {
+    descriptor : &MaterialDescriptor,
+) -> MaterialPipelines {
     let shader = renderer.device.create_shader_module(shader);
+    build_pipelines(renderer, camera_bind_group, &shader, bind_group, descriptor, renderer.depth_compare)
+}
 
-    let surface = &renderer.surface;
-    let swapchain_capabilities = surface.get_capabilities(&renderer.adapter);
-    let swapchain_format = swapchain_capabilities.formats[0];
+/// the pipelines of the cache, only built if no other material has the same key.
+/// `depth_compare` is the one of the camera, materials with a depth compare of their own keep it
+fn create_pipelines(
+    renderer: &crate::Renderer,
+    camera_bind_group: &CameraBindGroupLayout,
+    shader: &SharedShader,
+    bind_group : &SharedLayout,
+    descriptor : &MaterialDescriptor,
+    depth_compare : wgpu::CompareFunction,
+) -> Arc<MaterialPipelines> {
+    let key = PipelineKey::new(renderer, shader, bind_group, descriptor, depth_compare);
+    renderer.pipeline_cache.pipelines(key, || {
+        build_pipelines(renderer, camera_bind_group, &shader.module, &bind_group.layout, descriptor, depth_compare)
+    })
+}
+
+fn build_pipelines(
+    renderer: &crate::Renderer,
+    camera_bind_group: &CameraBindGroupLayout,
+    shader: &wgpu::ShaderModule,
+    bind_group : &wgpu::BindGroupLayout,
+    descriptor : &MaterialDescriptor,
+    depth_compare : wgpu::CompareFunction,
+) -> MaterialPipelines {
+    let label = descriptor.label.as_deref().unwrap_or("Render Pipeline");
+    let depth_compare = descriptor.depth_compare.unwrap_or(depth_compare);
 
     let pipeline_layout = renderer
         .device
         .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("pipeline_layout"),
-            bind_group_layouts: &[&camera_bind_group.0, &bind_group],
+            bind_group_layouts: &[
+                &camera_bind_group.0,
+                &bind_group,
+                &renderer.depth.bind_group_layout,
+                &renderer.lights.bind_group_layout,
+            ],
             push_constant_ranges: &[],
         });
 
     let render_pipeline = renderer
         .device
         .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
+            label: Some(label),
             layout: Some(&pipeline_layout),
 
             vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
+                module: shader,
+                entry_point: descriptor.vertex_entry.as_str(),
                 buffers: &[Vertex::desc(), InstanceRaw::desc()],
             },
 
             fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
+                module: shader,
+                entry_point: descriptor.fragment_entry.as_str(),
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: swapchain_format.into(),
-
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent {
-                            src_factor: wgpu::BlendFactor::SrcAlpha,
-                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                            operation: wgpu::BlendOperation::Add,
-                        },
-                        alpha: wgpu::BlendComponent::OVER,
-                    }),
-
+                    format: descriptor.color_format(),
+                    blend: descriptor.blend.state(),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
             }),
 
-            primitive: wgpu::PrimitiveState {
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                ..Default::default()
-            },
-            depth_stencil: if uses_depth_buffer {Some(wgpu::DepthStencilState {
-                format: texture::Texture::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
+            primitive: descriptor.primitive(),
+            // the main pass always has a depth buffer, materials without the depth test just leave it alone
+            depth_stencil: if descriptor.depth_test {Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_STENCIL_FORMAT,
+                depth_write_enabled: descriptor.depth_write,
+                depth_compare,
+                stencil: descriptor.stencil.clone(),
+                bias: wgpu::DepthBiasState::default(),
+            })} else {Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_STENCIL_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
                 stencil: wgpu::StencilState::default(),
                 bias: wgpu::DepthBiasState::default(),
-            })} else {None},
+            })},
 
-            multisample: wgpu::MultisampleState::default(),
+            multisample: wgpu::MultisampleState {
+                count: renderer.sample_count,
+                ..Default::default()
+            },
             multiview: None,
         });
 
-    render_pipeline
+    if !descriptor.writes_depth() {
+        return MaterialPipelines {
+            color: render_pipeline,
+            depth: None,
+            shadow: None,
+            spot_shadow: None,
+        };
+    }
+
+    // shadow casters only see the light camera and their own material,
+    // the lights group holds the shadow maps they render into
+    let shadow_layout = renderer
+        .device
+        .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("shadow_pipeline_layout"),
+            bind_group_layouts: &[&camera_bind_group.0, bind_group],
+            push_constant_ranges: &[],
+        });
+
+    let shadow_pipeline = |bias: wgpu::DepthBiasState, label: &str| {
+        depth_only_pipeline(
+            renderer,
+            shader,
+            descriptor,
+            &DepthPipelineDescriptor {
+                label,
+                layout: &shadow_layout,
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                bias,
+                // the shadow maps are never multisampled
+                sample_count: 1,
+            },
+        )
+    };
+    // vertex shaders that read group 2 or 3 don't fit the layout
+    let shadows = shader_assets::catch_wgpu_errors(&renderer.device, label, || {
+        (
+            shadow_pipeline(wgpu::DepthBiasState::default(), "Shadow Pipeline"),
+            shadow_pipeline(crate::shadows::SPOT_SHADOW_BIAS, "Spot Shadow Pipeline"),
+        )
+    });
+    let (shadow, spot_shadow) = match shadows {
+        Ok((shadow, spot_shadow)) => (Some(shadow), Some(spot_shadow)),
+        Err(e) => {
+            warn!(
+                "`{}` casts no shadows, its vertex shader can only use bind groups 0 and 1 for that : {}",
+                label, e
+            );
+            (None, None)
+        }
+    };
+
+    MaterialPipelines {
+        color: render_pipeline,
+        depth: Some(depth_only_pipeline(
+            renderer,
+            shader,
+            descriptor,
+            &DepthPipelineDescriptor {
+                label: "Depth Prepass Pipeline",
+                layout: &pipeline_layout,
+                format: texture::Texture::DEPTH_STENCIL_FORMAT,
+                depth_compare,
+                bias: wgpu::DepthBiasState::default(),
+                sample_count: renderer.sample_count,
+            },
+        )),
+        shadow,
+        spot_shadow,
+    }
+}
+
+/// the state of a depth only pipeline that doesn't come from the `MaterialDescriptor`
+struct DepthPipelineDescriptor<'a> {
+    label: &'a str,
+    layout: &'a wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    depth_compare: wgpu::CompareFunction,
+    bias: wgpu::DepthBiasState,
+    sample_count: u32,
+}
+
+fn depth_only_pipeline(
+    renderer: &crate::Renderer,
+    shader: &wgpu::ShaderModule,
+    descriptor: &MaterialDescriptor,
+    depth: &DepthPipelineDescriptor,
+) -> wgpu::RenderPipeline {
+    renderer.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(depth.label),
+        layout: Some(depth.layout),
+
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: descriptor.vertex_entry.as_str(),
+            buffers: &[Vertex::desc(), InstanceRaw::desc()],
+        },
+        fragment: None,
+
+        primitive: descriptor.primitive(),
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: depth.format,
+            depth_write_enabled: true,
+            depth_compare: depth.depth_compare,
+            stencil: wgpu::StencilState::default(),
+            bias: depth.bias,
+        }),
+
+        multisample: wgpu::MultisampleState {
+            count: depth.sample_count,
+            ..Default::default()
+        },
+        multiview: None,
+    })
 }
@@ -0,0 +1,119 @@
+use std::fmt;
+
+use crate::Renderer;
+
+/// the sample counts `Renderer::set_sample_count` accepts, if the adapter supports them
+pub const SAMPLE_COUNTS: [u32; 4] = [1, 2, 4, 8];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleCountError {
+    /// not one of `SAMPLE_COUNTS`
+    Invalid(u32),
+    /// the adapter can't multisample or resolve the hdr or depth format with this count
+    Unsupported(u32),
+}
+
+impl fmt::Display for SampleCountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SampleCountError::Invalid(count) => {
+                write!(f, "{} is not a valid sample count, use one of {:?}", count, SAMPLE_COUNTS)
+            }
+            SampleCountError::Unsupported(count) => {
+                write!(f, "the adapter doesn't support a sample count of {}", count)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SampleCountError {}
+
+/// the counts of `SAMPLE_COUNTS` the hdr color and the depth format both support,
+/// the color format also has to be resolvable. 1 is always supported
+pub fn supported_sample_counts(
+    color: &wgpu::TextureFormatFeatures,
+    depth: &wgpu::TextureFormatFeatures,
+) -> Vec<u32> {
+    SAMPLE_COUNTS
+        .iter()
+        .copied()
+        .filter(|count| {
+            *count == 1
+                || (color.flags.sample_count_supported(*count)
+                    && color
+                        .flags
+                        .contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE)
+                    && depth.flags.sample_count_supported(*count))
+        })
+        .collect()
+}
+
+/// what `Renderer::set_sample_count` checks before it changes anything
+pub fn check_sample_count(sample_count: u32, supported: &[u32]) -> Result<(), SampleCountError> {
+    if !SAMPLE_COUNTS.contains(&sample_count) {
+        return Err(SampleCountError::Invalid(sample_count));
+    }
+    if !supported.contains(&sample_count) {
+        return Err(SampleCountError::Unsupported(sample_count));
+    }
+    Ok(())
+}
+
+/// the multisampled color attachment materials draw into, resolved into the hdr target every pass.
+/// `None` without msaa
+pub(crate) fn create_msaa_color(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+    sample_count: u32,
+) -> Option<texture::Texture> {
+    if sample_count <= 1 {
+        return None;
+    }
+
+    Some(texture::Texture::create_render_target_multisampled(
+        device,
+        width,
+        height,
+        Renderer::HDR_FORMAT,
+        sample_count,
+        "msaa color",
+    ))
+}
+
+impl Renderer {
+    /// the samples per pixel of every material pipeline and camera attachment
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    pub fn supported_sample_counts(&self) -> Vec<u32> {
+        let color = self.adapter.get_texture_format_features(Self::HDR_FORMAT);
+        let depth = self
+            .adapter
+            .get_texture_format_features(texture::Texture::DEPTH_STENCIL_FORMAT);
+
+        supported_sample_counts(&color, &depth)
+    }
+
+    /// changes the msaa sample count, the attachments are recreated right away.
+    /// materials and camera views rebuild their pipelines and textures before they are drawn next
+    pub fn set_sample_count(&mut self, sample_count: u32) -> Result<(), SampleCountError> {
+        check_sample_count(sample_count, &self.supported_sample_counts())?;
+        if sample_count == self.sample_count {
+            return Ok(());
+        }
+
+        self.sample_count = sample_count;
+        self.resize_attachments();
+        Ok(())
+    }
+
+    /// recreates the depth buffer and msaa color in the size of the surface
+    pub(crate) fn resize_attachments(&mut self) {
+        let config = &self.surface_config;
+        self.depth.resize(&self.device, config, self.sample_count);
+        self.viewport_depth_clear = crate::depth::ViewportClear::new(&self.device, self.sample_count);
+        self.msaa_color = create_msaa_color(&self.device, config.width, config.height, self.sample_count);
+    }
+}
@@ -0,0 +1,386 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::material_descriptor::MaterialDescriptor;
+use crate::materials::MaterialPipelines;
+use crate::render_queue::RenderQueue;
+use crate::shader_assets::{self, ShaderError};
+use crate::Renderer;
+
+/// how often the shared objects of materials were reused, see `Renderer::pipeline_cache_stats`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PipelineCacheStats {
+    pub shader_hits: u64,
+    pub shader_misses: u64,
+    pub layout_hits: u64,
+    pub layout_misses: u64,
+    pub pipeline_hits: u64,
+    pub pipeline_misses: u64,
+    /// the objects that are still used by a material
+    pub shaders: usize,
+    pub layouts: usize,
+    pub pipelines: usize,
+}
+
+/// a shader module shared by every material with the same source
+#[derive(Clone)]
+pub(crate) struct SharedShader {
+    pub id: u64,
+    pub module: Arc<wgpu::ShaderModule>,
+}
+
+/// a bind group layout shared by every material with the same entries
+#[derive(Clone)]
+pub(crate) struct SharedLayout {
+    pub id: u64,
+    pub layout: Arc<wgpu::BindGroupLayout>,
+}
+
+/// everything that makes two material pipelines different.
+/// the vertex layout is the same for every material, the camera, depth and light layouts belong to the renderer
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct PipelineKey {
+    shader: u64,
+    layout: u64,
+    /// without the fields that don't change the pipeline
+    state: MaterialDescriptor,
+    sample_count: u32,
+    depth_compare: wgpu::CompareFunction,
+}
+
+impl PipelineKey {
+    /// `depth_compare` is the one of the camera, materials with a depth compare of their own keep it
+    pub fn new(
+        renderer: &Renderer,
+        shader: &SharedShader,
+        layout: &SharedLayout,
+        descriptor: &MaterialDescriptor,
+        depth_compare: wgpu::CompareFunction,
+    ) -> Self {
+        Self {
+            shader: shader.id,
+            layout: layout.id,
+            state: MaterialDescriptor {
+                label: None,
+                stencil_reference: 0,
+                queue: RenderQueue::default(),
+                queue_priority: 0,
+                ..descriptor.clone()
+            },
+            sample_count: renderer.sample_count,
+            depth_compare: descriptor.depth_compare.unwrap_or(depth_compare),
+        }
+    }
+}
+
+/// the entries are weak, objects are freed with the last material using them.
+/// ids are never reused, so a key can't point at a different object later
+struct Cached<T> {
+    id: u64,
+    value: Weak<T>,
+}
+
+struct CacheState<K, T> {
+    next_id: u64,
+    entries: HashMap<K, Cached<T>>,
+    hits: u64,
+    misses: u64,
+}
+
+impl<K: Hash + Eq, T> CacheState<K, T> {
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn get<Q>(&mut self, key: &Q) -> Option<(u64, Arc<T>)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let cached = self.entries.get(key)?;
+        let value = cached.value.upgrade()?;
+        let id = cached.id;
+        self.hits += 1;
+        Some((id, value))
+    }
+
+    fn insert(&mut self, key: K, value: T) -> (u64, Arc<T>) {
+        let id = self.next_id();
+        let value = Arc::new(value);
+        self.misses += 1;
+
+        // forgets the objects no material uses anymore
+        self.entries.retain(|_, x| x.value.strong_count() > 0);
+        self.entries.insert(
+            key,
+            Cached {
+                id,
+                value: Arc::downgrade(&value),
+            },
+        );
+        (id, value)
+    }
+}
+
+/// objects shared by key. they are created without holding the lock, so a material building
+/// its pipelines doesn't block the other threads. when two threads miss the same key at once
+/// both create the object and the first one inserted is kept
+struct WeakCache<K, T> {
+    state: Mutex<CacheState<K, T>>,
+}
+
+impl<K, T> Default for WeakCache<K, T> {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(CacheState {
+                next_id: 0,
+                entries: HashMap::new(),
+                hits: 0,
+                misses: 0,
+            }),
+        }
+    }
+}
+
+impl<K: Hash + Eq, T> WeakCache<K, T> {
+    fn lock(&self) -> std::sync::MutexGuard<'_, CacheState<K, T>> {
+        // a panic while holding the lock doesn't leave the map in a broken state
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// the object for `key` and its id, `create` only runs on a miss.
+    /// a failed `create` isn't counted
+    fn get_or_try_create<Q, E>(
+        &self,
+        key: &Q,
+        create: impl FnOnce() -> Result<T, E>,
+    ) -> Result<(u64, Arc<T>), E>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        if let Some(found) = self.lock().get(key) {
+            return Ok(found);
+        }
+
+        let value = create()?;
+
+        let mut state = self.lock();
+        if let Some(found) = state.get(key) {
+            return Ok(found);
+        }
+        Ok(state.insert(key.to_owned(), value))
+    }
+
+    /// an object that can't be compared with the others, it is never shared
+    fn unique(&self, value: T) -> (u64, Arc<T>) {
+        let mut state = self.lock();
+        state.misses += 1;
+        (state.next_id(), Arc::new(value))
+    }
+
+    /// the hits, the misses and how many objects are still used
+    fn stats(&self) -> (u64, u64, usize) {
+        let state = self.lock();
+        let alive = state
+            .entries
+            .values()
+            .filter(|x| x.value.strong_count() > 0)
+            .count();
+        (state.hits, state.misses, alive)
+    }
+}
+
+/// shares shader modules, bind group layouts and pipelines between materials
+#[derive(Default)]
+pub(crate) struct PipelineCache {
+    shaders: WeakCache<String, wgpu::ShaderModule>,
+    layouts: WeakCache<Vec<wgpu::BindGroupLayoutEntry>, wgpu::BindGroupLayout>,
+    pipelines: WeakCache<PipelineKey, MaterialPipelines>,
+}
+
+impl PipelineCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// a module for wgsl `source`, only created if no material uses the same source yet.
+    /// `module` is what validating `source` returned, so wgpu doesn't parse it again
+    pub fn shader(
+        &self,
+        device: &wgpu::Device,
+        file: &str,
+        source: &str,
+        module: naga::Module,
+    ) -> Result<SharedShader, ShaderError> {
+        let (id, module) = self.shaders.get_or_try_create(source, || {
+            shader_assets::create_shader_module(device, file, module)
+        })?;
+        Ok(SharedShader { id, module })
+    }
+
+    /// a module that isn't wgsl can't be compared, it is never shared
+    pub fn unique_shader(&self, module: wgpu::ShaderModule) -> SharedShader {
+        let (id, module) = self.shaders.unique(module);
+        SharedShader { id, module }
+    }
+
+    pub fn bind_group_layout(
+        &self,
+        device: &wgpu::Device,
+        entries: &[wgpu::BindGroupLayoutEntry],
+    ) -> SharedLayout {
+        let created = self.layouts.get_or_try_create(entries, || {
+            Ok::<_, Infallible>(
+                device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("material_bind_group_layout"),
+                    entries,
+                }),
+            )
+        });
+        let Ok((id, layout)) = created;
+        SharedLayout { id, layout }
+    }
+
+    /// the pipelines for `key`, `create` only runs on a miss
+    pub fn pipelines(
+        &self,
+        key: PipelineKey,
+        create: impl FnOnce() -> MaterialPipelines,
+    ) -> Arc<MaterialPipelines> {
+        let created = self
+            .pipelines
+            .get_or_try_create(&key, || Ok::<_, Infallible>(create()));
+        let Ok((_, pipelines)) = created;
+        pipelines
+    }
+
+    pub fn stats(&self) -> PipelineCacheStats {
+        let (shader_hits, shader_misses, shaders) = self.shaders.stats();
+        let (layout_hits, layout_misses, layouts) = self.layouts.stats();
+        let (pipeline_hits, pipeline_misses, pipelines) = self.pipelines.stats();
+
+        PipelineCacheStats {
+            shader_hits,
+            shader_misses,
+            layout_hits,
+            layout_misses,
+            pipeline_hits,
+            pipeline_misses,
+            shaders,
+            layouts,
+            pipelines,
+        }
+    }
+}
+
+impl Renderer {
+    /// how many shader modules, bind group layouts and pipelines materials share
+    pub fn pipeline_cache_stats(&self) -> PipelineCacheStats {
+        self.pipeline_cache.stats()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create(value: &str) -> impl FnOnce() -> Result<String, ()> + '_ {
+        move || Ok(value.to_string())
+    }
+
+    #[test]
+    fn shares_objects_with_the_same_key() {
+        let cache = WeakCache::<String, String>::default();
+
+        let (id, first) = cache.get_or_try_create("a", create("first")).unwrap();
+        let (same_id, same) = cache
+            .get_or_try_create("a", || -> Result<String, ()> { panic!("created twice") })
+            .unwrap();
+        let (other_id, _other) = cache.get_or_try_create("b", create("other")).unwrap();
+
+        assert_eq!(id, same_id);
+        assert!(Arc::ptr_eq(&first, &same));
+        assert_ne!(id, other_id);
+        assert_eq!(cache.stats(), (1, 2, 2));
+    }
+
+    #[test]
+    fn forgets_objects_nobody_uses() {
+        let cache = WeakCache::<String, String>::default();
+
+        let (id, first) = cache.get_or_try_create("a", create("first")).unwrap();
+        drop(first);
+        assert_eq!(cache.stats(), (0, 1, 0));
+
+        // created again with an id of its own
+        let (new_id, again) = cache.get_or_try_create("a", create("again")).unwrap();
+        assert_ne!(id, new_id);
+        assert_eq!(*again, "again");
+        assert_eq!(cache.stats(), (0, 2, 1));
+    }
+
+    #[test]
+    fn does_not_count_failures() {
+        let cache = WeakCache::<String, String>::default();
+
+        assert_eq!(
+            cache.get_or_try_create("a", || Err("broken")),
+            Err("broken")
+        );
+        assert_eq!(cache.stats(), (0, 0, 0));
+        let fixed = cache.get_or_try_create("a", create("fixed"));
+        assert!(fixed.is_ok());
+        assert_eq!(cache.stats(), (0, 1, 1));
+    }
+
+    #[test]
+    fn unique_objects_are_never_shared() {
+        let cache = WeakCache::<String, String>::default();
+
+        let (shared_id, _shared) = cache.get_or_try_create("a", create("a")).unwrap();
+        let (id, _unique) = cache.unique("a".to_string());
+        let (other_id, _other) = cache.unique("a".to_string());
+
+        assert_ne!(id, other_id);
+        assert_ne!(id, shared_id);
+        // counted, but not kept
+        assert_eq!(cache.stats(), (0, 3, 1));
+    }
+
+    #[test]
+    fn creates_without_holding_the_lock() {
+        let cache = WeakCache::<String, String>::default();
+
+        let (_, value) = cache
+            .get_or_try_create("a", || {
+                assert!(cache.state.try_lock().is_ok());
+                // building a pipeline can need other cached objects
+                cache.get_or_try_create("b", create("b"))?;
+                Ok::<_, ()>("a".to_string())
+            })
+            .unwrap();
+
+        assert_eq!(*value, "a");
+    }
+
+    #[test]
+    fn keeps_the_first_object_when_two_misses_race() {
+        let cache = WeakCache::<String, String>::default();
+        let mut winner = None;
+
+        let (_, value) = cache
+            .get_or_try_create("a", || {
+                winner = Some(cache.get_or_try_create("a", create("winner"))?);
+                Ok::<_, ()>("loser".to_string())
+            })
+            .unwrap();
+
+        assert_eq!(*value, "winner");
+        assert_eq!(cache.stats(), (1, 1, 1));
+    }
+}
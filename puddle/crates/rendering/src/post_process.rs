@@ -0,0 +1,744 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use application::log::error;
+
+use crate::render_graph::{NodeContext, NodeDesc, RenderNode, HDR_TARGET, SWAPCHAIN};
+use crate::{shader_assets, Renderer};
+
+/// the fullscreen vertex shader and the bindings every effect gets, put in front of custom effects
+pub const POST_COMMON_WGSL: &str = include_str!("shaders/post_common.wgsl");
+const POST_WGSL: &str = include_str!("shaders/post.wgsl");
+
+/// uniforms of every pass are this far apart in the uniform buffer
+const UNIFORM_STRIDE: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tonemapper {
+    /// clamps the colors
+    None,
+    Reinhard,
+    Aces,
+}
+
+/// an effect written in wgsl, `POST_COMMON_WGSL` is put in front of `source`.
+/// the source needs a `@fragment fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32>`
+/// that reads `input_texture` with `input_sampler`, `params` ends up in `effect.params`
+/// and the hdr scene is in `extra_texture`
+#[derive(Debug, Clone)]
+pub struct CustomEffect {
+    /// effects with the same name share their pipeline
+    pub name: String,
+    pub source: String,
+    pub params: [f32; 4],
+}
+
+impl CustomEffect {
+    pub fn new(name: impl Into<String>, source: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            source: source.into(),
+            params: [0.0; 4],
+        }
+    }
+
+    pub fn with_params(mut self, params: [f32; 4]) -> Self {
+        self.params = params;
+        self
+    }
+}
+
+#[derive(Clone)]
+pub enum PostEffect {
+    /// adds a blurred copy of everything brighter than `threshold`, put it before the tonemapping
+    Bloom {
+        threshold: f32,
+        intensity: f32,
+    },
+    /// scales the colors by `2^exposure` and maps them into the 0 to 1 range of the swapchain
+    Tonemap {
+        exposure: f32,
+        operator: Tonemapper,
+    },
+    /// looks the colors up in a lut strip of `size` tiles with `size * size` texels each,
+    /// red goes along a tile, green down and blue selects the tile
+    ColorGrading {
+        lut: Arc<texture::Texture>,
+        size: u32,
+        strength: f32,
+    },
+    Fxaa,
+    Vignette {
+        intensity: f32,
+        smoothness: f32,
+    },
+    Custom(CustomEffect),
+}
+
+/// the effects that map the hdr target to the swapchain, in order. a resource
+#[derive(Clone)]
+pub struct PostProcessStack {
+    pub effects: Vec<PostEffect>,
+}
+
+impl Default for PostProcessStack {
+    fn default() -> Self {
+        Self {
+            effects: vec![
+                PostEffect::Tonemap {
+                    exposure: 0.0,
+                    operator: Tonemapper::Aces,
+                },
+                PostEffect::Fxaa,
+            ],
+        }
+    }
+}
+
+impl PostProcessStack {
+    pub fn push(&mut self, effect: PostEffect) -> &mut Self {
+        self.effects.push(effect);
+        self
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct EffectUniform {
+    params: [f32; 4],
+    texel_size: [f32; 4],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Source {
+    Hdr,
+    Ping(usize),
+    Bloom(usize),
+    /// the lut of the effect with this index
+    Lut(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Output {
+    Ping(usize),
+    Bloom(usize),
+    Swapchain,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ShaderKey {
+    /// an entry point of `post.wgsl`
+    BuiltIn(&'static str),
+    Custom(String),
+}
+
+struct PostPass {
+    shader: ShaderKey,
+    format: wgpu::TextureFormat,
+    params: [f32; 4],
+    input: Source,
+    extra: Source,
+    output: Output,
+}
+
+/// two full sized textures the effects ping pong between and two half sized ones for bloom
+struct PostTargets {
+    size: (u32, u32),
+    ping: [texture::Texture; 2],
+    bloom: [texture::Texture; 2],
+}
+
+/// the bind groups of the last frame, they only change with the textures they point at
+#[derive(Default)]
+struct BindGroupCache {
+    hdr: Option<wgpu::Id<wgpu::TextureView>>,
+    generation: u64,
+    groups: Vec<Option<(Source, Source, wgpu::BindGroup)>>,
+}
+
+/// runs the `PostProcessStack` from the hdr target into the swapchain
+pub struct PostProcessNode {
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    built_in: wgpu::ShaderModule,
+    /// the source a module was made from, so changed effects get rebuilt.
+    /// effects that failed to build have no module and are skipped until their source changes
+    custom: HashMap<String, (String, Option<wgpu::ShaderModule>)>,
+    pipelines: HashMap<(ShaderKey, wgpu::TextureFormat), wgpu::RenderPipeline>,
+    sampler: wgpu::Sampler,
+
+    uniform_buffer: wgpu::Buffer,
+    uniform_capacity: usize,
+
+    targets: Option<PostTargets>,
+    passes: Vec<PostPass>,
+    luts: HashMap<usize, Arc<texture::Texture>>,
+
+    /// bumped when the targets, the luts or the uniform buffer are recreated
+    generation: u64,
+    bind_groups: Mutex<BindGroupCache>,
+}
+
+impl PostProcessNode {
+    pub const NAME: &'static str = "post_process";
+
+    pub fn new(device: &wgpu::Device) -> Self {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("post_process_bind_group_layout"),
+            entries: &[
+                texture_entry(0),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture_entry(3),
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("post_process_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let built_in = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("post process"),
+            source: wgpu::ShaderSource::Wgsl(format!("{}\n{}", POST_COMMON_WGSL, POST_WGSL).into()),
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let uniform_capacity = 16;
+        let uniform_buffer = Self::create_uniform_buffer(device, uniform_capacity);
+
+        Self {
+            bind_group_layout,
+            pipeline_layout,
+            built_in,
+            custom: HashMap::new(),
+            pipelines: HashMap::new(),
+            sampler,
+            uniform_buffer,
+            uniform_capacity,
+            targets: None,
+            passes: vec![],
+            luts: HashMap::new(),
+            generation: 0,
+            bind_groups: Mutex::new(BindGroupCache::default()),
+        }
+    }
+
+    fn create_uniform_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("post process uniform buffer"),
+            size: (capacity * UNIFORM_STRIDE) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// custom effects that failed to build are left out
+    fn is_usable(&self, effect: &PostEffect) -> bool {
+        match effect {
+            PostEffect::Custom(effect) => {
+                matches!(self.custom.get(&effect.name), Some((_, Some(_))))
+            }
+            _ => true,
+        }
+    }
+
+    /// turns the effects into fullscreen passes
+    fn build_passes(&mut self, effects: &[PostEffect], swapchain_format: wgpu::TextureFormat) {
+        let effects: Vec<&PostEffect> = effects.iter().filter(|x| self.is_usable(x)).collect();
+
+        self.passes.clear();
+        let old_luts = std::mem::take(&mut self.luts);
+
+        let hdr = Renderer::HDR_FORMAT;
+        let mut input = Source::Hdr;
+        let mut next_ping = 0;
+
+        let push = |passes: &mut Vec<PostPass>, shader, params, input, extra, output| {
+            let format = match output {
+                Output::Swapchain => swapchain_format,
+                _ => hdr,
+            };
+            passes.push(PostPass {
+                shader,
+                format,
+                params,
+                input,
+                extra,
+                output,
+            });
+        };
+
+        for (i, effect) in effects.iter().enumerate() {
+            let output = if i + 1 == effects.len() {
+                Output::Swapchain
+            } else {
+                Output::Ping(next_ping)
+            };
+
+            let (shader, params, extra) = match effect {
+                PostEffect::Bloom {
+                    threshold,
+                    intensity,
+                } => {
+                    let passes = &mut self.passes;
+                    let bright = ShaderKey::BuiltIn("fs_bright");
+                    let blur = ShaderKey::BuiltIn("fs_blur");
+
+                    push(
+                        passes,
+                        bright,
+                        [*threshold, 0.0, 0.0, 0.0],
+                        input,
+                        Source::Hdr,
+                        Output::Bloom(0),
+                    );
+                    push(
+                        passes,
+                        blur.clone(),
+                        [1.0, 0.0, 0.0, 0.0],
+                        Source::Bloom(0),
+                        Source::Hdr,
+                        Output::Bloom(1),
+                    );
+                    push(
+                        passes,
+                        blur,
+                        [0.0, 1.0, 0.0, 0.0],
+                        Source::Bloom(1),
+                        Source::Hdr,
+                        Output::Bloom(0),
+                    );
+
+                    (
+                        ShaderKey::BuiltIn("fs_bloom"),
+                        [*intensity, 0.0, 0.0, 0.0],
+                        Source::Bloom(0),
+                    )
+                }
+                PostEffect::Tonemap { exposure, operator } => {
+                    let operator = match operator {
+                        Tonemapper::None => 0.0,
+                        Tonemapper::Reinhard => 1.0,
+                        Tonemapper::Aces => 2.0,
+                    };
+                    (
+                        ShaderKey::BuiltIn("fs_tonemap"),
+                        [exposure.exp2(), operator, 0.0, 0.0],
+                        Source::Hdr,
+                    )
+                }
+                PostEffect::ColorGrading {
+                    lut,
+                    size,
+                    strength,
+                } => {
+                    self.luts.insert(i, lut.clone());
+                    (
+                        ShaderKey::BuiltIn("fs_color_grading"),
+                        [*size as f32, *strength, 0.0, 0.0],
+                        Source::Lut(i),
+                    )
+                }
+                PostEffect::Fxaa => (ShaderKey::BuiltIn("fs_fxaa"), [0.0; 4], Source::Hdr),
+                PostEffect::Vignette {
+                    intensity,
+                    smoothness,
+                } => (
+                    ShaderKey::BuiltIn("fs_vignette"),
+                    [*intensity, *smoothness, 0.0, 0.0],
+                    Source::Hdr,
+                ),
+                PostEffect::Custom(effect) => (
+                    ShaderKey::Custom(effect.name.clone()),
+                    effect.params,
+                    Source::Hdr,
+                ),
+            };
+
+            push(&mut self.passes, shader, params, input, extra, output);
+
+            if let Output::Ping(ping) = output {
+                input = Source::Ping(ping);
+                next_ping = 1 - ping;
+            }
+        }
+
+        let luts_changed = old_luts.len() != self.luts.len()
+            || self
+                .luts
+                .iter()
+                .any(|(i, lut)| !matches!(old_luts.get(i), Some(old) if Arc::ptr_eq(old, lut)));
+        if luts_changed {
+            self.generation += 1;
+        }
+
+        // without effects the hdr target is copied as it is
+        if self.passes.is_empty() {
+            push(
+                &mut self.passes,
+                ShaderKey::BuiltIn("fs_blit"),
+                [0.0; 4],
+                Source::Hdr,
+                Source::Hdr,
+                Output::Swapchain,
+            );
+        }
+    }
+
+    /// builds the modules of custom effects, a broken effect is logged and skipped
+    fn prepare_modules(&mut self, device: &wgpu::Device, effects: &[PostEffect]) {
+        for effect in effects {
+            let effect = match effect {
+                PostEffect::Custom(r) => r,
+                _ => continue,
+            };
+
+            if let Some((source, _)) = self.custom.get(&effect.name) {
+                if *source == effect.source {
+                    continue;
+                }
+            }
+
+            let source = format!("{}\n{}", POST_COMMON_WGSL, effect.source);
+            let module = shader_assets::validate_wgsl(&effect.name, &source).and_then(|_| {
+                shader_assets::catch_wgpu_errors(device, &effect.name, || {
+                    device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                        label: Some(effect.name.as_str()),
+                        source: wgpu::ShaderSource::Wgsl(source.as_str().into()),
+                    })
+                })
+            });
+            let module = match module {
+                Ok(r) => Some(r),
+                Err(e) => {
+                    error!("post effect {} is skipped : {}", effect.name, e);
+                    None
+                }
+            };
+
+            self.custom
+                .insert(effect.name.clone(), (effect.source.clone(), module));
+            self.pipelines
+                .retain(|(key, _), _| *key != ShaderKey::Custom(effect.name.clone()));
+        }
+    }
+
+    fn prepare_pipelines(&mut self, device: &wgpu::Device) {
+        let mut broken = vec![];
+        for pass in self.passes.iter() {
+            let key = (pass.shader.clone(), pass.format);
+            if self.pipelines.contains_key(&key) {
+                continue;
+            }
+
+            let (module, entry_point) = match &pass.shader {
+                ShaderKey::BuiltIn(entry_point) => (&self.built_in, *entry_point),
+                ShaderKey::Custom(name) => match self.custom.get(name) {
+                    Some((_, Some(module))) => (module, "fs_main"),
+                    _ => continue,
+                },
+            };
+
+            let label = match &pass.shader {
+                ShaderKey::BuiltIn(_) => "post.wgsl",
+                ShaderKey::Custom(name) => name.as_str(),
+            };
+            let pipeline = shader_assets::catch_wgpu_errors(device, label, || {
+                device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("post process pipeline"),
+                    layout: Some(&self.pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module,
+                        entry_point: "vs_main",
+                        buffers: &[],
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module,
+                        entry_point,
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: pass.format,
+                            blend: None,
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                    }),
+                    primitive: wgpu::PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState::default(),
+                    multiview: None,
+                })
+            });
+
+            match pipeline {
+                Ok(pipeline) => {
+                    self.pipelines.insert(key, pipeline);
+                }
+                Err(e) => {
+                    error!("post effect {} is skipped : {}", label, e);
+                    broken.push(pass.shader.clone());
+                }
+            }
+        }
+
+        // the pass stays for this frame, the next one leaves the effect out
+        for shader in broken {
+            if let ShaderKey::Custom(name) = shader {
+                if let Some((_, module)) = self.custom.get_mut(&name) {
+                    *module = None;
+                }
+            }
+        }
+    }
+
+    fn prepare_targets(&mut self, device: &wgpu::Device, size: (u32, u32)) {
+        if matches!(&self.targets, Some(targets) if targets.size == size) {
+            return;
+        }
+
+        self.generation += 1;
+
+        let (width, height) = size;
+        let (half_width, half_height) = ((width / 2).max(1), (height / 2).max(1));
+        let target = |width, height, label| {
+            texture::Texture::create_render_target(
+                device,
+                width,
+                height,
+                Renderer::HDR_FORMAT,
+                label,
+            )
+        };
+
+        self.targets = Some(PostTargets {
+            size,
+            ping: [
+                target(width, height, "post process ping"),
+                target(width, height, "post process pong"),
+            ],
+            bloom: [
+                target(half_width, half_height, "bloom ping"),
+                target(half_width, half_height, "bloom pong"),
+            ],
+        });
+    }
+
+    fn create_bind_group(
+        &self,
+        device: &wgpu::Device,
+        pass: usize,
+        input: &wgpu::TextureView,
+        extra: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("post_process_bind_group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(input),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &self.uniform_buffer,
+                        offset: (pass * UNIFORM_STRIDE) as wgpu::BufferAddress,
+                        size: wgpu::BufferSize::new(std::mem::size_of::<EffectUniform>() as u64),
+                    }),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(extra),
+                },
+            ],
+        })
+    }
+
+    fn source_size(&self, source: Source) -> (u32, u32) {
+        let targets = match &self.targets {
+            Some(r) => r,
+            None => return (1, 1),
+        };
+
+        match source {
+            Source::Bloom(i) => {
+                let size = targets.bloom[i].texture.size();
+                (size.width, size.height)
+            }
+            _ => targets.size,
+        }
+    }
+}
+
+impl RenderNode for PostProcessNode {
+    fn desc(&self) -> NodeDesc {
+        NodeDesc::new(Self::NAME)
+            .reads(HDR_TARGET)
+            .writes(SWAPCHAIN)
+    }
+
+    fn prepare(
+        &mut self,
+        world: &mut legion::World,
+        resources: &legion::Resources,
+        renderer: &mut Renderer,
+    ) {
+        let stack = resources.get::<PostProcessStack>();
+        let effects = match &stack {
+            Some(r) => r.effects.as_slice(),
+            None => &[],
+        };
+
+        let config = &renderer.surface_config;
+        self.prepare_modules(&renderer.device, effects);
+        self.build_passes(effects, config.format);
+        self.prepare_pipelines(&renderer.device);
+        self.prepare_targets(&renderer.device, (config.width, config.height));
+
+        if self.passes.len() > self.uniform_capacity {
+            self.uniform_capacity = self.passes.len().next_power_of_two();
+            self.uniform_buffer =
+                Self::create_uniform_buffer(&renderer.device, self.uniform_capacity);
+            self.generation += 1;
+        }
+
+        let mut uniforms = vec![0u8; self.passes.len() * UNIFORM_STRIDE];
+        for (i, pass) in self.passes.iter().enumerate() {
+            let (width, height) = self.source_size(pass.input);
+            let uniform = EffectUniform {
+                params: pass.params,
+                texel_size: [
+                    1.0 / width as f32,
+                    1.0 / height as f32,
+                    width as f32,
+                    height as f32,
+                ],
+            };
+
+            let offset = i * UNIFORM_STRIDE;
+            uniforms[offset..offset + std::mem::size_of::<EffectUniform>()]
+                .copy_from_slice(bytemuck::bytes_of(&uniform));
+        }
+        renderer
+            .queue
+            .write_buffer(&self.uniform_buffer, 0, &uniforms);
+    }
+
+    fn record(&self, encoder: &mut wgpu::CommandEncoder, ctx: &NodeContext) {
+        let (hdr, swapchain) = match (ctx.texture_view(HDR_TARGET), ctx.texture_view(SWAPCHAIN)) {
+            (Some(hdr), Some(swapchain)) => (hdr, swapchain),
+            _ => {
+                return;
+            }
+        };
+        let targets = match &self.targets {
+            Some(r) => r,
+            None => {
+                return;
+            }
+        };
+
+        let source_view = |source: Source| match source {
+            Source::Hdr => Some(hdr),
+            Source::Ping(i) => Some(&targets.ping[i].view),
+            Source::Bloom(i) => Some(&targets.bloom[i].view),
+            Source::Lut(i) => self.luts.get(&i).map(|x| &x.view),
+        };
+
+        let mut cache = self.bind_groups.lock().unwrap();
+        let hdr_id = hdr.global_id();
+        if cache.hdr != Some(hdr_id) || cache.generation != self.generation {
+            cache.hdr = Some(hdr_id);
+            cache.generation = self.generation;
+            cache.groups.clear();
+        }
+        cache.groups.resize_with(self.passes.len(), || None);
+
+        for (i, pass) in self.passes.iter().enumerate() {
+            let pipeline = match self.pipelines.get(&(pass.shader.clone(), pass.format)) {
+                Some(r) => r,
+                None => continue,
+            };
+            let (input, extra) = match (source_view(pass.input), source_view(pass.extra)) {
+                (Some(input), Some(extra)) => (input, extra),
+                _ => continue,
+            };
+            let output = match pass.output {
+                Output::Ping(i) => &targets.ping[i].view,
+                Output::Bloom(i) => &targets.bloom[i].view,
+                Output::Swapchain => swapchain,
+            };
+
+            let stale = !matches!(
+                &cache.groups[i],
+                Some((cached_input, cached_extra, _))
+                    if *cached_input == pass.input && *cached_extra == pass.extra
+            );
+            if stale {
+                let bind_group = self.create_bind_group(&ctx.renderer.device, i, input, extra);
+                cache.groups[i] = Some((pass.input, pass.extra, bind_group));
+            }
+            let bind_group = match &cache.groups[i] {
+                Some((_, _, r)) => r,
+                None => continue,
+            };
+
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("post process"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            rpass.set_pipeline(pipeline);
+            rpass.set_bind_group(0, bind_group, &[]);
+            rpass.draw(0..3, 0..1);
+        }
+    }
+}
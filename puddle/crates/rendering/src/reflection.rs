@@ -0,0 +1,463 @@
+use crate::shader_assets::ShaderError;
+
+/// a resource the shader declares with `@group(n) @binding(m)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShaderBinding {
+    /// the name of the wgsl variable
+    pub name: String,
+    pub binding: u32,
+    /// textures are assumed to be filterable and samplers filtering, the shader doesn't say
+    pub ty: wgpu::BindingType,
+    /// the stages of the entry points that use the binding, empty if none does
+    pub visibility: wgpu::ShaderStages,
+}
+
+/// a resource of a material for a shader binding, matched by the name of the wgsl variable
+#[derive(Debug, Clone, PartialEq)]
+pub struct GivenBinding<'a> {
+    pub name: &'a str,
+    pub ty: wgpu::BindingType,
+    /// used when the shader declares the binding without using it
+    pub visibility: wgpu::ShaderStages,
+    /// the size of the bound buffer, `None` for other resources
+    pub buffer_size: Option<u64>,
+}
+
+/// where the shader declares a given binding and its entry in the bind group layout
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedBinding {
+    pub binding: u32,
+    pub visibility: wgpu::ShaderStages,
+    pub ty: wgpu::BindingType,
+}
+
+/// the bindings of `group` in a validated module.
+/// binding arrays and acceleration structures are skipped, wgpu reports them when the pipeline is built
+pub fn reflect_bindings(
+    module: &naga::Module,
+    info: &naga::valid::ModuleInfo,
+    group: u32,
+) -> Vec<ShaderBinding> {
+    let mut bindings: Vec<ShaderBinding> = module
+        .global_variables
+        .iter()
+        .filter_map(|(handle, global)| {
+            let binding = match &global.binding {
+                Some(r) if r.group == group => r.binding,
+                _ => return None,
+            };
+            let ty = binding_type(module, global)?;
+
+            let mut visibility = wgpu::ShaderStages::NONE;
+            for (i, entry_point) in module.entry_points.iter().enumerate() {
+                if info.get_entry_point(i)[handle].is_empty() {
+                    continue;
+                }
+                visibility |= match entry_point.stage {
+                    naga::ShaderStage::Vertex => wgpu::ShaderStages::VERTEX,
+                    naga::ShaderStage::Fragment => wgpu::ShaderStages::FRAGMENT,
+                    naga::ShaderStage::Compute => wgpu::ShaderStages::COMPUTE,
+                };
+            }
+
+            Some(ShaderBinding {
+                name: global.name.clone().unwrap_or_default(),
+                binding,
+                ty,
+                visibility,
+            })
+        })
+        .collect();
+
+    bindings.sort_by_key(|x| x.binding);
+    bindings
+}
+
+fn binding_type(module: &naga::Module, global: &naga::GlobalVariable) -> Option<wgpu::BindingType> {
+    let inner = &module.types[global.ty].inner;
+
+    match global.space {
+        naga::AddressSpace::Uniform => Some(wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: wgpu::BufferSize::new(inner.size(module.to_ctx()) as u64),
+        }),
+        naga::AddressSpace::Storage { access } => Some(wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage {
+                read_only: !access.contains(naga::StorageAccess::STORE),
+            },
+            has_dynamic_offset: false,
+            // for runtime sized arrays this is one element
+            min_binding_size: wgpu::BufferSize::new(inner.size(module.to_ctx()) as u64),
+        }),
+        naga::AddressSpace::Handle => match *inner {
+            naga::TypeInner::Sampler { comparison } => {
+                Some(wgpu::BindingType::Sampler(if comparison {
+                    wgpu::SamplerBindingType::Comparison
+                } else {
+                    wgpu::SamplerBindingType::Filtering
+                }))
+            }
+            naga::TypeInner::Image {
+                dim,
+                arrayed,
+                class,
+            } => {
+                let view_dimension = match (dim, arrayed) {
+                    (naga::ImageDimension::D1, _) => wgpu::TextureViewDimension::D1,
+                    (naga::ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+                    (naga::ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+                    (naga::ImageDimension::D3, _) => wgpu::TextureViewDimension::D3,
+                    (naga::ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+                    (naga::ImageDimension::Cube, true) => wgpu::TextureViewDimension::CubeArray,
+                };
+
+                Some(match class {
+                    naga::ImageClass::Sampled { kind, multi } => wgpu::BindingType::Texture {
+                        sample_type: match kind {
+                            naga::ScalarKind::Sint => wgpu::TextureSampleType::Sint,
+                            naga::ScalarKind::Uint => wgpu::TextureSampleType::Uint,
+                            _ => wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        view_dimension,
+                        multisampled: multi,
+                    },
+                    naga::ImageClass::Depth { multi } => wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension,
+                        multisampled: multi,
+                    },
+                    naga::ImageClass::Storage { format, access } => {
+                        wgpu::BindingType::StorageTexture {
+                            access: match (
+                                access.contains(naga::StorageAccess::LOAD),
+                                access.contains(naga::StorageAccess::STORE),
+                            ) {
+                                (true, true) => wgpu::StorageTextureAccess::ReadWrite,
+                                (true, false) => wgpu::StorageTextureAccess::ReadOnly,
+                                _ => wgpu::StorageTextureAccess::WriteOnly,
+                            },
+                            format: storage_format(format),
+                            view_dimension,
+                        }
+                    }
+                })
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn storage_format(format: naga::StorageFormat) -> wgpu::TextureFormat {
+    use naga::StorageFormat as S;
+    use wgpu::TextureFormat as T;
+
+    match format {
+        S::R8Unorm => T::R8Unorm,
+        S::R8Snorm => T::R8Snorm,
+        S::R8Uint => T::R8Uint,
+        S::R8Sint => T::R8Sint,
+        S::R16Uint => T::R16Uint,
+        S::R16Sint => T::R16Sint,
+        S::R16Float => T::R16Float,
+        S::Rg8Unorm => T::Rg8Unorm,
+        S::Rg8Snorm => T::Rg8Snorm,
+        S::Rg8Uint => T::Rg8Uint,
+        S::Rg8Sint => T::Rg8Sint,
+        S::R32Uint => T::R32Uint,
+        S::R32Sint => T::R32Sint,
+        S::R32Float => T::R32Float,
+        S::Rg16Uint => T::Rg16Uint,
+        S::Rg16Sint => T::Rg16Sint,
+        S::Rg16Float => T::Rg16Float,
+        S::Rgba8Unorm => T::Rgba8Unorm,
+        S::Rgba8Snorm => T::Rgba8Snorm,
+        S::Rgba8Uint => T::Rgba8Uint,
+        S::Rgba8Sint => T::Rgba8Sint,
+        S::Bgra8Unorm => T::Bgra8Unorm,
+        S::Rgb10a2Uint => T::Rgb10a2Uint,
+        S::Rgb10a2Unorm => T::Rgb10a2Unorm,
+        S::Rg11b10Float => T::Rg11b10Float,
+        S::Rg32Uint => T::Rg32Uint,
+        S::Rg32Sint => T::Rg32Sint,
+        S::Rg32Float => T::Rg32Float,
+        S::Rgba16Uint => T::Rgba16Uint,
+        S::Rgba16Sint => T::Rgba16Sint,
+        S::Rgba16Float => T::Rgba16Float,
+        S::Rgba32Uint => T::Rgba32Uint,
+        S::Rgba32Sint => T::Rgba32Sint,
+        S::Rgba32Float => T::Rgba32Float,
+        S::R16Unorm => T::R16Unorm,
+        S::R16Snorm => T::R16Snorm,
+        S::Rg16Unorm => T::Rg16Unorm,
+        S::Rg16Snorm => T::Rg16Snorm,
+        S::Rgba16Unorm => T::Rgba16Unorm,
+        S::Rgba16Snorm => T::Rgba16Snorm,
+    }
+}
+
+/// how the type is written in wgsl, for the errors
+fn describe(ty: &wgpu::BindingType) -> String {
+    let dimension = |x: &wgpu::TextureViewDimension| match x {
+        wgpu::TextureViewDimension::D1 => "1d",
+        wgpu::TextureViewDimension::D2 => "2d",
+        wgpu::TextureViewDimension::D2Array => "2d_array",
+        wgpu::TextureViewDimension::Cube => "cube",
+        wgpu::TextureViewDimension::CubeArray => "cube_array",
+        wgpu::TextureViewDimension::D3 => "3d",
+    };
+
+    match ty {
+        wgpu::BindingType::Buffer { ty, .. } => match ty {
+            wgpu::BufferBindingType::Uniform => "var<uniform>".to_string(),
+            wgpu::BufferBindingType::Storage { read_only: true } => {
+                "var<storage, read>".to_string()
+            }
+            wgpu::BufferBindingType::Storage { read_only: false } => {
+                "var<storage, read_write>".to_string()
+            }
+        },
+        wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison) => {
+            "sampler_comparison".to_string()
+        }
+        wgpu::BindingType::Sampler(_) => "sampler".to_string(),
+        wgpu::BindingType::Texture {
+            sample_type,
+            view_dimension,
+            multisampled,
+        } => {
+            let multisampled = if *multisampled { "multisampled_" } else { "" };
+            match sample_type {
+                wgpu::TextureSampleType::Depth => {
+                    format!(
+                        "texture_depth_{}{}",
+                        multisampled,
+                        dimension(view_dimension)
+                    )
+                }
+                wgpu::TextureSampleType::Float { .. } => {
+                    format!("texture_{}{}<f32>", multisampled, dimension(view_dimension))
+                }
+                wgpu::TextureSampleType::Sint => {
+                    format!("texture_{}{}<i32>", multisampled, dimension(view_dimension))
+                }
+                wgpu::TextureSampleType::Uint => {
+                    format!("texture_{}{}<u32>", multisampled, dimension(view_dimension))
+                }
+            }
+        }
+        wgpu::BindingType::StorageTexture {
+            access,
+            format,
+            view_dimension,
+        } => format!(
+            "texture_storage_{}<{:?}, {:?}>",
+            dimension(view_dimension),
+            format,
+            access
+        ),
+        wgpu::BindingType::AccelerationStructure => "acceleration_structure".to_string(),
+    }
+}
+
+/// whether a layout entry of type `given` can be used for a shader binding of type `shader`
+fn compatible(shader: &wgpu::BindingType, given: &wgpu::BindingType) -> bool {
+    use wgpu::BindingType as B;
+
+    match (shader, given) {
+        (B::Buffer { ty: a, .. }, B::Buffer { ty: b, .. }) => a == b,
+        (B::Sampler(a), B::Sampler(b)) => {
+            (*a == wgpu::SamplerBindingType::Comparison)
+                == (*b == wgpu::SamplerBindingType::Comparison)
+        }
+        (
+            B::Texture {
+                sample_type: a,
+                view_dimension: a_dimension,
+                multisampled: a_multisampled,
+            },
+            B::Texture {
+                sample_type: b,
+                view_dimension: b_dimension,
+                multisampled: b_multisampled,
+            },
+        ) => {
+            let same_sample_type = matches!(
+                (a, b),
+                (
+                    wgpu::TextureSampleType::Float { .. },
+                    wgpu::TextureSampleType::Float { .. }
+                ) | (
+                    wgpu::TextureSampleType::Depth,
+                    wgpu::TextureSampleType::Depth
+                ) | (wgpu::TextureSampleType::Sint, wgpu::TextureSampleType::Sint)
+                    | (wgpu::TextureSampleType::Uint, wgpu::TextureSampleType::Uint)
+            );
+            same_sample_type && a_dimension == b_dimension && a_multisampled == b_multisampled
+        }
+        (
+            B::StorageTexture {
+                access: a,
+                format: a_format,
+                view_dimension: a_dimension,
+            },
+            B::StorageTexture {
+                access: b,
+                format: b_format,
+                view_dimension: b_dimension,
+            },
+        ) => a == b && a_format == b_format && a_dimension == b_dimension,
+        _ => false,
+    }
+}
+
+/// the type of the layout entry for a compatible binding, what the shader declares
+/// with the details wgsl can't say: the filtering of textures and samplers and dynamic offsets
+fn merge(shader: &wgpu::BindingType, given: &wgpu::BindingType) -> wgpu::BindingType {
+    use wgpu::BindingType as B;
+
+    match (*shader, *given) {
+        (
+            B::Buffer {
+                ty,
+                min_binding_size,
+                ..
+            },
+            B::Buffer {
+                has_dynamic_offset, ..
+            },
+        ) => B::Buffer {
+            ty,
+            has_dynamic_offset,
+            min_binding_size,
+        },
+        (
+            B::Texture {
+                sample_type: wgpu::TextureSampleType::Float { .. },
+                view_dimension,
+                multisampled,
+            },
+            B::Texture {
+                sample_type: sample_type @ wgpu::TextureSampleType::Float { .. },
+                ..
+            },
+        ) => B::Texture {
+            sample_type,
+            view_dimension,
+            multisampled,
+        },
+        (B::Sampler(_), B::Sampler(given)) => B::Sampler(given),
+        _ => *shader,
+    }
+}
+
+/// the binding index, stages and layout type of every given binding in the shader,
+/// matched by the name of the wgsl variable
+pub fn resolve_bindings(
+    file: &str,
+    group: u32,
+    shader: &[ShaderBinding],
+    bindings: &[GivenBinding],
+) -> Result<Vec<ResolvedBinding>, ShaderError> {
+    let error = |message: String| ShaderError::Binding {
+        file: file.to_string(),
+        message,
+    };
+
+    let mut resolved = Vec::with_capacity(bindings.len());
+    for (i, binding) in bindings.iter().enumerate() {
+        if bindings[..i].iter().any(|x| x.name == binding.name) {
+            return Err(error(format!(
+                "the resource {} is given twice",
+                binding.name
+            )));
+        }
+
+        let reflected = match shader.iter().find(|x| x.name == binding.name) {
+            Some(r) => r,
+            None => {
+                let declared: Vec<&str> = shader.iter().map(|x| x.name.as_str()).collect();
+                return Err(error(format!(
+                    "the shader has no variable {} in group {}, it declares [{}]",
+                    binding.name,
+                    group,
+                    declared.join(", ")
+                )));
+            }
+        };
+
+        if !compatible(&reflected.ty, &binding.ty) {
+            return Err(error(format!(
+                "{} is a {} in the shader but the material binds a {}",
+                binding.name,
+                describe(&reflected.ty),
+                describe(&binding.ty)
+            )));
+        }
+
+        let needed = match reflected.ty {
+            wgpu::BindingType::Buffer {
+                min_binding_size, ..
+            } => min_binding_size.map(|x| x.get()),
+            _ => None,
+        };
+        if let (Some(needed), Some(size)) = (needed, binding.buffer_size) {
+            if size < needed {
+                return Err(error(format!(
+                    "{} needs at least {} bytes but the buffer has {}, check the padding of the struct",
+                    binding.name, needed, size
+                )));
+            }
+        }
+
+        // storage arrays of a struct with another alignment in wgsl have another stride,
+        // a rust `[f32; 3]` is 12 bytes but a wgsl `vec3<f32>` 16
+        if let (
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { .. },
+                min_binding_size: Some(stride),
+                ..
+            },
+            wgpu::BindingType::Buffer {
+                min_binding_size: Some(given),
+                ..
+            },
+        ) = (&reflected.ty, &binding.ty)
+        {
+            if stride != given {
+                return Err(error(format!(
+                    "{} has {} bytes per element in the shader but {} in the material, check the alignment of the struct",
+                    binding.name, stride, given
+                )));
+            }
+        }
+
+        // a declared but unused binding still has to be in the layout
+        let visibility = if reflected.visibility.is_empty() {
+            binding.visibility
+        } else {
+            reflected.visibility
+        };
+        resolved.push(ResolvedBinding {
+            binding: reflected.binding,
+            visibility,
+            ty: merge(&reflected.ty, &binding.ty),
+        });
+    }
+
+    if let Some(missing) = shader
+        .iter()
+        .find(|x| !bindings.iter().any(|binding| binding.name == x.name))
+    {
+        return Err(error(format!(
+            "the shader declares {} : {} at @group({}) @binding({}) but the material has no resource for it",
+            missing.name,
+            describe(&missing.ty),
+            group,
+            missing.binding
+        )));
+    }
+
+    Ok(resolved)
+}
@@ -4,7 +4,6 @@ use std::sync::Arc;
 pub(crate) struct RenderContext {
     pub view: Arc<wgpu::TextureView>,
     pub frame: wgpu::SurfaceTexture,
-    pub depth_texture: texture::Texture,
     pub command_encoder: wgpu::CommandEncoder,
 }
 
@@ -31,7 +30,6 @@ impl RenderContext {
 
         Some(Self {
             view: Arc::new(view),
-            depth_texture: texture::Texture::cretate_depth_texture(&device, &surface_config),
             command_encoder,
             frame,
         })
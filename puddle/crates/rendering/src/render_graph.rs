@@ -0,0 +1,879 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt;
+use std::sync::Arc;
+
+use crate::Renderer;
+
+/// the color view of the current frame
+pub const SWAPCHAIN: &str = "swapchain";
+/// the persistent depth buffer of the swapchain
+pub const MAIN_DEPTH: &str = "depth";
+/// the `Renderer::HDR_FORMAT` target the main pass draws into, mapped to the swapchain by post processing
+pub const HDR_TARGET: &str = "hdr";
+/// the shadow maps of every light as a depth texture array
+pub const SHADOW_MAP: &str = "shadow_map";
+/// the multisampled color of the swapchain size, only there while msaa is on.
+/// resolve it into `HDR_TARGET` with `NodeDesc::resolved_color_attachment`
+pub const MSAA_COLOR: &str = "msaa_color";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SlotSize {
+    /// the size of the swapchain, follows resizes
+    Surface,
+    /// the swapchain size times a factor, at least one pixel
+    Scaled(f32),
+    Fixed(u32, u32),
+}
+
+impl SlotSize {
+    pub fn resolve(&self, surface_config: &wgpu::SurfaceConfiguration) -> (u32, u32) {
+        match *self {
+            SlotSize::Surface => (surface_config.width, surface_config.height),
+            SlotSize::Scaled(factor) => (
+                ((surface_config.width as f32 * factor) as u32).max(1),
+                ((surface_config.height as f32 * factor) as u32).max(1),
+            ),
+            SlotSize::Fixed(width, height) => (width, height),
+        }
+    }
+}
+
+/// a texture that only lives while the graph runs, nodes that don't overlap share the memory
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransientTexture {
+    pub name: String,
+    pub size: SlotSize,
+    pub format: wgpu::TextureFormat,
+    pub usage: wgpu::TextureUsages,
+}
+
+impl TransientTexture {
+    /// a surface sized texture that can be rendered to and sampled
+    pub fn new(name: impl Into<String>, format: wgpu::TextureFormat) -> Self {
+        Self {
+            name: name.into(),
+            size: SlotSize::Surface,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        }
+    }
+
+    pub fn with_size(mut self, size: SlotSize) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn with_usage(mut self, usage: wgpu::TextureUsages) -> Self {
+        self.usage = usage;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorAttachment {
+    pub slot: String,
+    /// the slot the multisampled `slot` is resolved into at the end of the pass
+    pub resolve_target: Option<String>,
+    pub load: wgpu::LoadOp<wgpu::Color>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DepthAttachment {
+    pub slot: String,
+    pub load: wgpu::LoadOp<f32>,
+}
+
+/// what a node touches, the graph orders nodes so every writer of a slot runs before its readers.
+///
+/// nodes with attachments get a render pass opened for them and are drawn through `RenderNode::draw`,
+/// following nodes with the same attachments that only load them share that pass.
+/// nodes without attachments record into the encoder with `RenderNode::record`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NodeDesc {
+    pub name: String,
+    pub reads: Vec<String>,
+    pub writes: Vec<String>,
+    pub transients: Vec<TransientTexture>,
+    pub color_attachments: Vec<ColorAttachment>,
+    pub depth_attachment: Option<DepthAttachment>,
+}
+
+impl NodeDesc {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn reads(mut self, slot: impl Into<String>) -> Self {
+        self.reads.push(slot.into());
+        self
+    }
+
+    pub fn writes(mut self, slot: impl Into<String>) -> Self {
+        self.writes.push(slot.into());
+        self
+    }
+
+    pub fn transient(mut self, texture: TransientTexture) -> Self {
+        self.transients.push(texture);
+        self
+    }
+
+    pub fn color_attachment(
+        mut self,
+        slot: impl Into<String>,
+        load: wgpu::LoadOp<wgpu::Color>,
+    ) -> Self {
+        self.color_attachments.push(ColorAttachment {
+            slot: slot.into(),
+            resolve_target: None,
+            load,
+        });
+        self
+    }
+
+    /// a multisampled color attachment that is resolved into `resolve_target`
+    pub fn resolved_color_attachment(
+        mut self,
+        slot: impl Into<String>,
+        resolve_target: impl Into<String>,
+        load: wgpu::LoadOp<wgpu::Color>,
+    ) -> Self {
+        self.color_attachments.push(ColorAttachment {
+            slot: slot.into(),
+            resolve_target: Some(resolve_target.into()),
+            load,
+        });
+        self
+    }
+
+    pub fn depth_attachment(mut self, slot: impl Into<String>, load: wgpu::LoadOp<f32>) -> Self {
+        self.depth_attachment = Some(DepthAttachment {
+            slot: slot.into(),
+            load,
+        });
+        self
+    }
+
+    pub fn is_raster(&self) -> bool {
+        !self.color_attachments.is_empty() || self.depth_attachment.is_some()
+    }
+
+    /// reads plus the attachments that get loaded
+    fn read_slots(&self) -> impl Iterator<Item = &str> {
+        let colors = self
+            .color_attachments
+            .iter()
+            .filter(|x| matches!(x.load, wgpu::LoadOp::Load))
+            .map(|x| x.slot.as_str());
+        let depth = self
+            .depth_attachment
+            .iter()
+            .filter(|x| matches!(x.load, wgpu::LoadOp::Load))
+            .map(|x| x.slot.as_str());
+
+        self.reads.iter().map(|x| x.as_str()).chain(colors).chain(depth)
+    }
+
+    /// writes plus every attachment and resolve target
+    fn write_slots(&self) -> impl Iterator<Item = &str> {
+        self.writes.iter().map(|x| x.as_str()).chain(self.attachment_slots())
+    }
+
+    /// the slots the pass opened for the node draws into, they have to be textures
+    fn attachment_slots(&self) -> impl Iterator<Item = &str> {
+        let colors = self
+            .color_attachments
+            .iter()
+            .flat_map(|x| std::iter::once(x.slot.as_str()).chain(x.resolve_target.as_deref()));
+        let depth = self.depth_attachment.iter().map(|x| x.slot.as_str());
+
+        colors.chain(depth)
+    }
+
+    fn slots(&self) -> impl Iterator<Item = &str> {
+        self.read_slots()
+            .chain(self.write_slots())
+            .chain(self.transients.iter().map(|x| x.name.as_str()))
+    }
+
+    /// a pass opened for `self` can also draw `next`
+    fn can_merge(&self, next: &NodeDesc) -> bool {
+        let same_colors = self.color_attachments.len() == next.color_attachments.len()
+            && self
+                .color_attachments
+                .iter()
+                .zip(next.color_attachments.iter())
+                .all(|(a, b)| {
+                    a.slot == b.slot
+                        && a.resolve_target == b.resolve_target
+                        && matches!(b.load, wgpu::LoadOp::Load)
+                });
+
+        let same_depth = match (&self.depth_attachment, &next.depth_attachment) {
+            (Some(a), Some(b)) => a.slot == b.slot && matches!(b.load, wgpu::LoadOp::Load),
+            (None, None) => true,
+            _ => false,
+        };
+
+        self.is_raster() && next.is_raster() && same_colors && same_depth
+    }
+}
+
+/// a pass of the render graph, added with `RenderGraph::add_node`.
+///
+/// nodes get the `Renderer` passed in, fetching it or the `RenderGraph` from the resources would fail
+pub trait RenderNode: Send + Sync + 'static {
+    /// read when the node is added and again after every `prepare`,
+    /// the graph is ordered again when it changed
+    fn desc(&self) -> NodeDesc;
+
+    /// runs every frame before anything is recorded, upload buffers here
+    fn prepare(
+        &mut self,
+        world: &mut legion::World,
+        resources: &legion::Resources,
+        renderer: &mut Renderer,
+    ) {
+    }
+
+    /// records a node without attachments.
+    /// nodes with attachments can record work here that has to happen before their pass is opened
+    fn record(&self, encoder: &mut wgpu::CommandEncoder, ctx: &NodeContext) {}
+
+    /// draws into the pass opened for the attachments of the node
+    fn draw<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>, ctx: &NodeContext<'a>) {}
+
+    /// runs after the recorded frame was submitted, not for frames that were skipped after `prepare`
+    fn finish(&mut self, world: &mut legion::World) {}
+}
+
+#[derive(Clone, Copy)]
+pub enum SlotRef<'a> {
+    Texture {
+        view: &'a wgpu::TextureView,
+        texture: &'a wgpu::Texture,
+        size: (u32, u32),
+        format: wgpu::TextureFormat,
+    },
+    Buffer(&'a wgpu::Buffer),
+}
+
+/// what a node can see while recording
+pub struct NodeContext<'a> {
+    pub world: &'a legion::World,
+    pub resources: &'a legion::Resources,
+    pub renderer: &'a Renderer,
+    slots: &'a HashMap<&'a str, SlotRef<'a>>,
+}
+
+impl<'a> NodeContext<'a> {
+    pub fn slot(&self, name: &str) -> Option<SlotRef<'a>> {
+        self.slots.get(name).copied()
+    }
+
+    pub fn texture_view(&self, name: &str) -> Option<&'a wgpu::TextureView> {
+        match self.slot(name)? {
+            SlotRef::Texture { view, .. } => Some(view),
+            SlotRef::Buffer(_) => None,
+        }
+    }
+
+    pub fn texture(&self, name: &str) -> Option<&'a wgpu::Texture> {
+        match self.slot(name)? {
+            SlotRef::Texture { texture, .. } => Some(texture),
+            SlotRef::Buffer(_) => None,
+        }
+    }
+
+    pub fn texture_size(&self, name: &str) -> Option<(u32, u32)> {
+        match self.slot(name)? {
+            SlotRef::Texture { size, .. } => Some(size),
+            SlotRef::Buffer(_) => None,
+        }
+    }
+
+    pub fn buffer(&self, name: &str) -> Option<&'a wgpu::Buffer> {
+        match self.slot(name)? {
+            SlotRef::Buffer(buffer) => Some(buffer),
+            SlotRef::Texture { .. } => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenderGraphError {
+    DuplicateNode(String),
+    DuplicateTransient(String),
+    /// the nodes that are part of a dependency cycle
+    Cycle(Vec<String>),
+    MissingSlot { node: String, slot: String },
+    /// an imported buffer is used as a color, resolve or depth attachment
+    BufferAttachment { node: String, slot: String },
+}
+
+impl fmt::Display for RenderGraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderGraphError::DuplicateNode(name) => write!(f, "render node `{}` already exists", name),
+            RenderGraphError::DuplicateTransient(name) => {
+                write!(f, "transient texture `{}` is declared twice", name)
+            }
+            RenderGraphError::Cycle(nodes) => {
+                write!(f, "render nodes depend on each other : {}", nodes.join(", "))
+            }
+            RenderGraphError::MissingSlot { node, slot } => {
+                write!(f, "render node `{}` uses slot `{}` that nobody provides", node, slot)
+            }
+            RenderGraphError::BufferAttachment { node, slot } => {
+                write!(f, "render node `{}` draws into slot `{}`, which is a buffer", node, slot)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RenderGraphError {}
+
+struct GraphNode {
+    desc: NodeDesc,
+    node: Box<dyn RenderNode>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TransientKey {
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+    usage: wgpu::TextureUsages,
+}
+
+struct PooledTexture {
+    key: TransientKey,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+}
+
+/// a texture of the pool before it is created
+struct PlannedTexture {
+    key: TransientKey,
+    /// the name of the first transient that uses it
+    label: String,
+    /// the last position in the order that uses it this frame
+    busy_until: usize,
+}
+
+const ATTACHMENT_CHECKED: &str = "attachments are checked to be textures";
+
+enum Imported {
+    Texture(Arc<texture::Texture>),
+    Buffer(Arc<wgpu::Buffer>),
+}
+
+/// the passes of a frame, inserted as a resource by the `RenderPlugin`.
+/// `draw` prepares and records every node each frame
+pub struct RenderGraph {
+    nodes: Vec<GraphNode>,
+    imported: HashMap<String, Imported>,
+
+    /// node indices grouped into render passes, in execution order
+    passes: Vec<Vec<usize>>,
+    dirty: bool,
+
+    pool: Vec<PooledTexture>,
+    /// transient name to pool index
+    assigned: HashMap<String, usize>,
+}
+
+impl Default for RenderGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RenderGraph {
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![],
+            imported: HashMap::new(),
+            passes: vec![],
+            dirty: true,
+            pool: vec![],
+            assigned: HashMap::new(),
+        }
+    }
+
+    /// adds a node, its `desc` decides where it runs
+    pub fn add_node(&mut self, node: impl RenderNode) -> Result<(), RenderGraphError> {
+        let desc = node.desc();
+        if self.nodes.iter().any(|x| x.desc.name == desc.name) {
+            return Err(RenderGraphError::DuplicateNode(desc.name));
+        }
+
+        self.nodes.push(GraphNode {
+            desc,
+            node: Box::new(node),
+        });
+        self.dirty = true;
+        Ok(())
+    }
+
+    pub fn remove_node(&mut self, name: &str) -> bool {
+        let len = self.nodes.len();
+        self.nodes.retain(|x| x.desc.name != name);
+        self.dirty = true;
+        len != self.nodes.len()
+    }
+
+    pub fn has_node(&self, name: &str) -> bool {
+        self.nodes.iter().any(|x| x.desc.name == name)
+    }
+
+    /// makes a texture that lives outside the graph readable and writable as `name`
+    pub fn import_texture(&mut self, name: impl Into<String>, texture: Arc<texture::Texture>) {
+        self.imported.insert(name.into(), Imported::Texture(texture));
+    }
+
+    pub fn import_buffer(&mut self, name: impl Into<String>, buffer: Arc<wgpu::Buffer>) {
+        self.imported.insert(name.into(), Imported::Buffer(buffer));
+    }
+
+    /// the node names in execution order, inner lists share a render pass
+    pub fn order(&mut self) -> Result<Vec<Vec<String>>, RenderGraphError> {
+        self.compile()?;
+        Ok(self
+            .passes
+            .iter()
+            .map(|pass| pass.iter().map(|i| self.nodes[*i].desc.name.clone()).collect())
+            .collect())
+    }
+
+    fn compile(&mut self) -> Result<(), RenderGraphError> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let mut transients = HashMap::new();
+        for node in self.nodes.iter() {
+            for transient in node.desc.transients.iter() {
+                if transients.insert(transient.name.as_str(), ()).is_some() {
+                    return Err(RenderGraphError::DuplicateTransient(transient.name.clone()));
+                }
+            }
+        }
+
+        // writers of every slot in the order they were added
+        let mut writers: HashMap<&str, Vec<usize>> = HashMap::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            for slot in node.desc.write_slots() {
+                let list = writers.entry(slot).or_default();
+                if list.last() != Some(&i) {
+                    list.push(i);
+                }
+            }
+        }
+
+        let mut dependents: Vec<Vec<usize>> = vec![vec![]; self.nodes.len()];
+        let mut dependencies = vec![0usize; self.nodes.len()];
+        let mut add_edge = |from: usize, to: usize| {
+            if from != to && !dependents[from].contains(&to) {
+                dependents[from].push(to);
+                dependencies[to] += 1;
+            }
+        };
+
+        for list in writers.values() {
+            for pair in list.windows(2) {
+                add_edge(pair[0], pair[1]);
+            }
+        }
+
+        for (i, node) in self.nodes.iter().enumerate() {
+            for slot in node.desc.read_slots() {
+                let writes_too = node.desc.write_slots().any(|x| x == slot);
+
+                for &writer in writers.get(slot).into_iter().flatten() {
+                    // a node that reads and writes a slot only waits for the writers added before it
+                    if writes_too && writer > i {
+                        continue;
+                    }
+                    add_edge(writer, i);
+                }
+            }
+        }
+
+        // kahn, ties are broken by the order nodes were added
+        let mut ready: BinaryHeap<Reverse<usize>> = dependencies
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count == 0)
+            .map(|(i, _)| Reverse(i))
+            .collect();
+
+        let mut order = Vec::with_capacity(self.nodes.len());
+        while let Some(Reverse(i)) = ready.pop() {
+            order.push(i);
+            for &next in dependents[i].iter() {
+                dependencies[next] -= 1;
+                if dependencies[next] == 0 {
+                    ready.push(Reverse(next));
+                }
+            }
+        }
+
+        if order.len() != self.nodes.len() {
+            return Err(RenderGraphError::Cycle(
+                (0..self.nodes.len())
+                    .filter(|i| !order.contains(i))
+                    .map(|i| self.nodes[i].desc.name.clone())
+                    .collect(),
+            ));
+        }
+
+        let mut passes: Vec<Vec<usize>> = vec![];
+        for i in order {
+            match passes.last_mut() {
+                Some(pass) if self.nodes[pass[0]].desc.can_merge(&self.nodes[i].desc) => pass.push(i),
+                _ => passes.push(vec![i]),
+            }
+        }
+
+        self.passes = passes;
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// orders the nodes, runs their `prepare` and allocates the transient textures
+    pub(crate) fn prepare(
+        &mut self,
+        world: &mut legion::World,
+        resources: &legion::Resources,
+        renderer: &mut Renderer,
+    ) -> Result<(), RenderGraphError> {
+        self.compile()?;
+
+        for pass in self.passes.iter() {
+            for &i in pass.iter() {
+                self.nodes[i].node.prepare(world, resources, renderer);
+            }
+        }
+
+        // nodes can change their attachments in `prepare`, like the main pass does for msaa
+        for node in self.nodes.iter_mut() {
+            let desc = node.node.desc();
+            if desc != node.desc {
+                node.desc = desc;
+                self.dirty = true;
+            }
+        }
+        self.compile()?;
+
+        self.allocate_transients(renderer);
+        Ok(())
+    }
+
+    /// the transients grouped by the texture they share, sorted by name.
+    /// a texture is handed to the next transient of the same size, format and usage
+    /// once every pass using it is done
+    pub fn transient_groups(
+        &mut self,
+        surface_config: &wgpu::SurfaceConfiguration,
+    ) -> Result<Vec<Vec<String>>, RenderGraphError> {
+        self.compile()?;
+
+        let (planned, assigned) = self.plan_transients(surface_config);
+        let mut groups = vec![vec![]; planned.len()];
+        for (name, i) in assigned {
+            groups[i].push(name);
+        }
+        for group in groups.iter_mut() {
+            group.sort();
+        }
+        Ok(groups)
+    }
+
+    /// the textures the transients need and the index of the texture of every transient
+    fn plan_transients(
+        &self,
+        surface_config: &wgpu::SurfaceConfiguration,
+    ) -> (Vec<PlannedTexture>, HashMap<String, usize>) {
+        // first and last position every transient is used at
+        let mut lifetimes: Vec<(&TransientTexture, usize, usize)> = vec![];
+        let position_of = |name: &str| {
+            let mut uses = self
+                .passes
+                .iter()
+                .enumerate()
+                .filter(|(_, pass)| pass.iter().any(|i| self.nodes[*i].desc.slots().any(|x| x == name)))
+                .map(|(position, _)| position);
+            let first = uses.next().unwrap_or(0);
+            (first, uses.next_back().unwrap_or(first))
+        };
+
+        for node in self.nodes.iter() {
+            for transient in node.desc.transients.iter() {
+                let (first, last) = position_of(&transient.name);
+                lifetimes.push((transient, first, last));
+            }
+        }
+        lifetimes.sort_by_key(|x| x.1);
+
+        let mut planned: Vec<PlannedTexture> = vec![];
+        let mut assigned = HashMap::new();
+
+        for (transient, first, last) in lifetimes {
+            let (width, height) = transient.size.resolve(surface_config);
+            let key = TransientKey {
+                width,
+                height,
+                format: transient.format,
+                usage: transient.usage,
+            };
+
+            let i = match planned.iter().position(|x| x.key == key && x.busy_until < first) {
+                Some(i) => i,
+                None => {
+                    planned.push(PlannedTexture {
+                        key,
+                        label: transient.name.clone(),
+                        busy_until: last,
+                    });
+                    planned.len() - 1
+                }
+            };
+            planned[i].busy_until = last;
+            assigned.insert(transient.name.clone(), i);
+        }
+
+        (planned, assigned)
+    }
+
+    fn allocate_transients(&mut self, renderer: &Renderer) {
+        let (planned, assigned) = self.plan_transients(&renderer.surface_config);
+
+        let mut old = std::mem::take(&mut self.pool);
+        let pool = planned
+            .into_iter()
+            .map(|planned| match old.iter().position(|x| x.key == planned.key) {
+                Some(i) => old.swap_remove(i),
+                None => {
+                    let key = planned.key;
+                    let texture = renderer.device.create_texture(&wgpu::TextureDescriptor {
+                        label: Some(planned.label.as_str()),
+                        size: wgpu::Extent3d {
+                            width: key.width,
+                            height: key.height,
+                            depth_or_array_layers: 1,
+                        },
+                        mip_level_count: 1,
+                        sample_count: 1,
+                        dimension: wgpu::TextureDimension::D2,
+                        format: key.format,
+                        usage: key.usage,
+                        view_formats: &[],
+                    });
+                    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+                    PooledTexture { key, texture, view }
+                }
+            })
+            .collect();
+
+        self.pool = pool;
+        self.assigned = assigned;
+    }
+
+    /// tells the nodes that the frame they recorded was submitted
+    pub(crate) fn finish(&mut self, world: &mut legion::World) {
+        for pass in self.passes.iter() {
+            for &i in pass.iter() {
+                self.nodes[i].node.finish(world);
+            }
+        }
+    }
+
+    /// records every node into `encoder`
+    pub(crate) fn execute(
+        &self,
+        world: &legion::World,
+        resources: &legion::Resources,
+        renderer: &Renderer,
+        swapchain: (&wgpu::Texture, &wgpu::TextureView),
+        encoder: &mut wgpu::CommandEncoder,
+    ) -> Result<(), RenderGraphError> {
+        let config = &renderer.surface_config;
+        let mut slots: HashMap<&str, SlotRef> = HashMap::new();
+
+        slots.insert(
+            SWAPCHAIN,
+            SlotRef::Texture {
+                view: swapchain.1,
+                texture: swapchain.0,
+                size: (config.width, config.height),
+                format: config.format,
+            },
+        );
+        slots.insert(
+            MAIN_DEPTH,
+            SlotRef::Texture {
+                view: &renderer.depth.depth_texture.view,
+                texture: &renderer.depth.depth_texture.texture,
+                size: (config.width, config.height),
+                format: renderer.depth.depth_texture.texture.format(),
+            },
+        );
+
+        if let Some(msaa) = &renderer.msaa_color {
+            slots.insert(
+                MSAA_COLOR,
+                SlotRef::Texture {
+                    view: &msaa.view,
+                    texture: &msaa.texture,
+                    size: (config.width, config.height),
+                    format: msaa.texture.format(),
+                },
+            );
+        }
+
+        slots.insert(
+            SHADOW_MAP,
+            SlotRef::Texture {
+                view: &renderer.shadows.texture.view,
+                texture: &renderer.shadows.texture.texture,
+                size: (renderer.shadows.resolution, renderer.shadows.resolution),
+                format: renderer.shadows.texture.texture.format(),
+            },
+        );
+
+        for (name, imported) in self.imported.iter() {
+            let slot = match imported {
+                Imported::Texture(texture) => {
+                    let size = texture.texture.size();
+                    SlotRef::Texture {
+                        view: &texture.view,
+                        texture: &texture.texture,
+                        size: (size.width, size.height),
+                        format: texture.texture.format(),
+                    }
+                }
+                Imported::Buffer(buffer) => SlotRef::Buffer(buffer),
+            };
+            slots.insert(name.as_str(), slot);
+        }
+
+        for (name, i) in self.assigned.iter() {
+            let pooled = &self.pool[*i];
+            slots.insert(
+                name.as_str(),
+                SlotRef::Texture {
+                    view: &pooled.view,
+                    texture: &pooled.texture,
+                    size: (pooled.key.width, pooled.key.height),
+                    format: pooled.key.format,
+                },
+            );
+        }
+
+        for pass in self.passes.iter() {
+            for &i in pass.iter() {
+                let desc = &self.nodes[i].desc;
+                if let Some(slot) = desc.slots().find(|x| !slots.contains_key(x)) {
+                    return Err(RenderGraphError::MissingSlot {
+                        node: desc.name.clone(),
+                        slot: slot.to_string(),
+                    });
+                }
+                if let Some(slot) = desc
+                    .attachment_slots()
+                    .find(|x| matches!(slots.get(x), Some(SlotRef::Buffer(_))))
+                {
+                    return Err(RenderGraphError::BufferAttachment {
+                        node: desc.name.clone(),
+                        slot: slot.to_string(),
+                    });
+                }
+            }
+        }
+
+        let ctx = NodeContext {
+            world,
+            resources,
+            renderer,
+            slots: &slots,
+        };
+
+        for pass in self.passes.iter() {
+            let first = &self.nodes[pass[0]];
+
+            if !first.desc.is_raster() {
+                first.node.record(encoder, &ctx);
+                continue;
+            }
+
+            for &i in pass.iter() {
+                self.nodes[i].node.record(encoder, &ctx);
+            }
+
+            let color_attachments: Vec<Option<wgpu::RenderPassColorAttachment>> = first
+                .desc
+                .color_attachments
+                .iter()
+                .map(|x| {
+                    Some(wgpu::RenderPassColorAttachment {
+                        view: ctx.texture_view(&x.slot).expect(ATTACHMENT_CHECKED),
+                        resolve_target: x
+                            .resolve_target
+                            .as_ref()
+                            .map(|slot| ctx.texture_view(slot).expect(ATTACHMENT_CHECKED)),
+                        ops: wgpu::Operations {
+                            load: x.load,
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })
+                })
+                .collect();
+
+            let depth_stencil_attachment =
+                first
+                    .desc
+                    .depth_attachment
+                    .as_ref()
+                    .map(|x| wgpu::RenderPassDepthStencilAttachment {
+                        view: ctx.texture_view(&x.slot).expect(ATTACHMENT_CHECKED),
+                        depth_ops: Some(wgpu::Operations {
+                            load: x.load,
+                            store: wgpu::StoreOp::Store,
+                        }),
+                        // the stencil is cleared together with the depth
+                        stencil_ops: match ctx.slot(&x.slot) {
+                            Some(SlotRef::Texture { format, .. }) if format.has_stencil_aspect() => {
+                                Some(wgpu::Operations {
+                                    load: match x.load {
+                                        wgpu::LoadOp::Clear(_) => wgpu::LoadOp::Clear(0),
+                                        wgpu::LoadOp::Load => wgpu::LoadOp::Load,
+                                    },
+                                    store: wgpu::StoreOp::Store,
+                                })
+                            }
+                            _ => None,
+                        },
+                    });
+
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(first.desc.name.as_str()),
+                color_attachments: &color_attachments,
+                depth_stencil_attachment,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            for &i in pass.iter() {
+                self.nodes[i].node.draw(&mut rpass, &ctx);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,205 @@
+use std::ops::Range;
+
+use cgmath::prelude::*;
+use cgmath::{Point3, Vector3};
+
+use crate::{
+    cull_instances_with_centers, Aabb, Camera, Frustum, InstanceRaw, Material, ModelMatrix,
+    RenderLayers,
+};
+
+/// when a material is drawn, queues are drawn in this order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum RenderQueue {
+    /// skyboxes and fullscreen backgrounds, drawn in `queue_priority` order
+    Background,
+    /// sorted front to back so the depth test rejects hidden pixels early
+    #[default]
+    Opaque,
+    /// opaque materials that discard pixels, after the others because discard slows down the depth test
+    AlphaTest,
+    /// sorted back to front per instance, across materials, so blending composites correctly.
+    /// a higher `queue_priority` draws over the lower ones whatever their distance
+    Transparent,
+    /// ui and gizmos on top of everything, drawn in `queue_priority` order
+    Overlay,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    /// keeps the order of the instances and materials
+    None,
+    FrontToBack,
+    BackToFront,
+}
+
+impl RenderQueue {
+    pub fn sort_order(self) -> SortOrder {
+        match self {
+            RenderQueue::Background | RenderQueue::Overlay => SortOrder::None,
+            RenderQueue::Opaque | RenderQueue::AlphaTest => SortOrder::FrontToBack,
+            RenderQueue::Transparent => SortOrder::BackToFront,
+        }
+    }
+}
+
+/// one draw of a material, `instances` indexes the instance buffer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DrawCall {
+    /// index into the materials the draws were queued for
+    pub material: usize,
+    pub instances: Range<u32>,
+}
+
+/// the camera the draws are culled and sorted for
+pub struct SortView {
+    frustum: Frustum,
+    layers: RenderLayers,
+    eye: Point3<f32>,
+    forward: Vector3<f32>,
+}
+
+impl SortView {
+    pub fn new(camera: &Camera, layers: RenderLayers) -> Self {
+        Self {
+            frustum: Frustum::from_view_projection(&camera.build_view_projection_matrix()),
+            layers,
+            eye: camera.eye,
+            forward: (camera.target - camera.eye).normalize(),
+        }
+    }
+
+    /// the distance along the view direction, the same for perspective and orthographic cameras
+    fn depth(&self, point: Vector3<f32>) -> f32 {
+        (Point3::from_vec(point) - self.eye).dot(self.forward)
+    }
+}
+
+/// what `queue_draws` needs of a material
+#[derive(Debug, Clone, Copy)]
+pub struct QueueItem<'a> {
+    pub queue: RenderQueue,
+    pub queue_priority: i32,
+    pub layers: RenderLayers,
+    /// the instances are never culled without bounds
+    pub bounds: Option<&'a Aabb>,
+    pub instances: &'a [ModelMatrix],
+}
+
+impl<'a> From<&'a Material> for QueueItem<'a> {
+    fn from(material: &'a Material) -> Self {
+        Self {
+            queue: material.descriptor.queue,
+            queue_priority: material.descriptor.queue_priority,
+            layers: material.layers,
+            bounds: if material.frustum_culling {
+                material.bounds.as_ref()
+            } else {
+                None
+            },
+            instances: &material.instances,
+        }
+    }
+}
+
+struct Batch {
+    queue: RenderQueue,
+    priority: i32,
+    /// the distance the batch is sorted by, negated for back to front
+    key: f32,
+    material: usize,
+    instances: Vec<InstanceRaw>,
+}
+
+/// culls the instances of every material on the layers of `view`, appends the visible ones to `instances`
+/// and returns the draws in the order they have to be recorded
+pub fn queue_draws(
+    view: &SortView,
+    materials: &[QueueItem],
+    instances: &mut Vec<InstanceRaw>,
+) -> Vec<DrawCall> {
+    let mut batches: Vec<Batch> = vec![];
+    let mut transparent: Vec<(i32, f32, usize, InstanceRaw)> = vec![];
+    let mut visible = vec![];
+
+    for (i, material) in materials.iter().enumerate() {
+        if !material.layers.intersects(view.layers) {
+            continue;
+        }
+
+        visible.clear();
+        cull_instances_with_centers(
+            &view.frustum,
+            material.bounds,
+            material.instances,
+            &mut visible,
+        );
+        if visible.is_empty() {
+            continue;
+        }
+
+        let queue = material.queue;
+        let priority = material.queue_priority;
+
+        let key = match queue.sort_order() {
+            SortOrder::None => 0.0,
+            SortOrder::FrontToBack => {
+                visible.sort_by(|a, b| view.depth(a.0).total_cmp(&view.depth(b.0)));
+                view.depth(visible[0].0)
+            }
+            SortOrder::BackToFront => {
+                transparent.extend(
+                    visible
+                        .iter()
+                        .map(|(center, instance)| (priority, view.depth(*center), i, *instance)),
+                );
+                continue;
+            }
+        };
+
+        batches.push(Batch {
+            queue,
+            priority,
+            key,
+            material: i,
+            instances: visible.iter().map(|(_, instance)| *instance).collect(),
+        });
+    }
+
+    // instances of different materials with the same priority interleave,
+    // neighbours of the same material share a draw
+    transparent.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.total_cmp(&a.1)));
+    for (priority, depth, material, instance) in transparent {
+        match batches.last_mut() {
+            Some(batch) if batch.queue == RenderQueue::Transparent && batch.material == material => {
+                batch.instances.push(instance);
+            }
+            _ => batches.push(Batch {
+                queue: RenderQueue::Transparent,
+                priority,
+                key: -depth,
+                material,
+                instances: vec![instance],
+            }),
+        }
+    }
+
+    // stable, so materials with the same key keep the order of the query
+    batches.sort_by(|a, b| {
+        (a.queue, a.priority)
+            .cmp(&(b.queue, b.priority))
+            .then(a.key.total_cmp(&b.key))
+    });
+
+    batches
+        .into_iter()
+        .map(|batch| {
+            let start = instances.len() as u32;
+            instances.extend(batch.instances);
+            DrawCall {
+                material: batch.material,
+                instances: start..instances.len() as u32,
+            }
+        })
+        .collect()
+}
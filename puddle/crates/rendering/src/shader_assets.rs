@@ -0,0 +1,288 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use application::log::{error, info};
+use assets::{DecodeLoader, Handle};
+use legion::IntoQuery;
+
+use crate::shader_preprocessor::{PreprocessedShader, ShaderPreprocessor};
+use crate::{CameraBindGroupLayout, Material, Renderer};
+
+#[derive(Debug, Clone)]
+pub enum ShaderError {
+    Io { path: PathBuf, message: String },
+    /// a broken `#` directive
+    Preprocess {
+        file: String,
+        line: u32,
+        message: String,
+    },
+    /// the wgsl couldn't be parsed
+    Parse {
+        file: String,
+        line: u32,
+        column: u32,
+        message: String,
+    },
+    /// the wgsl parsed but naga rejected the module
+    Validation {
+        file: String,
+        line: u32,
+        column: u32,
+        message: String,
+    },
+    /// the module is valid but wgpu couldn't build a module or pipeline from it,
+    /// usually the bindings don't match the material
+    Wgpu { file: String, message: String },
+    /// the resources of the material don't match the bindings the shader declares
+    Binding { file: String, message: String },
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderError::Io { path, message } => {
+                write!(f, "failed to read shader {} : {}", path.display(), message)
+            }
+            ShaderError::Preprocess {
+                file,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", file, line, message),
+            ShaderError::Parse {
+                file,
+                line,
+                column,
+                message,
+            } => write!(f, "{}:{}:{}: failed to parse shader\n{}", file, line, column, message),
+            ShaderError::Validation {
+                file,
+                line,
+                column,
+                message,
+            } => write!(f, "{}:{}:{}: invalid shader\n{}", file, line, column, message),
+            ShaderError::Wgpu { file, message } => write!(f, "{}: {}", file, message),
+            ShaderError::Binding { file, message } => {
+                write!(f, "{}: the material doesn't match the shader\n{}", file, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+/// parses and validates wgsl with naga, `file` is only used for the errors
+pub fn validate_wgsl(file: &str, source: &str) -> Result<naga::Module, ShaderError> {
+    validate_wgsl_with_info(file, source).map(|(module, _)| module)
+}
+
+/// like `validate_wgsl`, the info tells which entry points use which globals
+pub(crate) fn validate_wgsl_with_info(
+    file: &str,
+    source: &str,
+) -> Result<(naga::Module, naga::valid::ModuleInfo), ShaderError> {
+    let module = naga::front::wgsl::parse_str(source).map_err(|e| {
+        let location = e.location(source);
+        ShaderError::Parse {
+            file: file.to_string(),
+            line: location.map(|x| x.line_number).unwrap_or(0),
+            column: location.map(|x| x.line_position).unwrap_or(0),
+            message: e.emit_to_string_with_path(source, file),
+        }
+    })?;
+
+    let info = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(&module)
+    .map_err(|e| {
+        let location = e.location(source);
+        ShaderError::Validation {
+            file: file.to_string(),
+            line: location.map(|x| x.line_number).unwrap_or(0),
+            column: location.map(|x| x.line_position).unwrap_or(0),
+            message: e.emit_to_string_with_path(source, file),
+        }
+    })?;
+
+    Ok((module, info))
+}
+
+/// validates preprocessed wgsl, errors point at the file and line the broken code came from
+pub fn validate_preprocessed(shader: &PreprocessedShader) -> Result<naga::Module, ShaderError> {
+    validate_preprocessed_with_info(shader).map(|(module, _)| module)
+}
+
+pub(crate) fn validate_preprocessed_with_info(
+    shader: &PreprocessedShader,
+) -> Result<(naga::Module, naga::valid::ModuleInfo), ShaderError> {
+    let remap = |file: String, line: u32| match shader.origin(line) {
+        Some((file, line)) => (file.to_string(), line),
+        None => (file, line),
+    };
+
+    validate_wgsl_with_info(&shader.file, &shader.source).map_err(|e| match e {
+        ShaderError::Parse {
+            file,
+            line,
+            column,
+            message,
+        } => {
+            let (file, line) = remap(file, line);
+            ShaderError::Parse {
+                file,
+                line,
+                column,
+                message,
+            }
+        }
+        ShaderError::Validation {
+            file,
+            line,
+            column,
+            message,
+        } => {
+            let (file, line) = remap(file, line);
+            ShaderError::Validation {
+                file,
+                line,
+                column,
+                message,
+            }
+        }
+        e => e,
+    })
+}
+
+/// reads a wgsl file, runs the preprocessor and validates it
+pub fn load_wgsl(
+    preprocessor: &ShaderPreprocessor,
+    path: impl AsRef<Path>,
+) -> Result<PreprocessedShader, ShaderError> {
+    let shader = preprocessor.process_file(path)?;
+    validate_preprocessed(&shader)?;
+    Ok(shader)
+}
+
+/// runs the preprocessor on wgsl that isn't a file, like `include_str!` shaders, and validates it
+pub fn preprocess_wgsl(
+    preprocessor: &ShaderPreprocessor,
+    file: &str,
+    source: &str,
+) -> Result<PreprocessedShader, ShaderError> {
+    let shader = preprocessor.process(file, source)?;
+    validate_preprocessed(&shader)?;
+    Ok(shader)
+}
+
+/// runs `f` and returns the first wgpu validation error it caused instead of panicking
+pub(crate) fn catch_wgpu_errors<T>(
+    device: &wgpu::Device,
+    file: &str,
+    f: impl FnOnce() -> T,
+) -> Result<T, ShaderError> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let value = f();
+
+    match application::async_std::task::block_on(device.pop_error_scope()) {
+        Some(e) => Err(ShaderError::Wgpu {
+            file: file.to_string(),
+            message: e.to_string(),
+        }),
+        None => Ok(value),
+    }
+}
+
+/// creates a shader module from what `validate_wgsl` or `validate_preprocessed` returned,
+/// so the wgsl isn't parsed a second time. errors instead of panicking if wgpu rejects it
+pub fn create_shader_module(
+    device: &wgpu::Device,
+    file: &str,
+    module: naga::Module,
+) -> Result<wgpu::ShaderModule, ShaderError> {
+    catch_wgpu_errors(device, file, || {
+        device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(file),
+            source: wgpu::ShaderSource::Naga(std::borrow::Cow::Owned(module)),
+        })
+    })
+}
+
+/// the text of a wgsl file. materials made with `Material::from_file` hold a handle to their file
+/// and every file it imports, so the watcher of the asset server reports changes to them
+pub struct WgslFile(pub String);
+
+pub(crate) struct WgslLoader;
+
+impl DecodeLoader for WgslLoader {
+    type Asset = WgslFile;
+
+    fn extensions(&self) -> &[&str] {
+        &["wgsl"]
+    }
+
+    fn decode(&self, path: &Path, bytes: Vec<u8>) -> Result<Self::Asset, String> {
+        String::from_utf8(bytes)
+            .map(WgslFile)
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// a handle for every file of the shader and the version it was read at.
+/// empty without an `AssetServer`, the shader is not reloaded then
+pub(crate) fn watch_files(renderer: &Renderer, shader: &PreprocessedShader) -> Vec<(Handle<WgslFile>, u32)> {
+    let server = match &renderer.assets {
+        Some(r) => r,
+        None => {
+            return vec![];
+        }
+    };
+
+    shader
+        .files
+        .iter()
+        .map(|path| {
+            let handle = server.load::<WgslFile>(path.canonicalize().unwrap_or(path.clone()));
+            let version = handle.version();
+            (handle, version)
+        })
+        .collect()
+}
+
+/// reloads the materials whose shader file changed, broken shaders keep the last working pipeline
+pub(crate) fn reload_shaders(
+    world: &mut legion::World,
+    resources: &legion::Resources,
+    renderer: &mut Renderer,
+) {
+    let camera_layout = match resources.get::<CameraBindGroupLayout>() {
+        Some(r) => r,
+        None => {
+            return;
+        }
+    };
+
+    for material in <&mut Material>::query().iter_mut(world) {
+        let path = match &material.shader_path {
+            Some(r) => r.clone(),
+            None => continue,
+        };
+
+        // the first load of a file isn't a change, the material was built from it
+        let mut changed = false;
+        for (handle, seen) in material.shader_files.iter_mut() {
+            let version = handle.version();
+            changed |= *seen != 0 && version != *seen;
+            *seen = version;
+        }
+        if !changed {
+            continue;
+        }
+
+        match material.reload_shader(renderer, &camera_layout) {
+            Ok(()) => info!("reloaded shader {}", path.display()),
+            Err(e) => error!("{}", e),
+        }
+    }
+}
@@ -0,0 +1,320 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::shader_assets::ShaderError;
+
+/// the engine modules, import them with `#import <name>`
+pub const ENGINE_SHADER_MODULES: [(&str, &str); 4] = [
+    ("puddle::camera", include_str!("shaders/modules/camera.wgsl")),
+    ("puddle::instancing", include_str!("shaders/modules/instancing.wgsl")),
+    ("puddle::lighting", include_str!("shaders/modules/lighting.wgsl")),
+    ("puddle::voxel", include_str!("shaders/modules/voxel.wgsl")),
+];
+
+/// wgsl after the preprocessor ran
+#[derive(Debug, Clone)]
+pub struct PreprocessedShader {
+    /// the name errors and labels use
+    pub file: String,
+    pub source: String,
+    /// every file the shader was read from, including imported ones
+    pub files: Vec<PathBuf>,
+    /// the file and line every line of `source` came from
+    line_map: Vec<(String, u32)>,
+}
+
+impl PreprocessedShader {
+    /// the file and line a line of the processed source came from, lines start at 1
+    pub fn origin(&self, line: u32) -> Option<(&str, u32)> {
+        let (file, line) = self.line_map.get((line as usize).checked_sub(1)?)?;
+        Some((file.as_str(), *line))
+    }
+
+    fn push_line(&mut self, line: &str, file: &str, line_number: u32) {
+        self.source.push_str(line);
+        self.source.push('\n');
+        self.line_map.push((file.to_string(), line_number));
+    }
+}
+
+/// resolves `#import`, `#define`, `#undef`, `#ifdef`, `#ifndef`, `#else` and `#endif` in wgsl.
+///
+/// `#import puddle::camera` inserts a registered module, `#import "common.wgsl"` a file
+/// relative to the importing one. everything is imported once, later imports of it are skipped.
+/// defines with a value replace the name wherever it appears as a whole word
+#[derive(Debug, Clone)]
+pub struct ShaderPreprocessor {
+    modules: HashMap<String, String>,
+    defines: HashMap<String, String>,
+}
+
+impl Default for ShaderPreprocessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// one `#ifdef` block
+struct Condition {
+    active: bool,
+    in_else: bool,
+}
+
+struct Context {
+    output: PreprocessedShader,
+    defines: HashMap<String, String>,
+    imported: HashSet<String>,
+}
+
+impl ShaderPreprocessor {
+    /// a preprocessor with the engine modules and the defines they need
+    pub fn new() -> Self {
+        let mut preprocessor = Self::empty();
+        for (name, source) in ENGINE_SHADER_MODULES {
+            preprocessor.add_module(name, source);
+        }
+        preprocessor.define("SHADOW_LAYERS", crate::shadows::SHADOW_LAYERS.to_string());
+        preprocessor.define("MAX_CASCADES", crate::shadows::MAX_CASCADES.to_string());
+        preprocessor
+    }
+
+    /// a preprocessor without any modules or defines
+    pub fn empty() -> Self {
+        Self {
+            modules: HashMap::new(),
+            defines: HashMap::new(),
+        }
+    }
+
+    /// makes `source` importable with `#import <name>`, replaces a module with the same name
+    pub fn add_module(&mut self, name: impl Into<String>, source: impl Into<String>) {
+        self.modules.insert(name.into(), source.into());
+    }
+
+    /// defines `name` for every shader, an empty value only counts for `#ifdef`
+    pub fn define(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.defines.insert(name.into(), value.into());
+    }
+
+    pub fn undefine(&mut self, name: &str) {
+        self.defines.remove(name);
+    }
+
+    pub fn process(&self, file: &str, source: &str) -> Result<PreprocessedShader, ShaderError> {
+        self.process_with_dir(file, source, None, None)
+    }
+
+    /// reads and processes a wgsl file, file imports are relative to it
+    pub fn process_file(&self, path: impl AsRef<Path>) -> Result<PreprocessedShader, ShaderError> {
+        let path = path.as_ref();
+        let source = read_file(path)?;
+
+        // files that import the shader back don't include it a second time
+        let key = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let mut shader = self.process_with_dir(
+            &path.display().to_string(),
+            &source,
+            path.parent(),
+            Some(key.display().to_string()),
+        )?;
+        shader.files.insert(0, path.to_path_buf());
+        Ok(shader)
+    }
+
+    fn process_with_dir(
+        &self,
+        file: &str,
+        source: &str,
+        dir: Option<&Path>,
+        imported: Option<String>,
+    ) -> Result<PreprocessedShader, ShaderError> {
+        let mut ctx = Context {
+            output: PreprocessedShader {
+                file: file.to_string(),
+                source: String::new(),
+                files: vec![],
+                line_map: vec![],
+            },
+            defines: self.defines.clone(),
+            imported: imported.into_iter().collect(),
+        };
+
+        self.process_source(&mut ctx, file, source, dir)?;
+        Ok(ctx.output)
+    }
+
+    fn process_source(
+        &self,
+        ctx: &mut Context,
+        file: &str,
+        source: &str,
+        dir: Option<&Path>,
+    ) -> Result<(), ShaderError> {
+        let error = |line: u32, message: String| ShaderError::Preprocess {
+            file: file.to_string(),
+            line,
+            message,
+        };
+
+        let mut conditions: Vec<Condition> = vec![];
+
+        for (i, line) in source.lines().enumerate() {
+            let line_number = i as u32 + 1;
+            let active = conditions.iter().all(|x| x.active);
+
+            let directive = match line.trim_start().strip_prefix('#') {
+                Some(r) => r.trim(),
+                None => {
+                    if active {
+                        ctx.output
+                            .push_line(&substitute(line, &ctx.defines), file, line_number);
+                    } else {
+                        ctx.output.push_line("", file, line_number);
+                    }
+                    continue;
+                }
+            };
+
+            let (keyword, argument) = match directive.split_once(char::is_whitespace) {
+                Some((keyword, argument)) => (keyword, argument.trim()),
+                None => (directive, ""),
+            };
+            let name = || match argument.split_whitespace().next() {
+                Some(r) => Ok(r),
+                None => Err(error(line_number, format!("#{} needs a name", keyword))),
+            };
+
+            match keyword {
+                "ifdef" | "ifndef" => {
+                    let defined = ctx.defines.contains_key(name()?);
+                    conditions.push(Condition {
+                        active: defined == (keyword == "ifdef"),
+                        in_else: false,
+                    });
+                }
+                "else" => {
+                    let condition = match conditions.last_mut() {
+                        Some(r) if !r.in_else => r,
+                        _ => return Err(error(line_number, "#else without #ifdef".to_string())),
+                    };
+                    condition.active = !condition.active;
+                    condition.in_else = true;
+                }
+                "endif" => {
+                    if conditions.pop().is_none() {
+                        return Err(error(line_number, "#endif without #ifdef".to_string()));
+                    }
+                }
+                "define" | "undef" | "import" if !active => {}
+                "define" => {
+                    let name = name()?;
+                    let value = argument[name.len()..].trim();
+                    ctx.defines.insert(name.to_string(), value.to_string());
+                }
+                "undef" => {
+                    ctx.defines.remove(name()?);
+                }
+                "import" => {
+                    self.import(ctx, argument, dir, file, line_number)?;
+                }
+                _ => {
+                    return Err(error(line_number, format!("unknown directive #{}", keyword)));
+                }
+            }
+
+            // keeps the line numbers of the file the same
+            ctx.output.push_line("", file, line_number);
+        }
+
+        if !conditions.is_empty() {
+            return Err(error(source.lines().count() as u32, "missing #endif".to_string()));
+        }
+
+        Ok(())
+    }
+
+    fn import(
+        &self,
+        ctx: &mut Context,
+        argument: &str,
+        dir: Option<&Path>,
+        file: &str,
+        line: u32,
+    ) -> Result<(), ShaderError> {
+        let error = |message: String| ShaderError::Preprocess {
+            file: file.to_string(),
+            line,
+            message,
+        };
+
+        if argument.is_empty() {
+            return Err(error("#import needs a module name or a quoted path".to_string()));
+        }
+
+        // a file relative to the importing one
+        if let Some(path) = argument.strip_prefix('"') {
+            let path = match path.strip_suffix('"') {
+                Some(r) => r,
+                None => return Err(error(format!("missing closing quote in {}", argument))),
+            };
+            let path = match dir {
+                Some(dir) => dir.join(path),
+                None => PathBuf::from(path),
+            };
+            let path = path.canonicalize().unwrap_or(path);
+
+            let key = path.display().to_string();
+            if !ctx.imported.insert(key.clone()) {
+                return Ok(());
+            }
+
+            let source = read_file(&path)?;
+            ctx.output.files.push(path.clone());
+            return self.process_source(ctx, &key, &source, path.parent());
+        }
+
+        let source = match self.modules.get(argument) {
+            Some(r) => r,
+            None => return Err(error(format!("unknown shader module {}", argument))),
+        };
+        if !ctx.imported.insert(argument.to_string()) {
+            return Ok(());
+        }
+
+        self.process_source(ctx, argument, source, None)
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, ShaderError> {
+    std::fs::read_to_string(path).map_err(|e| ShaderError::Io {
+        path: path.to_path_buf(),
+        message: e.to_string(),
+    })
+}
+
+/// replaces every defined name that has a value, only whole words
+fn substitute(line: &str, defines: &HashMap<String, String>) -> String {
+    if defines.values().all(|x| x.is_empty()) {
+        return line.to_string();
+    }
+
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find(is_word) {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = rest.find(|c: char| !is_word(c)).unwrap_or(rest.len());
+        let word = &rest[..end];
+        match defines.get(word) {
+            Some(value) if !value.is_empty() => result.push_str(value),
+            _ => result.push_str(word),
+        }
+        rest = &rest[end..];
+    }
+    result.push_str(rest);
+
+    result
+}
@@ -0,0 +1,15 @@
+// clears the depth and stencil of a viewport inside a pass that is already open.
+// the triangle is at depth 0, the viewport maps that to the clear value
+
+// one triangle that covers the viewport
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4(uv * vec2(2.0, -2.0) + vec2(-1.0, 1.0), 0.0, 1.0);
+}
+
+// the color target isn't written
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4(0.0);
+}
@@ -0,0 +1,22 @@
+// copies the depth of the depth stencil buffer into the sampled depth texture,
+// with MULTISAMPLED defined only the first sample is used
+
+#ifdef MULTISAMPLED
+@group(0) @binding(0)
+    var depth_texture: texture_depth_multisampled_2d;
+#else
+@group(0) @binding(0)
+    var depth_texture: texture_depth_2d;
+#endif
+
+// one triangle that covers the screen
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4(uv * vec2(2.0, -2.0) + vec2(-1.0, 1.0), 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @builtin(frag_depth) f32 {
+    return textureLoad(depth_texture, vec2<i32>(position.xy), 0);
+}
@@ -0,0 +1,56 @@
+#import puddle::camera
+#import puddle::instancing
+#import puddle::lighting
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) uv: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) world_position: vec3<f32>,
+};
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    let model_matrix = instance_model_matrix(instance);
+
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * world_position;
+    out.uv = model.uv;
+    out.normal = (model_matrix * vec4<f32>(model.normal, 0.0)).xyz;
+    out.world_position = world_position.xyz;
+    return out;
+}
+
+
+struct MaterialUniform {
+    base_color: vec4<f32>,
+    // rgb specular color, w is the shininess
+    specular: vec4<f32>,
+};
+
+@group(1) @binding(0)
+    var base_color_texture: texture_2d<f32>;
+@group(1) @binding(1)
+    var base_color_sampler: sampler;
+@group(1) @binding(2)
+    var<uniform> material: MaterialUniform;
+
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(base_color_texture, base_color_sampler, in.uv) * material.base_color;
+    let lighting = blinn_phong(color.rgb, material.specular, normalize(in.normal), in.world_position);
+
+    return vec4(lighting, color.a);
+}
@@ -0,0 +1,9 @@
+// the camera of the view that is drawn, matches `CameraUniform` on the rust side
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    camera_eye: vec4<f32>,
+};
+
+@group(0) @binding(0)
+    var<uniform> camera: CameraUniform;
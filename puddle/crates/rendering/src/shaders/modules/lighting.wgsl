@@ -0,0 +1,144 @@
+// the lights and shadow maps the renderer binds as group 3, see `LightBuffer` on the rust side.
+// `SHADOW_LAYERS` and `MAX_CASCADES` are defined by the preprocessor of the renderer
+
+#import puddle::camera
+
+struct Light {
+    // xyz, w = 0 directional, 1 point, 2 spot
+    position: vec4<f32>,
+    // xyz, w = range
+    direction: vec4<f32>,
+    // rgb, w = intensity
+    color: vec4<f32>,
+    // cos inner angle, cos outer angle, first shadow layer or -1
+    cone: vec4<f32>,
+};
+
+struct Lights {
+    ambient: vec4<f32>,
+    count: u32,
+    lights: array<Light>,
+};
+
+@group(3) @binding(0)
+    var<storage, read> lights: Lights;
+
+struct ShadowUniform {
+    light_view_proj: array<mat4x4<f32>, SHADOW_LAYERS>,
+    cascade_splits: vec4<f32>,
+    // depth bias, normal bias, pcf radius, texel size
+    params: vec4<f32>,
+    cascade_count: u32,
+};
+
+@group(3) @binding(1)
+    var<uniform> shadows: ShadowUniform;
+@group(3) @binding(2)
+    var shadow_map: texture_depth_2d_array;
+@group(3) @binding(3)
+    var shadow_sampler: sampler_comparison;
+
+
+// smooth falloff that reaches zero at the range of the light
+fn attenuation(distance: f32, range: f32) -> f32 {
+    let ratio = distance / max(range, 0.0001);
+    let window = clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+    return window * window / (distance * distance + 1.0);
+}
+
+fn sample_shadow(layer: u32, world_position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    let position = world_position + normal * shadows.params.y;
+    let clip = shadows.light_view_proj[layer] * vec4(position, 1.0);
+    if clip.w <= 0.0 {
+        return 1.0;
+    }
+
+    let ndc = clip.xyz / clip.w;
+    let uv = ndc.xy * vec2(0.5, -0.5) + 0.5;
+    if any(uv < vec2(0.0)) || any(uv > vec2(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+
+    // spot light maps are perspective, they get a slope scaled bias while they are rendered instead
+    let bias = select(shadows.params.x, 0.0, layer >= u32(MAX_CASCADES));
+    let depth = ndc.z - bias;
+    let radius = i32(shadows.params.z);
+
+    // percentage closer filtering
+    var lit = 0.0;
+    var samples = 0.0;
+    for (var x = -radius; x <= radius; x++) {
+        for (var y = -radius; y <= radius; y++) {
+            let offset = vec2(f32(x), f32(y)) * shadows.params.w;
+            lit += textureSampleCompareLevel(shadow_map, shadow_sampler, uv + offset, layer, depth);
+            samples += 1.0;
+        }
+    }
+
+    return lit / samples;
+}
+
+fn shadow_factor(light: Light, world_position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    if light.cone.z < 0.0 {
+        return 1.0;
+    }
+
+    var layer = u32(light.cone.z);
+
+    // directional lights pick the cascade by the distance to the camera
+    if light.position.w == 0.0 {
+        let distance = length(camera.camera_eye.xyz - world_position);
+        if distance > shadows.cascade_splits[shadows.cascade_count - 1u] {
+            return 1.0;
+        }
+
+        var cascade = 0u;
+        for (var c = 0u; c + 1u < shadows.cascade_count; c++) {
+            if distance > shadows.cascade_splits[c] {
+                cascade = c + 1u;
+            }
+        }
+        layer += cascade;
+    }
+
+    return sample_shadow(layer, world_position, normal);
+}
+
+// ambient plus every light with blinn-phong shading, `specular.w` is the shininess
+fn blinn_phong(color: vec3<f32>, specular: vec4<f32>, normal: vec3<f32>, world_position: vec3<f32>) -> vec3<f32> {
+    let view_dir = normalize(camera.camera_eye.xyz - world_position);
+
+    var lighting = lights.ambient.rgb * color;
+
+    for (var i = 0u; i < lights.count; i++) {
+        let light = lights.lights[i];
+
+        var light_dir: vec3<f32>;
+        var strength = light.color.w;
+
+        if light.position.w == 0.0 {
+            light_dir = -normalize(light.direction.xyz);
+        } else {
+            let to_light = light.position.xyz - world_position;
+            let distance = length(to_light);
+            light_dir = to_light / max(distance, 0.0001);
+            strength *= attenuation(distance, light.direction.w);
+
+            if light.position.w == 2.0 {
+                let cos_angle = dot(-light_dir, normalize(light.direction.xyz));
+                strength *= smoothstep(light.cone.y, light.cone.x, cos_angle);
+            }
+        }
+
+        strength *= shadow_factor(light, world_position, normal);
+
+        let diffuse = max(dot(normal, light_dir), 0.0);
+
+        let half_dir = normalize(light_dir + view_dir);
+        let highlight = pow(max(dot(normal, half_dir), 0.0), specular.w) * select(0.0, 1.0, diffuse > 0.0);
+
+        lighting += light.color.rgb * strength * (color * diffuse + specular.rgb * highlight);
+    }
+
+    return lighting;
+}
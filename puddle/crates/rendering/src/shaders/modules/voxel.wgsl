@@ -0,0 +1,54 @@
+// stepping a ray through a voxel grid one cell at a time (dda).
+// the importing shader defines `fn voxel_is_solid(position: vec3<i32>) -> bool`
+
+struct VoxelHit {
+    // the empty cell in front of the hit one
+    position: vec3<f32>,
+    cell: vec3<i32>,
+    normal: vec3<f32>,
+    distance: f32,
+    hit: bool,
+    steps: i32,
+};
+
+fn voxel_raycast(origin: vec3<f32>, direction: vec3<f32>, max_steps: i32) -> VoxelHit {
+    var cell = vec3<i32>(floor(origin));
+    let delta_dist = abs(vec3(length(direction)) / direction);
+    let ray_step = vec3<i32>(sign(direction));
+    var side_dist = (sign(direction) * (vec3<f32>(cell) - origin) + (sign(direction) * 0.5) + 0.5) * delta_dist;
+    let dis_step = vec3<f32>(abs(ray_step));
+
+    let normal_dir = ceil(-direction) - direction;
+
+    var result = VoxelHit();
+
+    for (var i = 0; i < max_steps; i++) {
+        result.steps += 1;
+
+        if side_dist.x < side_dist.y && side_dist.x < side_dist.z {
+            side_dist.x += delta_dist.x;
+            cell.x += ray_step.x;
+            result.normal = vec3(normal_dir.x, 0.0, 0.0);
+            result.distance += dis_step.x;
+        } else if side_dist.y < side_dist.z {
+            side_dist.y += delta_dist.y;
+            cell.y += ray_step.y;
+            result.normal = vec3(0.0, normal_dir.y, 0.0);
+            result.distance += dis_step.y;
+        } else {
+            side_dist.z += delta_dist.z;
+            cell.z += ray_step.z;
+            result.normal = vec3(0.0, 0.0, normal_dir.z);
+            result.distance += dis_step.z;
+        }
+
+        if voxel_is_solid(cell) {
+            result.hit = true;
+            break;
+        }
+    }
+
+    result.cell = cell;
+    result.position = vec3<f32>(cell) + result.normal;
+    return result;
+}
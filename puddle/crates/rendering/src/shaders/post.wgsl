@@ -0,0 +1,134 @@
+// the built in post process effects, `post_common.wgsl` is put in front of this
+
+@fragment
+fn fs_blit(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    return sample_input(in.uv);
+}
+
+// params.x is the threshold, writes a half sized image of the bright parts
+@fragment
+fn fs_bright(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = sample_input(in.uv).rgb;
+    let brightness = max(color.r, max(color.g, color.b));
+    let contribution = max(brightness - effect.params.x, 0.0) / max(brightness, 0.0001);
+    return vec4(color * contribution, 1.0);
+}
+
+// params.xy is the direction of the blur
+@fragment
+fn fs_blur(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    var weights = array<f32, 5>(0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+    let blur_step = effect.params.xy * effect.texel_size.xy;
+
+    var color = sample_input(in.uv).rgb * weights[0];
+    for (var i = 1; i < 5; i++) {
+        let offset = blur_step * f32(i);
+        color += sample_input(in.uv + offset).rgb * weights[i];
+        color += sample_input(in.uv - offset).rgb * weights[i];
+    }
+    return vec4(color, 1.0);
+}
+
+// params.x is the intensity, the blurred bright parts are in the extra texture
+@fragment
+fn fs_bloom(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = sample_input(in.uv);
+    let bloom = textureSampleLevel(extra_texture, input_sampler, in.uv, 0.0).rgb;
+    return vec4(color.rgb + bloom * effect.params.x, color.a);
+}
+
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    // fitted curve by krzysztof narkowicz
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), vec3(0.0), vec3(1.0));
+}
+
+// params.x is the exposure scale, params.y the operator: 0 none, 1 reinhard, 2 aces
+@fragment
+fn fs_tonemap(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let input = sample_input(in.uv);
+    let color = input.rgb * effect.params.x;
+
+    var mapped = clamp(color, vec3(0.0), vec3(1.0));
+    if effect.params.y == 1.0 {
+        mapped = color / (color + vec3(1.0));
+    } else if effect.params.y == 2.0 {
+        mapped = aces(color);
+    }
+    return vec4(mapped, input.a);
+}
+
+// params.x is the lut size, params.y the strength.
+// the lut is a strip of `size` tiles, red goes along a tile, green down and blue selects the tile
+@fragment
+fn fs_color_grading(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let input = sample_input(in.uv);
+    let color = clamp(input.rgb, vec3(0.0), vec3(1.0));
+    let size = effect.params.x;
+
+    let blue = color.b * (size - 1.0);
+    let tile = floor(blue);
+    let next_tile = min(tile + 1.0, size - 1.0);
+
+    let x = (color.r * (size - 1.0) + 0.5) / (size * size);
+    let y = (color.g * (size - 1.0) + 0.5) / size;
+
+    let a = textureSampleLevel(extra_texture, input_sampler, vec2(x + tile / size, y), 0.0).rgb;
+    let b = textureSampleLevel(extra_texture, input_sampler, vec2(x + next_tile / size, y), 0.0).rgb;
+    let graded = mix(a, b, blue - tile);
+
+    return vec4(mix(input.rgb, graded, effect.params.y), input.a);
+}
+
+@fragment
+fn fs_fxaa(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let texel = effect.texel_size.xy;
+
+    let rgb_nw = sample_input(in.uv + vec2(-1.0, -1.0) * texel).rgb;
+    let rgb_ne = sample_input(in.uv + vec2(1.0, -1.0) * texel).rgb;
+    let rgb_sw = sample_input(in.uv + vec2(-1.0, 1.0) * texel).rgb;
+    let rgb_se = sample_input(in.uv + vec2(1.0, 1.0) * texel).rgb;
+    let center = sample_input(in.uv);
+
+    let luma_nw = luminance(rgb_nw);
+    let luma_ne = luminance(rgb_ne);
+    let luma_sw = luminance(rgb_sw);
+    let luma_se = luminance(rgb_se);
+    let luma_m = luminance(center.rgb);
+
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    var dir = vec2(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+
+    let dir_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * (1.0 / 8.0), 1.0 / 128.0);
+    let rcp_dir_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+    dir = clamp(dir * rcp_dir_min, vec2(-8.0), vec2(8.0)) * texel;
+
+    let rgb_a = 0.5 * (sample_input(in.uv + dir * (1.0 / 3.0 - 0.5)).rgb
+        + sample_input(in.uv + dir * (2.0 / 3.0 - 0.5)).rgb);
+    let rgb_b = rgb_a * 0.5 + 0.25 * (sample_input(in.uv - dir * 0.5).rgb
+        + sample_input(in.uv + dir * 0.5).rgb);
+
+    let luma_b = luminance(rgb_b);
+    if luma_b < luma_min || luma_b > luma_max {
+        return vec4(rgb_a, center.a);
+    }
+    return vec4(rgb_b, center.a);
+}
+
+// params.x is the intensity, params.y the smoothness
+@fragment
+fn fs_vignette(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = sample_input(in.uv);
+    let distance = length(in.uv - vec2(0.5)) * 1.4142;
+    let vignette = 1.0 - effect.params.x * smoothstep(1.0 - effect.params.y, 1.0, distance);
+    return vec4(color.rgb * vignette, color.a);
+}
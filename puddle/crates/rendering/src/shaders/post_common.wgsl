@@ -0,0 +1,42 @@
+// shared by every post process effect, custom effects get this put in front of their source
+
+struct FullscreenOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// one triangle that covers the screen, no vertex buffer needed
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> FullscreenOutput {
+    let uv = vec2(f32((index << 1u) & 2u), f32(index & 2u));
+
+    var out: FullscreenOutput;
+    out.clip_position = vec4(uv * vec2(2.0, -2.0) + vec2(-1.0, 1.0), 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+struct EffectUniform {
+    // depends on the effect
+    params: vec4<f32>,
+    // 1 / width, 1 / height, width, height of the input
+    texel_size: vec4<f32>,
+};
+
+@group(0) @binding(0)
+    var input_texture: texture_2d<f32>;
+@group(0) @binding(1)
+    var input_sampler: sampler;
+@group(0) @binding(2)
+    var<uniform> effect: EffectUniform;
+// the lut for color grading, the blurred image for bloom and the hdr scene for every other effect
+@group(0) @binding(3)
+    var extra_texture: texture_2d<f32>;
+
+fn sample_input(uv: vec2<f32>) -> vec4<f32> {
+    return textureSampleLevel(input_texture, input_sampler, uv, 0.0);
+}
+
+fn luminance(color: vec3<f32>) -> f32 {
+    return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
@@ -0,0 +1,35 @@
+#import puddle::camera
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) direction: vec3<f32>,
+};
+
+@group(1) @binding(0)
+    var sky_texture: texture_cube<f32>;
+@group(1) @binding(1)
+    var sky_sampler: sampler;
+
+@vertex
+fn vs_main(model: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+
+    // a direction instead of a point, so the box moves with the camera
+    let clip = camera.view_proj * vec4<f32>(model.position, 0.0);
+    // on the far plane. the depth test is off, so it works with reverse z as well
+    out.clip_position = clip.xyww;
+    out.direction = model.position;
+
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // cube textures are left handed
+    let direction = in.direction * vec3(1.0, 1.0, -1.0);
+    return textureSample(sky_texture, sky_sampler, direction);
+}
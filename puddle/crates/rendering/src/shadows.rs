@@ -0,0 +1,625 @@
+use std::ops::Range;
+
+use cgmath::prelude::*;
+use cgmath::{Deg, Matrix4, Point3, Vector3};
+use legion::{Entity, EntityStore, IntoQuery};
+use wgpu::util::DeviceExt;
+
+use crate::camera::OPENGL_TO_WGPU_MATRIX;
+use crate::render_graph::{NodeContext, NodeDesc, RenderNode, SHADOW_MAP};
+use crate::{
+    cull_instances, Camera, CameraUniform, Frustum, InstanceRaw, Material, Projection, Renderer,
+};
+
+/// the shadow map layers used by the cascades of the first shadow casting `DirectionalLight`
+pub const MAX_CASCADES: usize = 4;
+/// shadow casting `SpotLight`s after this many don't get shadows
+pub const MAX_SPOT_SHADOWS: usize = 4;
+pub(crate) const SHADOW_LAYERS: usize = MAX_CASCADES + MAX_SPOT_SHADOWS;
+
+/// the bias of the spot light shadow maps, their depth isn't linear so a constant
+/// bias in the shader would be far too large close to the light and too small far from it
+pub(crate) const SPOT_SHADOW_BIAS: wgpu::DepthBiasState = wgpu::DepthBiasState {
+    constant: 2,
+    slope_scale: 2.0,
+    clamp: 0.0,
+};
+
+/// tunes the shadows of every light, a resource
+#[derive(Debug, Clone, Copy)]
+pub struct ShadowSettings {
+    /// width and height of every shadow map
+    pub resolution: u32,
+    /// how many cascades the sun gets, up to `MAX_CASCADES`.
+    /// they fit the frustum of the `Camera` resource only, every `CameraView` samples the same
+    /// cascades and gets no sun shadows where it looks outside of that frustum
+    pub cascades: usize,
+    /// no sun shadows past this distance from the camera
+    pub max_distance: f32,
+    /// between 0 and 1, higher puts more cascades close to the camera
+    pub cascade_split_lambda: f32,
+    /// subtracted from the depth of the sun cascades before comparing.
+    /// spot lights use a slope scaled bias while their maps are rendered instead
+    pub depth_bias: f32,
+    /// moves the sampled position along the surface normal, in world units
+    pub normal_bias: f32,
+    /// percentage closer filtering samples `(2 * radius + 1)^2` texels, 0 is a single hard sample
+    pub pcf_radius: u32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            resolution: 2048,
+            cascades: 4,
+            max_distance: 100.0,
+            cascade_split_lambda: 0.6,
+            depth_bias: 0.002,
+            normal_bias: 0.02,
+            pcf_radius: 1,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowUniform {
+    light_view_proj: [[[f32; 4]; 4]; SHADOW_LAYERS],
+    /// the far distance of every cascade
+    cascade_splits: [f32; 4],
+    /// depth bias, normal bias, pcf radius, texel size
+    params: [f32; 4],
+    cascade_count: u32,
+    _padding: [u32; 3],
+}
+
+/// one depth texture array for every shadow casting light, bound in group 3 next to the lights:
+/// ```wgsl
+/// struct ShadowUniform {
+///     light_view_proj: array<mat4x4<f32>, 8>,
+///     cascade_splits: vec4<f32>,
+///     params: vec4<f32>, // depth bias, normal bias, pcf radius, texel size
+///     cascade_count: u32,
+/// };
+/// @group(3) @binding(1) var<uniform> shadows: ShadowUniform;
+/// @group(3) @binding(2) var shadow_map: texture_depth_2d_array;
+/// @group(3) @binding(3) var shadow_sampler: sampler_comparison;
+/// ```
+/// `Light::cone.z` is the first layer of a light, or -1 without shadows
+pub(crate) struct ShadowMaps {
+    pub texture: texture::Texture,
+    pub layer_views: Vec<wgpu::TextureView>,
+    pub resolution: u32,
+    pub uniform_buffer: wgpu::Buffer,
+    /// a camera bind group for every layer
+    layer_cameras: Vec<(wgpu::Buffer, wgpu::BindGroup)>,
+    /// the layers rendered this frame and their light matrices
+    pub active: Vec<(usize, Matrix4<f32>)>,
+    pub instance_buffer: wgpu::Buffer,
+    pub instance_capacity: usize,
+}
+
+impl ShadowMaps {
+    pub fn new(
+        device: &wgpu::Device,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+        resolution: u32,
+    ) -> Self {
+        let (texture, layer_views) = Self::create_texture(device, resolution);
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("shadow uniform buffer"),
+            size: std::mem::size_of::<ShadowUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let layer_cameras = (0..SHADOW_LAYERS)
+            .map(|_| {
+                let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("shadow camera buffer"),
+                    contents: bytemuck::cast_slice(&[CameraUniform::new()]),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                });
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("shadow_camera_bind_group"),
+                    layout: camera_bind_group_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }],
+                });
+                (buffer, bind_group)
+            })
+            .collect();
+
+        let instance_capacity = 1024;
+        let instance_buffer = Self::create_instance_buffer(device, instance_capacity);
+
+        Self {
+            texture,
+            layer_views,
+            resolution,
+            uniform_buffer,
+            layer_cameras,
+            active: vec![],
+            instance_buffer,
+            instance_capacity,
+        }
+    }
+
+    fn create_texture(
+        device: &wgpu::Device,
+        resolution: u32,
+    ) -> (texture::Texture, Vec<wgpu::TextureView>) {
+        let texture = texture::Texture::create_depth_texture_array(
+            device,
+            resolution,
+            SHADOW_LAYERS as u32,
+            "shadow maps",
+        );
+
+        let layer_views = (0..SHADOW_LAYERS as u32)
+            .map(|layer| {
+                texture.texture.create_view(&wgpu::TextureViewDescriptor {
+                    label: Some("shadow map layer"),
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        (texture, layer_views)
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("shadow instance buffer"),
+            size: (capacity * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// returns true when the textures were recreated and bind groups using them need to be too
+    pub fn set_resolution(&mut self, device: &wgpu::Device, resolution: u32) -> bool {
+        if resolution == self.resolution {
+            return false;
+        }
+
+        let (texture, layer_views) = Self::create_texture(device, resolution);
+        self.texture = texture;
+        self.layer_views = layer_views;
+        self.resolution = resolution;
+        true
+    }
+
+    /// uploads the light matrices of this frame
+    pub fn update(
+        &mut self,
+        queue: &wgpu::Queue,
+        active: Vec<(usize, Matrix4<f32>)>,
+        cascade_splits: [f32; 4],
+        cascade_count: usize,
+        settings: &ShadowSettings,
+    ) {
+        let mut uniform = ShadowUniform {
+            light_view_proj: [Matrix4::identity().into(); SHADOW_LAYERS],
+            cascade_splits,
+            params: [
+                settings.depth_bias,
+                settings.normal_bias,
+                settings.pcf_radius as f32,
+                1.0 / self.resolution as f32,
+            ],
+            cascade_count: cascade_count as u32,
+            _padding: [0; 3],
+        };
+
+        for (layer, matrix) in active.iter() {
+            uniform.light_view_proj[*layer] = (*matrix).into();
+
+            let eye = matrix
+                .invert()
+                .map(|x| Point3::from_homogeneous(x * cgmath::Vector4::new(0.0, 0.0, 0.0, 1.0)))
+                .unwrap_or_else(Point3::origin);
+            queue.write_buffer(
+                &self.layer_cameras[*layer].0,
+                0,
+                bytemuck::cast_slice(&[CameraUniform::from_view_proj(*matrix, eye)]),
+            );
+        }
+
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+        self.active = active;
+    }
+}
+
+fn light_up(direction: Vector3<f32>) -> Vector3<f32> {
+    if direction.y.abs() > 0.99 {
+        Vector3::unit_z()
+    } else {
+        Vector3::unit_y()
+    }
+}
+
+/// the split distances of the cascades, the first one starts at `znear`
+pub(crate) fn cascade_splits(camera: &Camera, settings: &ShadowSettings) -> Vec<f32> {
+    let count = settings.cascades.clamp(1, MAX_CASCADES);
+    let near = camera.znear.max(0.001);
+    let far = match camera.projection {
+        Projection::InfiniteReverseZ => settings.max_distance,
+        _ => camera.zfar.min(settings.max_distance),
+    }
+    .max(near + 0.001);
+
+    (1..=count)
+        .map(|i| {
+            let p = i as f32 / count as f32;
+            let log = near * (far / near).powf(p);
+            let uniform = near + (far - near) * p;
+            settings.cascade_split_lambda * log + (1.0 - settings.cascade_split_lambda) * uniform
+        })
+        .collect()
+}
+
+/// the corners of the part of the camera frustum between two view distances
+fn frustum_slice(camera: &Camera, near: f32, far: f32) -> [Point3<f32>; 8] {
+    let forward = (camera.target - camera.eye).normalize();
+    let right = forward.cross(camera.up).normalize();
+    let up = right.cross(forward);
+
+    let half_size = |distance: f32| match camera.projection {
+        Projection::Orthographic { height } => (height / 2.0 * camera.aspect, height / 2.0),
+        _ => {
+            let half_h = distance * (cgmath::Rad::from(Deg(camera.fovy)).0 / 2.0).tan();
+            (half_h * camera.aspect, half_h)
+        }
+    };
+
+    let mut corners = [Point3::origin(); 8];
+    for (i, distance) in [near, far].iter().enumerate() {
+        let (half_w, half_h) = half_size(*distance);
+        let center = camera.eye + forward * *distance;
+        corners[i * 4] = center - right * half_w - up * half_h;
+        corners[i * 4 + 1] = center + right * half_w - up * half_h;
+        corners[i * 4 + 2] = center + right * half_w + up * half_h;
+        corners[i * 4 + 3] = center - right * half_w + up * half_h;
+    }
+    corners
+}
+
+/// an orthographic light matrix around a frustum slice, snapped to texels so shadows don't shimmer
+pub(crate) fn cascade_matrix(
+    camera: &Camera,
+    direction: Vector3<f32>,
+    near: f32,
+    far: f32,
+    settings: &ShadowSettings,
+) -> Matrix4<f32> {
+    let corners = frustum_slice(camera, near, far);
+    let center = Point3::centroid(&corners);
+    let radius = corners
+        .iter()
+        .map(|x| x.distance(center))
+        .fold(0.0, f32::max);
+    // a fixed size per cascade, so the texel snapping stays valid when the camera turns
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    let direction = direction.normalize();
+    let view = Matrix4::look_to_rh(Point3::origin(), direction, light_up(direction));
+
+    let texel = radius * 2.0 / settings.resolution as f32;
+    let mut center = view.transform_point(center);
+    center.x = (center.x / texel).floor() * texel;
+    center.y = (center.y / texel).floor() * texel;
+
+    // catch casters between the sun and the slice
+    let proj = cgmath::ortho(
+        center.x - radius,
+        center.x + radius,
+        center.y - radius,
+        center.y + radius,
+        -center.z - radius - settings.max_distance,
+        -center.z + radius,
+    );
+
+    OPENGL_TO_WGPU_MATRIX * proj * view
+}
+
+pub(crate) fn spot_matrix(
+    position: Vector3<f32>,
+    direction: Vector3<f32>,
+    outer_angle: Deg<f32>,
+    range: f32,
+) -> Matrix4<f32> {
+    let direction = direction.normalize();
+    let view = Matrix4::look_to_rh(Point3::from_vec(position), direction, light_up(direction));
+    let fov = Deg((outer_angle.0 * 2.0).clamp(1.0, 170.0));
+    let proj = cgmath::perspective(fov, 1.0, 0.05, range.max(0.1));
+
+    OPENGL_TO_WGPU_MATRIX * proj * view
+}
+
+struct ShadowLayer {
+    layer: usize,
+    instance_ranges: Vec<Range<u32>>,
+}
+
+/// renders every `Material` that tests and writes depth into the shadow maps of the lights.
+/// the vertex shaders of shadow casters can only use bind groups 0 and 1, other materials cast no shadows
+pub struct ShadowPassNode {
+    layers: Vec<ShadowLayer>,
+    materials: Vec<Entity>,
+}
+
+impl ShadowPassNode {
+    pub const NAME: &'static str = "shadows";
+
+    pub fn new() -> Self {
+        Self {
+            layers: vec![],
+            materials: vec![],
+        }
+    }
+}
+
+impl Default for ShadowPassNode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RenderNode for ShadowPassNode {
+    fn desc(&self) -> NodeDesc {
+        NodeDesc::new(Self::NAME).writes(SHADOW_MAP)
+    }
+
+    fn prepare(
+        &mut self,
+        world: &mut legion::World,
+        resources: &legion::Resources,
+        renderer: &mut Renderer,
+    ) {
+        self.layers.clear();
+
+        let mut materials = <(Entity, &Material)>::query();
+        let materials: Vec<(&Entity, &Material)> = materials
+            .iter(world)
+            .filter(|(_, material)| material.pipelines.shadow.is_some())
+            .collect();
+        self.materials = materials.iter().map(|(entity, _)| **entity).collect();
+
+        let mut instances: Vec<InstanceRaw> = vec![];
+
+        for (layer, matrix) in renderer.shadows.active.iter() {
+            let frustum = Frustum::from_view_projection(matrix);
+            let instance_ranges = materials
+                .iter()
+                .map(|(_, material)| {
+                    let start = instances.len() as u32;
+                    let bounds = if material.frustum_culling {
+                        material.bounds.as_ref()
+                    } else {
+                        None
+                    };
+                    cull_instances(&frustum, bounds, &material.instances, &mut instances);
+                    start..instances.len() as u32
+                })
+                .collect();
+
+            self.layers.push(ShadowLayer {
+                layer: *layer,
+                instance_ranges,
+            });
+        }
+
+        let shadows = &mut renderer.shadows;
+        if instances.len() > shadows.instance_capacity {
+            shadows.instance_capacity = instances.len().next_power_of_two();
+            shadows.instance_buffer =
+                ShadowMaps::create_instance_buffer(&renderer.device, shadows.instance_capacity);
+        }
+        if !instances.is_empty() {
+            renderer
+                .queue
+                .write_buffer(&shadows.instance_buffer, 0, bytemuck::cast_slice(&instances));
+        }
+    }
+
+    fn record(&self, encoder: &mut wgpu::CommandEncoder, ctx: &NodeContext) {
+        let shadows = &ctx.renderer.shadows;
+
+        let materials: Vec<Option<&Material>> = self
+            .materials
+            .iter()
+            .map(|entity| {
+                ctx.world
+                    .entry_ref(*entity)
+                    .ok()
+                    .and_then(|x| x.into_component::<Material>().ok())
+            })
+            .collect();
+
+        for layer in self.layers.iter() {
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("shadow pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &shadows.layer_views[layer.layer],
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            rpass.set_vertex_buffer(1, shadows.instance_buffer.slice(..));
+            rpass.set_bind_group(0, &shadows.layer_cameras[layer.layer].1, &[]);
+
+            let spot = layer.layer >= MAX_CASCADES;
+
+            for (material, instance_range) in materials.iter().zip(layer.instance_ranges.iter()) {
+                let material = match material {
+                    Some(r) => r,
+                    None => continue,
+                };
+                let pipeline = if spot {
+                    &material.pipelines.spot_shadow
+                } else {
+                    &material.pipelines.shadow
+                };
+                let pipeline = match pipeline {
+                    Some(r) => r,
+                    None => continue,
+                };
+                if instance_range.is_empty() {
+                    continue;
+                }
+
+                rpass.set_pipeline(pipeline);
+                rpass.set_vertex_buffer(0, material.vertex_buffer.slice(..));
+                rpass.set_index_buffer(material.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+                rpass.set_bind_group(1, &material.bind_groups, &[]);
+                rpass.draw_indexed(
+                    0..material.indecies.len() as u32,
+                    0,
+                    instance_range.clone(),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera(projection: Projection) -> Camera {
+        Camera {
+            eye: (0.0, 5.0, 10.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: Vector3::unit_y(),
+            projection,
+            aspect: 16.0 / 9.0,
+            fovy: 60.0,
+            znear: 0.1,
+            zfar: 50.0,
+            auto_aspect: false,
+        }
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn splits_grow_up_to_the_shadow_distance() {
+        let settings = ShadowSettings {
+            max_distance: 30.0,
+            ..Default::default()
+        };
+        let splits = cascade_splits(&camera(Projection::Perspective), &settings);
+
+        assert_eq!(splits.len(), settings.cascades);
+        assert!(splits.windows(2).all(|x| x[0] < x[1]), "{:?}", splits);
+        assert_close(*splits.last().unwrap(), 30.0);
+
+        // the far plane is closer than the shadow distance
+        let splits = cascade_splits(&camera(Projection::Perspective), &ShadowSettings::default());
+        assert_close(*splits.last().unwrap(), 50.0);
+
+        // reverse z has no far plane
+        let splits = cascade_splits(
+            &camera(Projection::InfiniteReverseZ),
+            &ShadowSettings::default(),
+        );
+        assert_close(*splits.last().unwrap(), 100.0);
+    }
+
+    #[test]
+    fn the_lambda_blends_uniform_and_logarithmic_splits() {
+        let mut camera = camera(Projection::Perspective);
+        camera.znear = 1.0;
+        camera.zfar = 16.0;
+        let settings = |cascade_split_lambda| ShadowSettings {
+            cascades: 4,
+            cascade_split_lambda,
+            ..Default::default()
+        };
+
+        let uniform = cascade_splits(&camera, &settings(0.0));
+        for (split, expected) in uniform.iter().zip([4.75, 8.5, 12.25, 16.0]) {
+            assert_close(*split, expected);
+        }
+        let logarithmic = cascade_splits(&camera, &settings(1.0));
+        for (split, expected) in logarithmic.iter().zip([2.0, 4.0, 8.0, 16.0]) {
+            assert_close(*split, expected);
+        }
+    }
+
+    #[test]
+    fn the_cascade_count_is_clamped() {
+        let camera = camera(Projection::Perspective);
+        let count = |cascades| {
+            let settings = ShadowSettings {
+                cascades,
+                ..Default::default()
+            };
+            cascade_splits(&camera, &settings).len()
+        };
+
+        assert_eq!(count(0), 1);
+        assert_eq!(count(MAX_CASCADES + 3), MAX_CASCADES);
+    }
+
+    #[test]
+    fn a_cascade_holds_its_whole_frustum_slice() {
+        let settings = ShadowSettings::default();
+        for projection in [
+            Projection::Perspective,
+            Projection::Orthographic { height: 10.0 },
+        ] {
+            let camera = camera(projection);
+            let direction = Vector3::new(-1.0, -2.0, 0.5);
+            let matrix = cascade_matrix(&camera, direction, 2.0, 12.0, &settings);
+
+            for corner in frustum_slice(&camera, 2.0, 12.0) {
+                let clip = matrix * corner.to_homogeneous();
+                let ndc = clip.truncate() / clip.w;
+                assert!(ndc.x.abs() <= 1.0 && ndc.y.abs() <= 1.0, "{:?}", ndc);
+                assert!((0.0..=1.0).contains(&ndc.z), "{:?}", ndc);
+            }
+        }
+    }
+
+    #[test]
+    fn a_cascade_keeps_its_size_and_snaps_to_texels_when_the_camera_moves() {
+        let settings = ShadowSettings {
+            resolution: 1024,
+            ..Default::default()
+        };
+        let direction = Vector3::new(0.0, -1.0, 0.0);
+        let still = camera(Projection::Perspective);
+        let mut turned = still;
+        turned.target = (3.0, 0.0, 1.0).into();
+        let mut moved = still;
+        moved.eye.x += 0.01;
+        moved.target.x += 0.01;
+
+        let matrix = cascade_matrix(&still, direction, 0.1, 10.0, &settings);
+        let turned = cascade_matrix(&turned, direction, 0.1, 10.0, &settings);
+        let moved = cascade_matrix(&moved, direction, 0.1, 10.0, &settings);
+        assert_close(matrix.x.x, turned.x.x);
+
+        // the light space offset between the two cascades is a whole number of texels
+        let texel = 2.0 / settings.resolution as f32;
+        let offset = (moved.w.x - matrix.w.x) / texel;
+        assert!((offset - offset.round()).abs() < 1e-2, "{}", offset);
+    }
+}
@@ -0,0 +1,294 @@
+use assets::Handle;
+use legion::{Entity, IntoQuery};
+
+use application::log::error;
+
+use crate::{
+    CameraBindGroupLayout, Material, MaterialResource, ModelMatrix, PuddleBindGroupEntry,
+    RenderQueue, Renderer, Vertex,
+};
+
+const SKYBOX_WGSL: &str = include_str!("shaders/skybox.wgsl");
+
+/// draws a cube texture behind everything else, load it with `CubeTextureLoader`.
+/// the material is added to the entity of the skybox, replace `texture` to change the sky
+pub struct Skybox {
+    pub texture: Handle<texture::Texture>,
+    /// the texture the material was last built or bound with
+    tried: TriedTexture<texture::Texture>,
+}
+
+impl Skybox {
+    pub fn new(texture: Handle<texture::Texture>) -> Self {
+        Self {
+            texture,
+            tried: TriedTexture { tried: None },
+        }
+    }
+}
+
+fn entries(texture: &Handle<texture::Texture>) -> Vec<PuddleBindGroupEntry> {
+    vec![
+        PuddleBindGroupEntry {
+            name: "sky_texture".to_string(),
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::Cube,
+                multisampled: false,
+            },
+            resource: MaterialResource::TextureAsset(texture.clone()),
+            visibility: wgpu::ShaderStages::FRAGMENT,
+        },
+        PuddleBindGroupEntry {
+            name: "sky_sampler".to_string(),
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            resource: MaterialResource::TextureAssetSampler(texture.clone()),
+            visibility: wgpu::ShaderStages::FRAGMENT,
+        },
+    ]
+}
+
+fn build_material(
+    texture: &Handle<texture::Texture>,
+    renderer: &mut Renderer,
+    camera_layout: &CameraBindGroupLayout,
+) -> Option<Material> {
+    let mut material = match Material::from_wgsl("skybox.wgsl", SKYBOX_WGSL)
+        .label("skybox")
+        .entries(entries(texture))
+        .depth_test(false)
+        // seen from the inside
+        .cull_mode(None)
+        .queue(RenderQueue::Background)
+        .build(renderer, camera_layout)
+    {
+        Ok(r) => r,
+        Err(e) => {
+            error!("{}", e);
+            return None;
+        }
+    };
+
+    // the shader keeps the box around the camera
+    material.frustum_culling = false;
+
+    let mut vertices = Vec::with_capacity(8);
+    for i in 0..8 {
+        vertices.push(Vertex {
+            position: [
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+                if i & 4 == 0 { -1.0 } else { 1.0 },
+            ],
+            uv: [0.0, 0.0],
+            normal: [0.0, 0.0, 0.0],
+        });
+    }
+    let indices = vec![
+        0, 1, 3, 3, 2, 0, // -z
+        4, 6, 7, 7, 5, 4, // +z
+        0, 2, 6, 6, 4, 0, // -x
+        1, 5, 7, 7, 3, 1, // +x
+        0, 4, 5, 5, 1, 0, // -y
+        2, 3, 7, 7, 6, 2, // +y
+    ];
+    material.add_mesh(vertices, indices, ModelMatrix::default());
+
+    Some(material)
+}
+
+/// what a skybox needs this frame
+#[derive(Debug, PartialEq, Eq)]
+enum SkyboxUpdate {
+    None,
+    Build,
+    Rebind,
+}
+
+/// a texture and the version of it a material was built or bound with, whether that worked or not
+struct TriedTexture<T> {
+    tried: Option<(Handle<T>, u32)>,
+}
+
+impl<T> TriedTexture<T> {
+    /// what to do with `texture` this frame, it counts as tried from now on.
+    /// a material that failed to build is only built again once the texture is reloaded or replaced
+    fn next(&mut self, texture: &Handle<T>, has_material: bool) -> SkyboxUpdate {
+        // a texture that is still loading binds a 2d placeholder, which the cube layout rejects
+        if !texture.is_loaded() {
+            return SkyboxUpdate::None;
+        }
+
+        let attempt = (texture.clone(), texture.version());
+        let update = match &self.tried {
+            _ if !has_material => {
+                if self.tried.as_ref() == Some(&attempt) {
+                    SkyboxUpdate::None
+                } else {
+                    SkyboxUpdate::Build
+                }
+            }
+            // the material follows reloads of the texture it binds
+            Some((tried, _)) if tried == texture => SkyboxUpdate::None,
+            _ => SkyboxUpdate::Rebind,
+        };
+        self.tried = Some(attempt);
+        update
+    }
+}
+
+/// builds the material of new skyboxes and rebinds the ones whose texture was replaced,
+/// both wait until the texture is loaded. failures are logged once per texture version
+pub(crate) fn update_skyboxes(world: &mut legion::World, resources: &mut legion::Resources) {
+    let mut renderer = match resources.get_mut::<Renderer>() {
+        Some(r) => r,
+        None => {
+            return;
+        }
+    };
+    let camera_layout = match resources.get::<CameraBindGroupLayout>() {
+        Some(r) => r,
+        None => {
+            return;
+        }
+    };
+
+    let mut new: Vec<Entity> = vec![];
+    for (entity, skybox, material) in
+        <(Entity, &mut Skybox, Option<&mut Material>)>::query().iter_mut(world)
+    {
+        let update = skybox.tried.next(&skybox.texture, material.is_some());
+        let material = match (update, material) {
+            (SkyboxUpdate::Rebind, Some(r)) => r,
+            (SkyboxUpdate::Build, _) => {
+                new.push(*entity);
+                continue;
+            }
+            _ => continue,
+        };
+
+        let result = material
+            .set_resource(
+                "sky_texture",
+                MaterialResource::TextureAsset(skybox.texture.clone()),
+            )
+            .and_then(|_| {
+                material.set_resource(
+                    "sky_sampler",
+                    MaterialResource::TextureAssetSampler(skybox.texture.clone()),
+                )
+            });
+        if let Err(e) = result {
+            error!("{}", e);
+        }
+    }
+
+    for entity in new {
+        let mut entry = match world.entry(entity) {
+            Some(r) => r,
+            None => continue,
+        };
+        let texture = match entry.get_component::<Skybox>() {
+            Ok(skybox) => skybox.texture.clone(),
+            Err(_) => continue,
+        };
+
+        if let Some(material) = build_material(&texture, &mut renderer, &camera_layout) {
+            entry.add_component(material);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ObjLoader, ObjModel};
+    use assets::AssetServer;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, Instant};
+
+    const TRIANGLE: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n";
+
+    /// an asset server for an empty folder, models stand in for the cube textures
+    fn server(name: &str) -> (AssetServer, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("puddle_rendering_skybox_{}", name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let server = AssetServer::new(&dir);
+        server.register_loader(ObjLoader);
+        (server, dir)
+    }
+
+    fn load(server: &AssetServer, dir: &Path, file: &str) -> Handle<ObjModel> {
+        std::fs::write(dir.join(file), TRIANGLE).unwrap();
+        server.load(file)
+    }
+
+    /// runs `finish_loads` like the `AssetPlugin` does every frame until the handle has `version`
+    fn wait_for_version(server: &AssetServer, handle: &Handle<ObjModel>, version: u32) {
+        let resources = legion::Resources::default();
+        let start = Instant::now();
+        while handle.version() != version {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "the model took too long to load"
+            );
+            server.finish_loads(&resources);
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn builds_once_the_texture_is_loaded() {
+        let (server, dir) = server("build");
+        let texture = load(&server, &dir, "sky.obj");
+        let mut tried = TriedTexture { tried: None };
+
+        assert_eq!(tried.next(&texture, false), SkyboxUpdate::None);
+
+        wait_for_version(&server, &texture, 1);
+        assert_eq!(tried.next(&texture, false), SkyboxUpdate::Build);
+        // the material was added
+        assert_eq!(tried.next(&texture, true), SkyboxUpdate::None);
+
+        // it binds the reloaded texture by itself
+        server.reload("sky.obj");
+        wait_for_version(&server, &texture, 2);
+        assert_eq!(tried.next(&texture, true), SkyboxUpdate::None);
+    }
+
+    #[test]
+    fn a_failed_build_waits_for_a_reload() {
+        let (server, dir) = server("failed");
+        let texture = load(&server, &dir, "sky.obj");
+        wait_for_version(&server, &texture, 1);
+        let mut tried = TriedTexture { tried: None };
+
+        assert_eq!(tried.next(&texture, false), SkyboxUpdate::Build);
+        // the build failed, there is still no material
+        assert_eq!(tried.next(&texture, false), SkyboxUpdate::None);
+        assert_eq!(tried.next(&texture, false), SkyboxUpdate::None);
+
+        server.reload("sky.obj");
+        wait_for_version(&server, &texture, 2);
+        assert_eq!(tried.next(&texture, false), SkyboxUpdate::Build);
+        assert_eq!(tried.next(&texture, false), SkyboxUpdate::None);
+    }
+
+    #[test]
+    fn a_replaced_texture_is_bound_once_it_is_loaded() {
+        let (server, dir) = server("replace");
+        let first = load(&server, &dir, "first.obj");
+        wait_for_version(&server, &first, 1);
+        let mut tried = TriedTexture { tried: None };
+        assert_eq!(tried.next(&first, false), SkyboxUpdate::Build);
+
+        let second = load(&server, &dir, "second.obj");
+        assert_eq!(tried.next(&second, true), SkyboxUpdate::None);
+
+        wait_for_version(&server, &second, 1);
+        assert_eq!(tried.next(&second, true), SkyboxUpdate::Rebind);
+        // a texture that doesn't fit the layout is not bound again every frame
+        assert_eq!(tried.next(&second, true), SkyboxUpdate::None);
+    }
+}
@@ -0,0 +1,86 @@
+use cgmath::{InnerSpace, Point3, Vector2};
+use rendering::{Camera, Projection};
+
+const SCREEN: Vector2<f32> = Vector2::new(800.0, 600.0);
+
+fn camera(projection: Projection) -> Camera {
+    let mut camera = Camera::default(SCREEN.x / SCREEN.y);
+    camera.eye = (1.0, 2.0, 5.0).into();
+    camera.target = (0.0, 0.0, 0.0).into();
+    camera.projection = projection;
+    camera
+}
+
+fn projections() -> [Projection; 3] {
+    [
+        Projection::Perspective,
+        Projection::Orthographic { height: 10.0 },
+        Projection::InfiniteReverseZ,
+    ]
+}
+
+fn assert_close(a: f32, b: f32, epsilon: f32) {
+    assert!((a - b).abs() < epsilon, "{} != {}", a, b);
+}
+
+#[test]
+fn points_on_a_ray_project_back_to_its_pixel() {
+    for projection in projections() {
+        let camera = camera(projection);
+
+        for pixel in [
+            Vector2::new(400.0, 300.0),
+            Vector2::new(10.0, 20.0),
+            Vector2::new(790.0, 550.0),
+        ] {
+            let ray = camera.screen_to_world_ray(pixel, SCREEN).unwrap();
+            assert_close(ray.direction.magnitude(), 1.0, 1e-5);
+
+            for distance in [1.0, 10.0, 100.0] {
+                let point = ray.origin + ray.direction * distance;
+                let screen = camera.world_to_screen(point, SCREEN).unwrap();
+
+                assert_close(screen.x, pixel.x, 0.05);
+                assert_close(screen.y, pixel.y, 0.05);
+            }
+        }
+    }
+}
+
+#[test]
+fn rays_through_projected_points_hit_them() {
+    for projection in projections() {
+        let camera = camera(projection);
+        let point = Point3::new(0.5, -0.25, 1.0);
+
+        let screen = camera.world_to_screen(point, SCREEN).unwrap();
+        let ray = camera
+            .screen_to_world_ray(Vector2::new(screen.x, screen.y), SCREEN)
+            .unwrap();
+
+        // the distance of the point to the ray
+        let to_point = point - ray.origin;
+        let along = to_point.dot(ray.direction);
+        assert!(along > 0.0);
+        assert_close((to_point - ray.direction * along).magnitude(), 0.0, 1e-3);
+    }
+}
+
+#[test]
+fn the_center_of_the_screen_looks_at_the_target() {
+    for projection in projections() {
+        let camera = camera(projection);
+
+        let screen = camera.world_to_screen(camera.target, SCREEN).unwrap();
+        assert_close(screen.x, 400.0, 0.05);
+        assert_close(screen.y, 300.0, 0.05);
+    }
+}
+
+#[test]
+fn points_behind_a_perspective_camera_are_not_on_screen() {
+    let camera = camera(Projection::Perspective);
+    let behind = Point3::new(2.0, 4.0, 10.0);
+
+    assert!(camera.world_to_screen(behind, SCREEN).is_none());
+}
@@ -0,0 +1,117 @@
+use cgmath::{Deg, Quaternion, Rotation3, Vector3};
+use rendering::{cull_instances, Aabb, Camera, Frustum, ModelMatrix, Projection};
+
+/// a camera at z = 5 looking at the origin
+fn camera() -> Camera {
+    let mut camera = Camera::default(1.0);
+    camera.eye = (0.0, 0.0, 5.0).into();
+    camera.target = (0.0, 0.0, 0.0).into();
+    camera
+}
+
+fn frustum(camera: &Camera) -> Frustum {
+    Frustum::from_view_projection(&camera.build_view_projection_matrix())
+}
+
+fn unit_box_at(x: f32, y: f32, z: f32) -> Aabb {
+    Aabb::new(
+        Vector3::new(x - 0.5, y - 0.5, z - 0.5),
+        Vector3::new(x + 0.5, y + 0.5, z + 0.5),
+    )
+}
+
+#[test]
+fn keeps_boxes_in_front_of_the_camera() {
+    let frustum = frustum(&camera());
+
+    assert!(frustum.intersects_aabb(&unit_box_at(0.0, 0.0, 0.0)));
+    assert!(frustum.intersects_aabb(&unit_box_at(1.0, 1.0, -20.0)));
+}
+
+#[test]
+fn culls_boxes_behind_and_beside_the_camera() {
+    let frustum = frustum(&camera());
+
+    assert!(!frustum.intersects_aabb(&unit_box_at(0.0, 0.0, 10.0)));
+    assert!(!frustum.intersects_aabb(&unit_box_at(-100.0, 0.0, 0.0)));
+    assert!(!frustum.intersects_aabb(&unit_box_at(0.0, 100.0, 0.0)));
+}
+
+#[test]
+fn keeps_boxes_crossing_a_plane() {
+    let frustum = frustum(&camera());
+
+    // the left plane is at x = -2.89 for z = 0
+    let aabb = Aabb::new(Vector3::new(-10.0, -0.5, -0.5), Vector3::new(-2.5, 0.5, 0.5));
+    assert!(frustum.intersects_aabb(&aabb));
+}
+
+#[test]
+fn culls_boxes_past_the_far_plane_unless_it_is_infinite() {
+    let mut camera = camera();
+    let far_box = unit_box_at(0.0, 0.0, -2000.0);
+
+    assert!(!frustum(&camera).intersects_aabb(&far_box));
+
+    camera.projection = Projection::InfiniteReverseZ;
+    assert!(frustum(&camera).intersects_aabb(&far_box));
+    assert!(!frustum(&camera).intersects_aabb(&unit_box_at(0.0, 0.0, 10.0)));
+}
+
+#[test]
+fn culls_against_orthographic_bounds() {
+    let mut camera = camera();
+    camera.projection = Projection::Orthographic { height: 10.0 };
+    let frustum = frustum(&camera);
+
+    assert!(frustum.intersects_aabb(&unit_box_at(4.0, 0.0, 0.0)));
+    assert!(!frustum.intersects_aabb(&unit_box_at(8.0, 0.0, 0.0)));
+}
+
+#[test]
+fn culls_spheres() {
+    let frustum = frustum(&camera());
+
+    assert!(frustum.intersects_sphere(&unit_box_at(0.0, 0.0, 0.0).bounding_sphere()));
+    assert!(!frustum.intersects_sphere(&unit_box_at(0.0, 0.0, 10.0).bounding_sphere()));
+}
+
+#[test]
+fn transformed_boxes_grow_with_rotation() {
+    let matrix = ModelMatrix {
+        position: Vector3::new(1.0, 0.0, 0.0),
+        rotation: Quaternion::from_angle_y(Deg(45.0)),
+        scale: Vector3::new(1.0, 1.0, 1.0),
+    }
+    .to_matrix();
+
+    let aabb = unit_box_at(0.0, 0.0, 0.0).transformed(&matrix);
+    let half_diagonal = 0.5 * 2.0_f32.sqrt();
+
+    assert!((aabb.min.x - (1.0 - half_diagonal)).abs() < 1e-5);
+    assert!((aabb.max.z - half_diagonal).abs() < 1e-5);
+    assert!((aabb.max.y - 0.5).abs() < 1e-5);
+}
+
+#[test]
+fn compacts_only_visible_instances() {
+    let frustum = frustum(&camera());
+    let bounds = unit_box_at(0.0, 0.0, 0.0);
+
+    let instances: Vec<ModelMatrix> = [0.0, 10.0, -10.0, 200.0]
+        .iter()
+        .map(|z| {
+            let mut matrix = ModelMatrix::default();
+            matrix.position.z = *z;
+            matrix
+        })
+        .collect();
+
+    let mut visible = vec![];
+    cull_instances(&frustum, Some(&bounds), &instances, &mut visible);
+    assert_eq!(visible.len(), 2);
+
+    let mut all = vec![];
+    cull_instances(&frustum, None, &instances, &mut all);
+    assert_eq!(all.len(), instances.len());
+}
@@ -0,0 +1,67 @@
+use std::path::{Path, PathBuf};
+
+use rendering::{load_gltf, Projection};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+fn assert_close(a: [f32; 3], b: [f32; 3]) {
+    for i in 0..3 {
+        assert!((a[i] - b[i]).abs() < 1e-5, "{:?} != {:?}", a, b);
+    }
+}
+
+#[test]
+fn reads_nodes_with_parents_before_children() {
+    let model = load_gltf(fixture("scene.gltf")).unwrap();
+
+    let names: Vec<&str> = model.nodes.iter().map(|x| x.name.as_str()).collect();
+    assert_eq!(names, vec!["Root", "Triangle", "Camera"]);
+
+    assert_eq!(model.nodes[0].parent, None);
+    assert_eq!(model.nodes[1].parent, Some(0));
+    assert_eq!(model.nodes[2].parent, Some(0));
+}
+
+#[test]
+fn combines_transforms_into_world_space() {
+    let model = load_gltf(fixture("scene.gltf")).unwrap();
+    let triangle = &model.nodes[1];
+
+    assert_close(triangle.transform.position.into(), [1.0, 2.0, 0.0]);
+    assert_close(triangle.transform.scale.into(), [2.0, 2.0, 2.0]);
+}
+
+#[test]
+fn reads_primitives_and_their_material() {
+    let model = load_gltf(fixture("scene.gltf")).unwrap();
+
+    assert!(model.nodes[0].primitives.is_empty());
+
+    let primitives = &model.nodes[1].primitives;
+    assert_eq!(primitives.len(), 1);
+
+    let primitive = &primitives[0];
+    assert_eq!(primitive.indices, vec![0, 1, 2]);
+    assert_eq!(primitive.vertices.len(), 3);
+    assert_eq!(primitive.vertices[1].position, [1.0, 0.0, 0.0]);
+    assert_eq!(primitive.base_color, [1.0, 0.5, 0.25, 1.0]);
+    assert_eq!(primitive.roughness, 0.5);
+    assert_eq!(primitive.base_color_image, None);
+}
+
+#[test]
+fn cameras_look_down_their_negative_z_axis() {
+    let model = load_gltf(fixture("scene.gltf")).unwrap();
+    let camera = model.nodes[2].camera.unwrap();
+
+    assert_close(camera.eye.into(), [1.0, 0.0, 0.0]);
+    assert_close(camera.target.into(), [0.0, 0.0, 0.0]);
+    assert_eq!(camera.znear, 0.5);
+    // no zfar in the file
+    assert_eq!(camera.projection, Projection::InfiniteReverseZ);
+    assert!(camera.auto_aspect);
+}
@@ -0,0 +1,56 @@
+use rendering::wgpu;
+use rendering::{view_clears, ViewClear, ViewTarget};
+
+const BLUE: wgpu::Color = wgpu::Color {
+    r: 0.0,
+    g: 0.0,
+    b: 1.0,
+    a: 1.0,
+};
+
+fn view(target: usize, viewport: [f32; 4], clear_color: wgpu::Color) -> ViewTarget {
+    ViewTarget {
+        target,
+        viewport,
+        clear_color,
+        depth_clear_value: 1.0,
+    }
+}
+
+#[test]
+fn a_minimap_over_the_main_camera_clears_the_depth_of_its_viewport() {
+    let main = view(ViewTarget::SWAPCHAIN, [0.0, 0.0, 800.0, 600.0], BLUE);
+    let mut minimap = view(ViewTarget::SWAPCHAIN, [600.0, 0.0, 200.0, 200.0], wgpu::Color::RED);
+    minimap.depth_clear_value = 0.0;
+
+    assert_eq!(
+        view_clears(&[main, minimap]),
+        vec![
+            ViewClear {
+                color: Some(BLUE),
+                depth: 1.0,
+                depth_in_viewport: false,
+            },
+            // draws over the main camera, but tests against its own depth
+            ViewClear {
+                color: None,
+                depth: 0.0,
+                depth_in_viewport: true,
+            },
+        ]
+    );
+}
+
+#[test]
+fn offscreen_cameras_clear_the_color_of_their_target_once_and_their_depth_with_the_pass() {
+    let left = view(1, [0.0, 0.0, 64.0, 128.0], BLUE);
+    let right = view(1, [64.0, 0.0, 64.0, 128.0], wgpu::Color::RED);
+    let other = view(2, [0.0, 0.0, 128.0, 128.0], wgpu::Color::RED);
+    let main = view(ViewTarget::SWAPCHAIN, [0.0, 0.0, 800.0, 600.0], BLUE);
+
+    let clears = view_clears(&[left, right, other, main]);
+
+    let colors: Vec<_> = clears.iter().map(|x| x.color).collect();
+    assert_eq!(colors, vec![Some(BLUE), None, Some(wgpu::Color::RED), Some(BLUE)]);
+    assert!(clears.iter().all(|x| !x.depth_in_viewport));
+}
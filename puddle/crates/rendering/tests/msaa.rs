@@ -0,0 +1,53 @@
+use rendering::wgpu::{self, TextureFormatFeatureFlags as Flags};
+use rendering::{check_sample_count, supported_sample_counts, SampleCountError};
+
+fn features(flags: Flags) -> wgpu::TextureFormatFeatures {
+    wgpu::TextureFormatFeatures {
+        allowed_usages: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        flags,
+    }
+}
+
+#[test]
+fn one_sample_is_always_supported() {
+    let none = features(Flags::empty());
+    assert_eq!(supported_sample_counts(&none, &none), vec![1]);
+}
+
+#[test]
+fn counts_need_color_depth_and_resolve_support() {
+    let color = features(Flags::MULTISAMPLE_X2 | Flags::MULTISAMPLE_X4 | Flags::MULTISAMPLE_X8);
+    let resolvable = features(color.flags | Flags::MULTISAMPLE_RESOLVE);
+    let depth = features(Flags::MULTISAMPLE_X4 | Flags::MULTISAMPLE_X8);
+
+    assert_eq!(supported_sample_counts(&resolvable, &depth), vec![1, 4, 8]);
+    // the color can't be resolved into the hdr target
+    assert_eq!(supported_sample_counts(&color, &depth), vec![1]);
+    // every count of the depth format, but the color only has 4
+    let color = features(Flags::MULTISAMPLE_X4 | Flags::MULTISAMPLE_RESOLVE);
+    assert_eq!(supported_sample_counts(&color, &depth), vec![1, 4]);
+}
+
+#[test]
+fn rejects_counts_outside_of_sample_counts() {
+    let supported = [1, 2, 4, 8];
+
+    for count in [0, 3, 16] {
+        assert_eq!(
+            check_sample_count(count, &supported),
+            Err(SampleCountError::Invalid(count))
+        );
+    }
+}
+
+#[test]
+fn rejects_counts_the_adapter_lacks() {
+    let supported = [1, 4];
+
+    assert_eq!(check_sample_count(1, &supported), Ok(()));
+    assert_eq!(check_sample_count(4, &supported), Ok(()));
+    assert_eq!(
+        check_sample_count(8, &supported),
+        Err(SampleCountError::Unsupported(8))
+    );
+}
@@ -0,0 +1,155 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use assets::{AssetServer, Handle};
+use rendering::{load_obj, parse_obj, ObjError, ObjLoader, ObjMeshAsset, ObjModel};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+#[test]
+fn loads_meshes_split_by_material() {
+    let model = load_obj(fixture("cube.obj")).unwrap();
+
+    assert_eq!(model.meshes.len(), 2);
+
+    let wood = &model.meshes[0];
+    assert_eq!(wood.name, "Cube");
+    assert_eq!(wood.material.as_deref(), Some("Wood"));
+    assert_eq!(wood.vertices.len(), 16);
+    assert_eq!(wood.indices.len(), 4 * 6);
+
+    let metal = &model.meshes[1];
+    assert_eq!(metal.name, "Cube");
+    assert_eq!(metal.material.as_deref(), Some("Metal"));
+    assert_eq!(metal.vertices.len(), 8);
+    assert_eq!(metal.indices.len(), 2 * 6);
+}
+
+#[test]
+fn reads_positions_normals_and_flipped_uvs() {
+    let model = load_obj(fixture("cube.obj")).unwrap();
+    let first = model.meshes[0].vertices[0];
+
+    assert_eq!(first.position, [-0.5, -0.5, 0.5]);
+    assert_eq!(first.normal, [0.0, 0.0, 1.0]);
+    assert_eq!(first.uv, [0.0, 1.0]);
+}
+
+#[test]
+fn loads_mtl_materials_and_texture_paths() {
+    let model = load_obj(fixture("cube.obj")).unwrap();
+
+    assert_eq!(model.materials.len(), 2);
+
+    let wood = model.material("Wood").unwrap();
+    assert_eq!(wood.diffuse, [0.6, 0.4, 0.2]);
+    assert_eq!(wood.shininess, 10.0);
+    assert_eq!(
+        wood.diffuse_texture.as_deref(),
+        Some(fixture("textures/wood.png").as_path())
+    );
+
+    let metal = model.material("Metal").unwrap();
+    assert_eq!(metal.diffuse, [0.8, 0.8, 0.8]);
+    assert_eq!(metal.dissolve, 0.5);
+    assert_eq!(
+        metal.normal_texture.as_deref(),
+        Some(fixture("textures/metal_normal.png").as_path())
+    );
+}
+
+#[test]
+fn generates_normals_for_faces_without_them() {
+    let model = load_obj(fixture("triangle.obj")).unwrap();
+    let mesh = &model.meshes[0];
+
+    assert_eq!(mesh.indices, vec![0, 1, 2]);
+    for vertex in mesh.vertices.iter() {
+        assert_eq!(vertex.normal, [0.0, 0.0, 1.0]);
+    }
+}
+
+#[test]
+fn reports_the_line_of_an_invalid_face() {
+    let err = load_obj(fixture("broken.obj")).unwrap_err();
+
+    match err {
+        ObjError::Parse { line, message, .. } => {
+            assert_eq!(line, 5);
+            assert!(message.contains("out of range"), "{}", message);
+        }
+        e => panic!("expected a parse error, got {}", e),
+    }
+}
+
+#[test]
+fn reports_the_line_of_an_invalid_mtl_entry() {
+    let err = load_obj(fixture("broken_mtl.obj")).unwrap_err();
+
+    match err {
+        ObjError::Parse { file, line, .. } => {
+            assert!(file.ends_with("broken.mtl"), "{}", file);
+            assert_eq!(line, 2);
+        }
+        e => panic!("expected a parse error, got {}", e),
+    }
+}
+
+#[test]
+fn rejects_faces_with_less_than_three_vertices() {
+    let err = parse_obj("v 0 0 0\nv 1 0 0\nf 1 2\n", "inline.obj").unwrap_err();
+
+    assert_eq!(err.to_string(), "inline.obj:3: a face needs at least 3 vertices, got 2");
+}
+
+/// runs `finish_loads` like the `AssetPlugin` does every frame until the handle has `version`
+fn wait_for_version(server: &AssetServer, handle: &Handle<ObjModel>, version: u32) {
+    let resources = legion::Resources::default();
+    let start = Instant::now();
+    while handle.version() != version {
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "the model took too long to load"
+        );
+        server.finish_loads(&resources);
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn mesh_assets_take_the_mesh_again_when_the_model_is_reloaded() {
+    let dir = std::env::temp_dir().join("puddle_rendering_obj_reload");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("tri.obj"), "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+
+    let server = AssetServer::new(&dir);
+    server.register_loader(ObjLoader);
+    let handle: Handle<ObjModel> = server.load("tri.obj");
+    let mut mesh = ObjMeshAsset::new(handle.clone(), 0);
+    let mut missing = ObjMeshAsset::new(handle.clone(), 1);
+
+    assert!(mesh.take_changed().is_none());
+    wait_for_version(&server, &handle, 1);
+
+    let first = mesh.take_changed().unwrap().unwrap();
+    assert_eq!(first.vertices[1].position, [1.0, 0.0, 0.0]);
+    assert!(mesh.take_changed().is_none());
+    assert_eq!(
+        missing.take_changed().unwrap().unwrap_err(),
+        format!("{} has 1 meshes, there is no mesh 1", handle.path().display())
+    );
+    assert!(missing.take_changed().is_none());
+
+    std::fs::write(dir.join("tri.obj"), "v 0 0 0\nv 2 0 0\nv 0 2 0\nf 1 2 3\n").unwrap();
+    server.reload("tri.obj");
+    wait_for_version(&server, &handle, 2);
+
+    let second = mesh.take_changed().unwrap().unwrap();
+    assert_eq!(second.vertices[1].position, [2.0, 0.0, 0.0]);
+    assert!(mesh.take_changed().is_none());
+}
@@ -0,0 +1,257 @@
+use rendering::wgpu;
+use rendering::{
+    reflect_bindings, resolve_bindings, GivenBinding, ResolvedBinding, ShaderBinding, ShaderError,
+};
+
+const SHADER: &str = "
+struct Params {
+    color: vec4<f32>,
+    scale: f32,
+};
+
+struct Particle {
+    position: vec3<f32>,
+    size: f32,
+    velocity: vec3<f32>,
+};
+
+@group(1) @binding(0) var<uniform> params: Params;
+@group(1) @binding(1) var base_color: texture_2d<f32>;
+@group(1) @binding(2) var base_sampler: sampler;
+@group(1) @binding(3) var<storage, read> particles: array<Particle>;
+@group(1) @binding(4) var unused: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) i: u32) -> @builtin(position) vec4<f32> {
+    return vec4<f32>(particles[i].position * params.scale, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return textureSample(base_color, base_sampler, vec2<f32>(0.5)) * params.color;
+}
+";
+
+fn reflect(source: &str) -> Vec<ShaderBinding> {
+    let module = naga::front::wgsl::parse_str(source).unwrap();
+    let info = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::empty(),
+    )
+    .validate(&module)
+    .unwrap();
+    reflect_bindings(&module, &info, 1)
+}
+
+fn uniform(size: Option<u64>) -> wgpu::BindingType {
+    wgpu::BindingType::Buffer {
+        ty: wgpu::BufferBindingType::Uniform,
+        has_dynamic_offset: false,
+        min_binding_size: size.and_then(wgpu::BufferSize::new),
+    }
+}
+
+fn storage(stride: Option<u64>) -> wgpu::BindingType {
+    wgpu::BindingType::Buffer {
+        ty: wgpu::BufferBindingType::Storage { read_only: true },
+        has_dynamic_offset: false,
+        min_binding_size: stride.and_then(wgpu::BufferSize::new),
+    }
+}
+
+fn texture(filterable: bool) -> wgpu::BindingType {
+    wgpu::BindingType::Texture {
+        sample_type: wgpu::TextureSampleType::Float { filterable },
+        view_dimension: wgpu::TextureViewDimension::D2,
+        multisampled: false,
+    }
+}
+
+fn given(name: &str, ty: wgpu::BindingType) -> GivenBinding<'_> {
+    GivenBinding {
+        name,
+        ty,
+        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+        buffer_size: match ty {
+            wgpu::BindingType::Buffer { .. } => Some(256),
+            _ => None,
+        },
+    }
+}
+
+/// a binding for every variable of `SHADER`, in another order than the shader
+fn material() -> Vec<GivenBinding<'static>> {
+    vec![
+        given(
+            "base_sampler",
+            wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
+        ),
+        given("base_color", texture(false)),
+        given("params", uniform(None)),
+        given("particles", storage(None)),
+        given("unused", texture(true)),
+    ]
+}
+
+fn resolve(material: &[GivenBinding]) -> Result<Vec<ResolvedBinding>, ShaderError> {
+    resolve_bindings("material.wgsl", 1, &reflect(SHADER), material)
+}
+
+fn message(result: Result<Vec<ResolvedBinding>, ShaderError>) -> String {
+    match result {
+        Err(ShaderError::Binding { file, message }) => {
+            assert_eq!(file, "material.wgsl");
+            message
+        }
+        Err(e) => panic!("expected a binding error, got {}", e),
+        Ok(_) => panic!("expected a binding error"),
+    }
+}
+
+#[test]
+fn reflects_the_bindings_and_the_stages_using_them() {
+    let reflected = reflect(SHADER);
+
+    let names: Vec<_> = reflected
+        .iter()
+        .map(|x| (x.binding, x.name.as_str()))
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            (0, "params"),
+            (1, "base_color"),
+            (2, "base_sampler"),
+            (3, "particles"),
+            (4, "unused")
+        ]
+    );
+
+    let visibility: Vec<_> = reflected.iter().map(|x| x.visibility).collect();
+    assert_eq!(
+        visibility,
+        vec![
+            wgpu::ShaderStages::VERTEX_FRAGMENT,
+            wgpu::ShaderStages::FRAGMENT,
+            wgpu::ShaderStages::FRAGMENT,
+            wgpu::ShaderStages::VERTEX,
+            wgpu::ShaderStages::NONE,
+        ]
+    );
+
+    assert_eq!(reflected[0].ty, uniform(Some(32)));
+    // one element of the runtime sized array
+    assert_eq!(reflected[3].ty, storage(Some(32)));
+}
+
+#[test]
+fn the_layout_takes_the_shader_types_with_the_filtering_of_the_material() {
+    let resolved = resolve(&material()).unwrap();
+
+    assert_eq!(
+        resolved,
+        vec![
+            ResolvedBinding {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
+            },
+            ResolvedBinding {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: texture(false),
+            },
+            ResolvedBinding {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: uniform(Some(32)),
+            },
+            ResolvedBinding {
+                binding: 3,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: storage(Some(32)),
+            },
+            // not used by any entry point, it keeps the stages of the material
+            ResolvedBinding {
+                binding: 4,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: texture(true),
+            },
+        ]
+    );
+}
+
+#[test]
+fn names_the_variables_when_a_name_does_not_match() {
+    let mut material = material();
+    material[1].name = "albedo";
+
+    let message = message(resolve(&material));
+
+    assert!(
+        message.contains("no variable albedo in group 1"),
+        "{}",
+        message
+    );
+    assert!(
+        message.contains("[params, base_color, base_sampler, particles, unused]"),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn reports_bindings_of_another_type() {
+    let mut material = material();
+    material[2].ty = storage(None);
+
+    let message = message(resolve(&material));
+
+    assert_eq!(
+        message,
+        "params is a var<uniform> in the shader but the material binds a var<storage, read>"
+    );
+}
+
+#[test]
+fn reports_storage_strides_that_differ_from_the_shader() {
+    // a rust struct of [f32; 3], f32 and [f32; 3] is 28 bytes, the wgsl one 32
+    let mut material = material();
+    material[3].ty = storage(Some(28));
+
+    let message = message(resolve(&material));
+
+    assert!(
+        message.contains("particles has 32 bytes per element in the shader but 28"),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn reports_buffers_smaller_than_the_shader_struct() {
+    let mut material = material();
+    material[2].buffer_size = Some(16);
+
+    let message = message(resolve(&material));
+
+    assert!(
+        message.contains("params needs at least 32 bytes but the buffer has 16"),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn reports_shader_bindings_without_a_resource() {
+    let mut material = material();
+    material.remove(4);
+
+    let message = message(resolve(&material));
+
+    assert!(
+        message.contains("the shader declares unused : texture_2d<f32> at @group(1) @binding(4)"),
+        "{}",
+        message
+    );
+}
@@ -0,0 +1,198 @@
+use rendering::wgpu;
+use rendering::{NodeDesc, RenderGraph, RenderGraphError, RenderNode, SlotSize, TransientTexture};
+
+/// a node that only declares what it touches
+struct Dummy(NodeDesc);
+
+impl RenderNode for Dummy {
+    fn desc(&self) -> NodeDesc {
+        self.0.clone()
+    }
+}
+
+fn build(nodes: Vec<NodeDesc>) -> RenderGraph {
+    let mut graph = RenderGraph::new();
+    for desc in nodes {
+        graph.add_node(Dummy(desc)).unwrap();
+    }
+    graph
+}
+
+fn surface_config() -> wgpu::SurfaceConfiguration {
+    wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format: wgpu::TextureFormat::Bgra8UnormSrgb,
+        width: 800,
+        height: 600,
+        present_mode: wgpu::PresentMode::Fifo,
+        desired_maximum_frame_latency: 2,
+        alpha_mode: wgpu::CompositeAlphaMode::Auto,
+        view_formats: vec![],
+    }
+}
+
+fn names(order: &[&[&str]]) -> Vec<Vec<String>> {
+    order
+        .iter()
+        .map(|pass| pass.iter().map(|x| x.to_string()).collect())
+        .collect()
+}
+
+#[test]
+fn writers_run_before_readers_added_earlier() {
+    let mut graph = build(vec![
+        NodeDesc::new("post").reads("hdr").writes("swapchain"),
+        NodeDesc::new("main").reads("shadow").writes("hdr"),
+        NodeDesc::new("shadows").writes("shadow"),
+    ]);
+
+    assert_eq!(
+        graph.order().unwrap(),
+        names(&[&["shadows"], &["main"], &["post"]])
+    );
+}
+
+#[test]
+fn writers_of_one_slot_keep_the_order_they_were_added() {
+    let mut graph = build(vec![
+        NodeDesc::new("first").writes("hdr"),
+        NodeDesc::new("second").reads("hdr").writes("hdr"),
+        NodeDesc::new("third").writes("hdr"),
+    ]);
+
+    assert_eq!(
+        graph.order().unwrap(),
+        names(&[&["first"], &["second"], &["third"]])
+    );
+}
+
+#[test]
+fn nodes_loading_the_same_attachments_share_a_pass() {
+    let mut graph = build(vec![
+        NodeDesc::new("opaque")
+            .color_attachment("hdr", wgpu::LoadOp::Clear(wgpu::Color::BLACK))
+            .depth_attachment("depth", wgpu::LoadOp::Clear(1.0)),
+        NodeDesc::new("transparent")
+            .color_attachment("hdr", wgpu::LoadOp::Load)
+            .depth_attachment("depth", wgpu::LoadOp::Load),
+        NodeDesc::new("compute").reads("hdr").writes("bloom"),
+    ]);
+
+    assert_eq!(
+        graph.order().unwrap(),
+        names(&[&["opaque", "transparent"], &["compute"]])
+    );
+}
+
+#[test]
+fn resolved_attachments_write_their_target_and_only_share_a_pass_with_the_same_resolve() {
+    let mut graph = build(vec![
+        NodeDesc::new("post").reads("hdr").writes("swapchain"),
+        NodeDesc::new("main")
+            .resolved_color_attachment("msaa", "hdr", wgpu::LoadOp::Clear(wgpu::Color::BLACK))
+            .depth_attachment("depth", wgpu::LoadOp::Clear(1.0)),
+        NodeDesc::new("overlay")
+            .resolved_color_attachment("msaa", "hdr", wgpu::LoadOp::Load)
+            .depth_attachment("depth", wgpu::LoadOp::Load),
+        NodeDesc::new("unresolved")
+            .color_attachment("msaa", wgpu::LoadOp::Load)
+            .depth_attachment("depth", wgpu::LoadOp::Load),
+    ]);
+
+    assert_eq!(
+        graph.order().unwrap(),
+        names(&[&["main", "overlay"], &["post"], &["unresolved"]])
+    );
+}
+
+#[test]
+fn reports_every_node_of_a_cycle() {
+    let mut graph = build(vec![
+        NodeDesc::new("a").reads("y").writes("x"),
+        NodeDesc::new("b").reads("x").writes("y"),
+        NodeDesc::new("c").writes("z"),
+    ]);
+
+    assert_eq!(
+        graph.order(),
+        Err(RenderGraphError::Cycle(vec![
+            "a".to_string(),
+            "b".to_string()
+        ]))
+    );
+}
+
+#[test]
+fn rejects_duplicate_nodes_and_transients() {
+    let mut graph = build(vec![NodeDesc::new("main")]);
+    assert_eq!(
+        graph.add_node(Dummy(NodeDesc::new("main"))),
+        Err(RenderGraphError::DuplicateNode("main".to_string()))
+    );
+
+    let format = wgpu::TextureFormat::Rgba16Float;
+    let mut graph = build(vec![
+        NodeDesc::new("a").transient(TransientTexture::new("t", format)),
+        NodeDesc::new("b").transient(TransientTexture::new("t", format)),
+    ]);
+    assert_eq!(
+        graph.order(),
+        Err(RenderGraphError::DuplicateTransient("t".to_string()))
+    );
+}
+
+#[test]
+fn transients_share_textures_once_their_passes_are_done() {
+    let format = wgpu::TextureFormat::Rgba16Float;
+    let mut graph = build(vec![
+        NodeDesc::new("a")
+            .transient(TransientTexture::new("a_out", format))
+            .writes("a_out"),
+        NodeDesc::new("b")
+            .reads("a_out")
+            .transient(TransientTexture::new("b_out", format))
+            .writes("b_out"),
+        NodeDesc::new("c")
+            .reads("b_out")
+            .transient(TransientTexture::new("c_out", format))
+            .writes("c_out"),
+        NodeDesc::new("d").reads("c_out").writes("swapchain"),
+    ]);
+
+    // `a_out` is done after `b`, so `c` can reuse it. `b_out` is still read by `c`
+    assert_eq!(
+        graph.transient_groups(&surface_config()).unwrap(),
+        vec![
+            vec!["a_out".to_string(), "c_out".to_string()],
+            vec!["b_out".to_string()],
+        ]
+    );
+}
+
+#[test]
+fn transients_of_different_sizes_or_formats_dont_share() {
+    let mut graph = build(vec![
+        NodeDesc::new("a")
+            .transient(TransientTexture::new(
+                "a_out",
+                wgpu::TextureFormat::Rgba16Float,
+            ))
+            .writes("a_out"),
+        NodeDesc::new("b")
+            .reads("a_out")
+            .transient(
+                TransientTexture::new("b_out", wgpu::TextureFormat::Rgba16Float)
+                    .with_size(SlotSize::Scaled(0.5)),
+            )
+            .writes("b_out"),
+        NodeDesc::new("c")
+            .reads("b_out")
+            .transient(TransientTexture::new(
+                "c_out",
+                wgpu::TextureFormat::Rgba8Unorm,
+            ))
+            .writes("c_out"),
+    ]);
+
+    assert_eq!(graph.transient_groups(&surface_config()).unwrap().len(), 3);
+}
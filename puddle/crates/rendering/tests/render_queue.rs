@@ -0,0 +1,128 @@
+use rendering::{
+    queue_draws, Aabb, Camera, DrawCall, InstanceRaw, ModelMatrix, QueueItem, RenderLayers,
+    RenderQueue, SortView,
+};
+
+/// a camera at z = 5 looking at the origin
+fn view() -> SortView {
+    let mut camera = Camera::default(1.0);
+    camera.eye = (0.0, 0.0, 5.0).into();
+    camera.target = (0.0, 0.0, 0.0).into();
+    SortView::new(&camera, RenderLayers::DEFAULT)
+}
+
+fn at_depth(z: f32) -> ModelMatrix {
+    ModelMatrix {
+        position: (0.0, 0.0, z).into(),
+        ..Default::default()
+    }
+}
+
+fn item(queue: RenderQueue, instances: &[ModelMatrix]) -> QueueItem<'_> {
+    QueueItem {
+        queue,
+        queue_priority: 0,
+        layers: RenderLayers::DEFAULT,
+        bounds: None,
+        instances,
+    }
+}
+
+fn draw(material: usize, instances: std::ops::Range<u32>) -> DrawCall {
+    DrawCall {
+        material,
+        instances,
+    }
+}
+
+/// the z of the translation of every queued instance
+fn depths(instances: &[InstanceRaw]) -> Vec<f32> {
+    instances
+        .iter()
+        .map(|x| bytemuck::cast::<_, [f32; 16]>(*x)[14])
+        .collect()
+}
+
+#[test]
+fn draws_the_queues_in_order_and_opaque_materials_front_to_back() {
+    let overlay = [at_depth(0.0)];
+    let far = [at_depth(-10.0), at_depth(2.0)];
+    let near = [at_depth(-1.0), at_depth(1.0)];
+    let background = [at_depth(0.0)];
+    let mut first_background = item(RenderQueue::Background, &background);
+    first_background.queue_priority = -1;
+    let materials = [
+        item(RenderQueue::Overlay, &overlay),
+        item(RenderQueue::Opaque, &far),
+        item(RenderQueue::Opaque, &near),
+        item(RenderQueue::Background, &background),
+        first_background,
+    ];
+
+    let mut instances = vec![];
+    let draws = queue_draws(&view(), &materials, &mut instances);
+
+    assert_eq!(
+        draws,
+        vec![
+            draw(4, 0..1),
+            draw(3, 1..2),
+            // the nearest instance of each material decides, they are sorted inside too
+            draw(1, 2..4),
+            draw(2, 4..6),
+            draw(0, 6..7),
+        ]
+    );
+    assert_eq!(depths(&instances[2..6]), vec![2.0, -10.0, 1.0, -1.0]);
+}
+
+#[test]
+fn interleaves_transparent_instances_back_to_front() {
+    let glass = [at_depth(-3.0), at_depth(1.0), at_depth(-2.0)];
+    let smoke = [at_depth(0.0)];
+    let materials = [
+        item(RenderQueue::Transparent, &glass),
+        item(RenderQueue::Transparent, &smoke),
+    ];
+
+    let mut instances = vec![];
+    let draws = queue_draws(&view(), &materials, &mut instances);
+
+    // the two instances of glass behind the smoke share a draw
+    assert_eq!(draws, vec![draw(0, 0..2), draw(1, 2..3), draw(0, 3..4)]);
+    assert_eq!(depths(&instances), vec![-3.0, -2.0, 0.0, 1.0]);
+}
+
+#[test]
+fn transparent_materials_with_a_higher_priority_draw_over_the_others() {
+    let glass = [at_depth(-2.0), at_depth(2.0)];
+    let decal = [at_depth(-4.0)];
+    let mut decals = item(RenderQueue::Transparent, &decal);
+    decals.queue_priority = 1;
+    let materials = [decals, item(RenderQueue::Transparent, &glass)];
+
+    let mut instances = vec![];
+    let draws = queue_draws(&view(), &materials, &mut instances);
+
+    assert_eq!(draws, vec![draw(1, 0..2), draw(0, 2..3)]);
+    assert_eq!(depths(&instances), vec![-2.0, 2.0, -4.0]);
+}
+
+#[test]
+fn skips_materials_on_other_layers_and_outside_the_view() {
+    let origin = [at_depth(0.0)];
+    let behind = [at_depth(20.0)];
+    let unit_box = Aabb::new((-0.5, -0.5, -0.5).into(), (0.5, 0.5, 0.5).into());
+
+    let mut other_layer = item(RenderQueue::Opaque, &origin);
+    other_layer.layers = RenderLayers::layer(3);
+    let mut culled = item(RenderQueue::Transparent, &behind);
+    culled.bounds = Some(&unit_box);
+    let never_culled = item(RenderQueue::Opaque, &behind);
+    let materials = [other_layer, culled, never_culled];
+
+    let mut instances = vec![];
+    let draws = queue_draws(&view(), &materials, &mut instances);
+
+    assert_eq!(draws, vec![draw(2, 0..1)]);
+}
@@ -0,0 +1,66 @@
+use rendering::{validate_preprocessed, validate_wgsl, ShaderError, ShaderPreprocessor};
+
+/// the file, line and column of a parse or validation error
+fn location(error: ShaderError) -> (String, u32, u32) {
+    match error {
+        ShaderError::Parse {
+            file, line, column, ..
+        }
+        | ShaderError::Validation {
+            file, line, column, ..
+        } => (file, line, column),
+        e => panic!("expected a parse or validation error, got {}", e),
+    }
+}
+
+#[test]
+fn accepts_valid_wgsl() {
+    let source = "fn double(x: f32) -> f32 {\n    return x * 2.0;\n}\n";
+    assert!(validate_wgsl("valid.wgsl", source).is_ok());
+}
+
+#[test]
+fn parse_errors_point_at_line_and_column() {
+    let source = "fn broken() {\n    let x = ;\n}\n";
+    let error = validate_wgsl("broken.wgsl", source).unwrap_err();
+
+    assert!(matches!(error, ShaderError::Parse { .. }));
+    assert_eq!(location(error), ("broken.wgsl".to_string(), 2, 13));
+}
+
+#[test]
+fn validation_errors_point_at_the_broken_function() {
+    // naga reports the function the error is in
+    let source = "fn fine() {}\n\nfn broken() -> f32 {\n    return 1u;\n}\n";
+    let error = validate_wgsl("broken.wgsl", source).unwrap_err();
+
+    assert!(matches!(error, ShaderError::Validation { .. }));
+    assert_eq!(location(error), ("broken.wgsl".to_string(), 3, 1));
+}
+
+#[test]
+fn errors_in_modules_point_at_the_module() {
+    let mut preprocessor = ShaderPreprocessor::empty();
+    preprocessor.add_module(
+        "common",
+        "fn helper() -> f32 {\n    return 1.0;\n}\nfn oops( {\n",
+    );
+
+    let source = "#import common\n\nfn main() {}\n";
+    let shader = preprocessor.process("main.wgsl", source).unwrap();
+    let (file, line, _) = location(validate_preprocessed(&shader).unwrap_err());
+
+    assert_eq!((file.as_str(), line), ("common", 4));
+}
+
+#[test]
+fn errors_after_imports_keep_the_line_of_the_file() {
+    let mut preprocessor = ShaderPreprocessor::empty();
+    preprocessor.add_module("common", "fn a() {}\nfn b() {}\nfn c() {}\n");
+
+    let source = "#import common\n\nfn main() {\n    let x = ;\n}\n";
+    let shader = preprocessor.process("main.wgsl", source).unwrap();
+    let (file, line, column) = location(validate_preprocessed(&shader).unwrap_err());
+
+    assert_eq!((file.as_str(), line, column), ("main.wgsl", 4, 13));
+}
@@ -0,0 +1,243 @@
+use std::fs;
+use std::path::PathBuf;
+
+use rendering::{ShaderError, ShaderPreprocessor};
+
+/// an empty folder for shader files of one test
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("puddle_preprocessor_{}", name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// the lines of the output that aren't empty
+fn lines(source: &str) -> Vec<&str> {
+    source.lines().filter(|x| !x.trim().is_empty()).collect()
+}
+
+fn preprocess_error(error: ShaderError) -> (String, u32, String) {
+    match error {
+        ShaderError::Preprocess {
+            file,
+            line,
+            message,
+        } => (file, line, message),
+        e => panic!("expected a preprocessor error, got {}", e),
+    }
+}
+
+#[test]
+fn nested_conditions_follow_their_defines() {
+    let source = "\
+#ifdef A
+a
+#ifdef B
+ab
+#else
+a_not_b
+#endif
+#else
+not_a
+#ifndef B
+not_a_not_b
+#endif
+#endif
+";
+    let mut preprocessor = ShaderPreprocessor::empty();
+
+    let shader = preprocessor.process("test.wgsl", source).unwrap();
+    assert_eq!(lines(&shader.source), vec!["not_a", "not_a_not_b"]);
+
+    preprocessor.define("A", "");
+    let shader = preprocessor.process("test.wgsl", source).unwrap();
+    assert_eq!(lines(&shader.source), vec!["a", "a_not_b"]);
+
+    preprocessor.define("B", "");
+    let shader = preprocessor.process("test.wgsl", source).unwrap();
+    assert_eq!(lines(&shader.source), vec!["a", "ab"]);
+}
+
+#[test]
+fn defines_in_the_shader_count_for_later_lines() {
+    let source = "\
+#define FLAG
+#ifdef FLAG
+flag
+#endif
+#undef FLAG
+#ifdef FLAG
+still_flag
+#endif
+";
+    let shader = ShaderPreprocessor::empty()
+        .process("test.wgsl", source)
+        .unwrap();
+    assert_eq!(lines(&shader.source), vec!["flag"]);
+}
+
+#[test]
+fn inactive_blocks_dont_define_or_import() {
+    let source = "\
+#ifdef MISSING
+#define FLAG
+#import missing::module
+#endif
+#ifdef FLAG
+flag
+#endif
+";
+    let shader = ShaderPreprocessor::empty()
+        .process("test.wgsl", source)
+        .unwrap();
+    assert!(lines(&shader.source).is_empty());
+}
+
+#[test]
+fn reports_broken_directives_with_their_line() {
+    let preprocessor = ShaderPreprocessor::empty();
+    let cases = [
+        ("#else\n", 1, "#else without #ifdef"),
+        ("\n#endif\n", 2, "#endif without #ifdef"),
+        (
+            "#ifdef A\n#else\n#else\n#endif\n",
+            3,
+            "#else without #ifdef",
+        ),
+        ("#ifdef A\nx\n", 2, "missing #endif"),
+        ("#ifdef\n#endif\n", 1, "#ifdef needs a name"),
+        ("\n\n#pragma once\n", 3, "unknown directive #pragma"),
+        (
+            "#import\n",
+            1,
+            "#import needs a module name or a quoted path",
+        ),
+        ("#import missing\n", 1, "unknown shader module missing"),
+        (
+            "#import \"file.wgsl\n",
+            1,
+            "missing closing quote in \"file.wgsl",
+        ),
+    ];
+
+    for (source, line, message) in cases {
+        let error = preprocessor.process("test.wgsl", source).unwrap_err();
+        assert_eq!(
+            preprocess_error(error),
+            ("test.wgsl".to_string(), line, message.to_string()),
+            "{:?}",
+            source
+        );
+    }
+}
+
+#[test]
+fn errors_in_modules_name_the_module() {
+    let mut preprocessor = ShaderPreprocessor::empty();
+    preprocessor.add_module("broken", "\n#endif\n");
+
+    let error = preprocessor
+        .process("test.wgsl", "#import broken\n")
+        .unwrap_err();
+    assert_eq!(preprocess_error(error).0, "broken");
+}
+
+#[test]
+fn missing_files_are_io_errors() {
+    let dir = temp_dir("missing");
+    let path = dir.join("main.wgsl");
+    fs::write(&path, "#import \"missing.wgsl\"\n").unwrap();
+
+    let error = ShaderPreprocessor::empty().process_file(&path).unwrap_err();
+    assert!(matches!(error, ShaderError::Io { .. }), "{}", error);
+}
+
+#[test]
+fn modules_are_imported_once() {
+    let mut preprocessor = ShaderPreprocessor::empty();
+    preprocessor.add_module("common", "common");
+    preprocessor.add_module("lights", "#import common\nlights");
+
+    let source = "#import common\n#import lights\n#import common\nmain\n";
+    let shader = preprocessor.process("test.wgsl", source).unwrap();
+    assert_eq!(lines(&shader.source), vec!["common", "lights", "main"]);
+}
+
+#[test]
+fn files_are_imported_once_and_relative_to_the_importer() {
+    let dir = temp_dir("dedup");
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    fs::write(dir.join("lib/common.wgsl"), "common\n").unwrap();
+    fs::write(
+        dir.join("lib/lights.wgsl"),
+        "#import \"common.wgsl\"\nlights\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("main.wgsl"),
+        "#import \"lib/common.wgsl\"\n#import \"lib/lights.wgsl\"\nmain\n",
+    )
+    .unwrap();
+
+    let shader = ShaderPreprocessor::empty()
+        .process_file(dir.join("main.wgsl"))
+        .unwrap();
+    assert_eq!(lines(&shader.source), vec!["common", "lights", "main"]);
+    // every file that has to be watched, the shader itself first
+    assert_eq!(shader.files.len(), 3);
+    assert_eq!(shader.files[0], dir.join("main.wgsl"));
+}
+
+#[test]
+fn import_cycles_end() {
+    let mut preprocessor = ShaderPreprocessor::empty();
+    preprocessor.add_module("a", "#import b\na");
+    preprocessor.add_module("b", "#import a\nb");
+
+    let shader = preprocessor
+        .process("test.wgsl", "#import a\nmain\n")
+        .unwrap();
+    assert_eq!(lines(&shader.source), vec!["b", "a", "main"]);
+
+    let dir = temp_dir("cycle");
+    fs::write(dir.join("main.wgsl"), "#import \"other.wgsl\"\nmain\n").unwrap();
+    fs::write(dir.join("other.wgsl"), "#import \"main.wgsl\"\nother\n").unwrap();
+
+    let shader = ShaderPreprocessor::empty()
+        .process_file(dir.join("main.wgsl"))
+        .unwrap();
+    assert_eq!(lines(&shader.source), vec!["other", "main"]);
+}
+
+#[test]
+fn substitutes_whole_words_only() {
+    let mut preprocessor = ShaderPreprocessor::empty();
+    preprocessor.define("SIZE", "4");
+    preprocessor.define("FLAG", "");
+
+    let source = "array<f32, SIZE> SIZE_X MY_SIZE SIZE2 (SIZE) FLAG\n#define N SIZE\nN";
+    let shader = preprocessor.process("test.wgsl", source).unwrap();
+    assert_eq!(
+        lines(&shader.source),
+        vec!["array<f32, 4> SIZE_X MY_SIZE SIZE2 (4) FLAG", "SIZE"]
+    );
+}
+
+#[test]
+fn origin_maps_lines_back_to_their_file() {
+    let mut preprocessor = ShaderPreprocessor::empty();
+    preprocessor.add_module("common", "one\ntwo");
+
+    let source = "first\n#import common\nthird\n";
+    let shader = preprocessor.process("main.wgsl", source).unwrap();
+
+    assert_eq!(shader.origin(1), Some(("main.wgsl", 1)));
+    assert_eq!(shader.origin(2), Some(("common", 1)));
+    assert_eq!(shader.origin(3), Some(("common", 2)));
+    // the line the import was on stays empty
+    assert_eq!(shader.origin(4), Some(("main.wgsl", 2)));
+    assert_eq!(shader.origin(5), Some(("main.wgsl", 3)));
+
+    assert_eq!(shader.origin(0), None);
+    assert_eq!(shader.origin(6), None);
+}
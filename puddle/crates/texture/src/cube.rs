@@ -0,0 +1,389 @@
+use std::f32::consts::PI;
+
+use crate::{Texture, TextureSettings};
+
+/// the six faces of a cube texture on the cpu, in the order wgpu expects: +x, -x, +y, -y, +z, -z.
+/// cube textures are left handed, sample them with the z of the direction flipped
+pub struct CubeFaces {
+    pub size: u32,
+    pub faces: [image::Rgba32FImage; 6],
+    /// came from a float image, stored as `Rgba16Float` to keep values above 1
+    pub hdr: bool,
+}
+
+impl CubeFaces {
+    /// six square images of the same size
+    pub fn from_faces(faces: [&image::DynamicImage; 6]) -> Result<Self, String> {
+        let size = faces[0].width();
+        if faces
+            .iter()
+            .any(|x| x.width() != size || x.height() != size)
+        {
+            return Err("cube faces have to be square and the same size".to_string());
+        }
+        if size == 0 {
+            return Err("the cube faces are empty".to_string());
+        }
+
+        Ok(Self {
+            size,
+            hdr: faces.iter().any(|x| is_hdr(x)),
+            faces: faces.map(|x| x.to_rgba32f()),
+        })
+    }
+
+    /// a horizontal cross, 4 faces wide and 3 high:
+    /// +y above the second face, -x, +z, +x, -z in the middle row, -y below the second face
+    pub fn from_cross(image: &image::DynamicImage) -> Result<Self, String> {
+        let size = image.width() / 4;
+        if size == 0 || image.width() != size * 4 || image.height() != size * 3 {
+            return Err(format!(
+                "a cube cross has to be 4 by 3 faces, the image is {}x{}",
+                image.width(),
+                image.height()
+            ));
+        }
+
+        let rgba = image.to_rgba32f();
+        // the column and row of every face in the cross
+        let cells = [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (3, 1)];
+        let faces = cells.map(|(x, y)| {
+            image::imageops::crop_imm(&rgba, x * size, y * size, size, size).to_image()
+        });
+
+        Ok(Self {
+            size,
+            faces,
+            hdr: is_hdr(image),
+        })
+    }
+
+    /// an equirectangular panorama, twice as wide as high.
+    /// every face is sampled from it with bilinear filtering
+    pub fn from_equirectangular(image: &image::DynamicImage, size: u32) -> Result<Self, String> {
+        if image.width() == 0 || image.height() == 0 || size == 0 {
+            return Err("the panorama is empty".to_string());
+        }
+
+        let rgba = image.to_rgba32f();
+        let faces = [0, 1, 2, 3, 4, 5].map(|face| {
+            image::Rgba32FImage::from_fn(size, size, |x, y| {
+                let u = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                let v = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                let [dx, dy, dz] = face_direction(face, u, v);
+
+                let length = (dx * dx + dy * dy + dz * dz).sqrt();
+                let longitude = dz.atan2(dx);
+                let latitude = (dy / length).asin();
+
+                sample_bilinear(&rgba, 0.5 + longitude / (2.0 * PI), 0.5 - latitude / PI)
+            })
+        });
+
+        Ok(Self {
+            size,
+            faces,
+            hdr: is_hdr(image),
+        })
+    }
+}
+
+impl Texture {
+    /// a cube texture viewed as `TextureViewDimension::Cube`, `settings.srgb` is ignored for hdr faces
+    pub fn from_cube_faces(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        cube: &CubeFaces,
+        settings: &TextureSettings,
+    ) -> Result<Self, String> {
+        let format = if cube.hdr {
+            wgpu::TextureFormat::Rgba16Float
+        } else {
+            settings.format()
+        };
+        let mip_level_count = if settings.mipmaps {
+            Self::mip_level_count(cube.size, cube.size)
+        } else {
+            1
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("cube texture"),
+            size: wgpu::Extent3d {
+                width: cube.size,
+                height: cube.size,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        for (layer, face) in cube.faces.iter().enumerate() {
+            let mut level = face.clone();
+            for mip_level in 0..mip_level_count {
+                if mip_level > 0 {
+                    level = downsample(&level);
+                }
+
+                let (bytes, texel_size) = if cube.hdr {
+                    (encode_f16(&level), 8)
+                } else {
+                    (encode_unorm(&level), 4)
+                };
+
+                queue.write_texture(
+                    wgpu::ImageCopyTexture {
+                        aspect: wgpu::TextureAspect::All,
+                        texture: &texture,
+                        mip_level,
+                        origin: wgpu::Origin3d {
+                            x: 0,
+                            y: 0,
+                            z: layer as u32,
+                        },
+                    },
+                    &bytes,
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(texel_size * level.width()),
+                        rows_per_image: Some(level.height()),
+                    },
+                    wgpu::Extent3d {
+                        width: level.width(),
+                        height: level.height(),
+                        depth_or_array_layers: 1,
+                    },
+                );
+            }
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        let sampler = settings.sampler.create_sampler(device);
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+        })
+    }
+}
+
+fn is_hdr(image: &image::DynamicImage) -> bool {
+    matches!(
+        image,
+        image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_)
+    )
+}
+
+/// the direction of a texel of a face, `u` goes right and `v` down, both from -1 to 1
+fn face_direction(face: usize, u: f32, v: f32) -> [f32; 3] {
+    match face {
+        0 => [1.0, -v, -u],
+        1 => [-1.0, -v, u],
+        2 => [u, 1.0, v],
+        3 => [u, -1.0, -v],
+        4 => [u, -v, 1.0],
+        _ => [-u, -v, -1.0],
+    }
+}
+
+/// wraps around horizontally and clamps vertically, like a panorama
+fn sample_bilinear(image: &image::Rgba32FImage, u: f32, v: f32) -> image::Rgba<f32> {
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    let x = u * width as f32 - 0.5;
+    let y = v * height as f32 - 0.5;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (tx, ty) = (x - x0, y - y0);
+
+    let texel = |x: i64, y: i64| {
+        image
+            .get_pixel(x.rem_euclid(width) as u32, y.clamp(0, height - 1) as u32)
+            .0
+    };
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let (a, b, c, d) = (
+        texel(x0, y0),
+        texel(x0 + 1, y0),
+        texel(x0, y0 + 1),
+        texel(x0 + 1, y0 + 1),
+    );
+
+    image::Rgba(std::array::from_fn(|i| {
+        let top = a[i] + (b[i] - a[i]) * tx;
+        let bottom = c[i] + (d[i] - c[i]) * tx;
+        top + (bottom - top) * ty
+    }))
+}
+
+/// the next mip level of a face
+fn downsample(image: &image::Rgba32FImage) -> image::Rgba32FImage {
+    crate::downsample_with(image, |_, value| value, |_, value| value)
+}
+
+/// the values are stored as they were in the source image, so srgb images stay srgb
+fn encode_unorm(image: &image::Rgba32FImage) -> Vec<u8> {
+    image
+        .as_raw()
+        .iter()
+        .map(|x| (x.clamp(0.0, 1.0) * 255.0 + 0.5) as u8)
+        .collect()
+}
+
+fn encode_f16(image: &image::Rgba32FImage) -> Vec<u8> {
+    image
+        .as_raw()
+        .iter()
+        .flat_map(|x| f32_to_f16(*x).to_le_bytes())
+        .collect()
+}
+
+/// rounds towards zero, too small values become 0 and too large ones the largest f16
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+
+    if value.is_nan() {
+        return sign | 0x7e00;
+    }
+
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent >= 31 {
+        // the largest finite f16
+        sign | 0x7bff
+    } else if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+        // subnormal
+        let mantissa = (mantissa | 0x80_0000) >> (14 - exponent);
+        sign | mantissa as u16
+    } else {
+        sign | ((exponent as u16) << 10) | (mantissa >> 13) as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cross_faces_come_out_in_wgpu_order() {
+        // every cell is filled with its column and row, the top left texel is marked in blue
+        let cross = image::Rgba32FImage::from_fn(8, 6, |x, y| {
+            let marker = if x % 2 == 0 && y % 2 == 0 { 1.0 } else { 0.0 };
+            image::Rgba([(x / 2) as f32, (y / 2) as f32, marker, 1.0])
+        });
+        let cube = CubeFaces::from_cross(&image::DynamicImage::ImageRgba32F(cross)).unwrap();
+
+        assert_eq!(cube.size, 2);
+        assert!(cube.hdr);
+        // +x, -x, +y, -y, +z, -z
+        let cells = [(2, 1), (0, 1), (1, 0), (1, 2), (1, 1), (3, 1)];
+        for (face, (column, row)) in cube.faces.iter().zip(cells) {
+            assert_eq!(
+                face.get_pixel(0, 0).0,
+                [column as f32, row as f32, 1.0, 1.0]
+            );
+            // not flipped or rotated
+            assert_eq!(face.get_pixel(1, 1).0[2], 0.0);
+            assert_eq!(face.get_pixel(1, 0).0[2], 0.0);
+        }
+    }
+
+    #[test]
+    fn crosses_need_4_by_3_faces() {
+        let image = |width, height| image::DynamicImage::new_rgba8(width, height);
+
+        assert!(CubeFaces::from_cross(&image(8, 6)).is_ok());
+        assert!(CubeFaces::from_cross(&image(6, 8)).is_err());
+        assert!(CubeFaces::from_cross(&image(9, 6)).is_err());
+        assert!(CubeFaces::from_cross(&image(3, 2)).is_err());
+    }
+
+    #[test]
+    fn face_centers_point_along_their_axis() {
+        let axes = [
+            [1.0, 0.0, 0.0],
+            [-1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, -1.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [0.0, 0.0, -1.0],
+        ];
+        for (face, axis) in axes.iter().enumerate() {
+            assert_eq!(face_direction(face, 0.0, 0.0).map(|x| x + 0.0), *axis);
+        }
+    }
+
+    #[test]
+    fn neighbouring_faces_share_their_edges() {
+        for t in [-1.0, -0.5, 0.0, 0.5, 1.0] {
+            // +z right, +x left
+            assert_eq!(face_direction(4, 1.0, t), face_direction(0, -1.0, t));
+            // +x right, -z left
+            assert_eq!(face_direction(0, 1.0, t), face_direction(5, -1.0, t));
+            // -z right, -x left
+            assert_eq!(face_direction(5, 1.0, t), face_direction(1, -1.0, t));
+            // +y bottom, +z top
+            assert_eq!(face_direction(2, t, 1.0), face_direction(4, t, -1.0));
+            // +z bottom, -y top
+            assert_eq!(face_direction(4, t, 1.0), face_direction(3, t, -1.0));
+        }
+    }
+
+    #[test]
+    fn equirectangular_faces_sample_their_direction() {
+        // red is the column and green the row of the panorama
+        let panorama =
+            image::Rgba32FImage::from_fn(8, 4, |x, y| image::Rgba([x as f32, y as f32, 0.0, 1.0]));
+        // one texel per face, sampled at its center
+        let cube = CubeFaces::from_equirectangular(&image::DynamicImage::ImageRgba32F(panorama), 1)
+            .unwrap();
+        let sample = |face: usize| cube.faces[face].get_pixel(0, 0).0;
+
+        // the horizon is in the middle row
+        assert_eq!(sample(0), [3.5, 1.5, 0.0, 1.0]);
+        assert_eq!(sample(4), [5.5, 1.5, 0.0, 1.0]);
+        assert_eq!(sample(5), [1.5, 1.5, 0.0, 1.0]);
+        // -x is at the seam, between the last and the first column
+        assert_eq!(sample(1), [3.5, 1.5, 0.0, 1.0]);
+        // the poles clamp to the first and last row
+        assert_eq!(sample(2)[1], 0.0);
+        assert_eq!(sample(3)[1], 3.0);
+    }
+
+    #[test]
+    fn mip_levels_of_faces_average_without_clamping() {
+        let face = image::Rgba32FImage::from_fn(2, 2, |x, y| {
+            image::Rgba([(x + y * 2) as f32 * 4.0, 0.0, 0.0, 1.0])
+        });
+
+        let level = downsample(&face);
+        assert_eq!(level.dimensions(), (1, 1));
+        // hdr values above 1 survive
+        assert_eq!(level.get_pixel(0, 0).0, [6.0, 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn odd_faces_keep_their_last_row_and_column() {
+        // only the last column and row are bright
+        let face = image::Rgba32FImage::from_fn(3, 3, |x, y| {
+            let value = if x == 2 || y == 2 { 9.0 } else { 0.0 };
+            image::Rgba([value, 0.0, 0.0, 1.0])
+        });
+
+        let level = downsample(&face);
+        assert_eq!(level.dimensions(), (1, 1));
+        // the mean of the face, 5 of 9 pixels are bright
+        assert!((level.get_pixel(0, 0).0[0] - 5.0).abs() < 1e-5);
+    }
+}
@@ -1,5 +1,13 @@
 use image::GenericImageView;
 
+mod cube;
+mod settings;
+mod volume;
+
+pub use cube::CubeFaces;
+pub use settings::{SamplerSettings, TextureSettings};
+pub use volume::{R16Uint, R32Uint, R8Uint, Rgba8, VolumeTexture, VoxelFormat};
+
 pub struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
@@ -8,15 +16,31 @@ pub struct Texture {
 
 impl Texture {
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+    /// the format of camera depth buffers, the stencil aspect is used by materials with stencil state
+    pub const DEPTH_STENCIL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth24PlusStencil8;
 
     pub fn create_depth_texture_non_comparison_sampler(
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
         label: &str,
+    ) -> Self {
+        Self::create_depth_texture_non_comparison_sampler_sized(
+            device,
+            config.width,
+            config.height,
+            label,
+        )
+    }
+
+    pub fn create_depth_texture_non_comparison_sampler_sized(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        label: &str,
     ) -> Self {
         let size = wgpu::Extent3d {
-            width: config.width,
-            height: config.height,
+            width,
+            height,
             depth_or_array_layers: 1,
         };
         let desc = wgpu::TextureDescriptor {
@@ -26,7 +50,9 @@ impl Texture {
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: Self::DEPTH_FORMAT,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST,
             view_formats: &[Self::DEPTH_FORMAT],
         };
         let texture = device.create_texture(&desc);
@@ -54,10 +80,25 @@ impl Texture {
     pub fn cretate_depth_texture(
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
+    ) -> Self {
+        Self::create_depth_texture_sized(device, config.width, config.height)
+    }
+
+    pub fn create_depth_texture_sized(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        Self::create_depth_texture_multisampled(device, width, height, 1)
+    }
+
+    /// a depth and stencil texture with `sample_count` samples per pixel, the adapter has to support the count.
+    /// it can't be copied, sample its depth through a view with `wgpu::TextureAspect::DepthOnly`
+    pub fn create_depth_texture_multisampled(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        sample_count: u32,
     ) -> Self {
         let size = wgpu::Extent3d {
-            width: config.width,
-            height: config.height,
+            width,
+            height,
             depth_or_array_layers: 1,
         };
 
@@ -65,11 +106,11 @@ impl Texture {
             label: None,
             size,
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
-            format: Self::DEPTH_FORMAT,
+            format: Self::DEPTH_STENCIL_FORMAT,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[Self::DEPTH_FORMAT],
+            view_formats: &[Self::DEPTH_STENCIL_FORMAT],
         };
 
         let texture = device.create_texture(&desc);
@@ -94,66 +135,186 @@ impl Texture {
         }
     }
 
+    /// a square depth texture with `layers` layers and a comparison sampler, viewed as a 2d array.
+    /// render into single layers with views of their own
+    pub fn create_depth_texture_array(
+        device: &wgpu::Device,
+        size: u32,
+        layers: u32,
+        label: &str,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: layers,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    /// a color texture that can be rendered to and then sampled by other materials
+    pub fn create_render_target(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        label: &str,
+    ) -> Self {
+        Self::create_render_target_multisampled(device, width, height, format, 1, label)
+    }
+
+    /// a color texture with `sample_count` samples per pixel, resolve it into a normal render target
+    pub fn create_render_target_multisampled(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        label: &str,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
     pub fn from_bytes(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         bytes: &[u8],
     ) -> Result<Self, String> {
-        let img = image::load_from_memory(bytes).unwrap();
+        let img = image::load_from_memory(bytes).map_err(|e| e.to_string())?;
         Self::from_image(device, queue, &img)
     }
 
+    /// an srgb texture with mipmaps and a linear sampler
     pub fn from_image(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         img: &image::DynamicImage,
+    ) -> Result<Self, String> {
+        Self::from_image_with(device, queue, img, &TextureSettings::default())
+    }
+
+    pub fn from_image_with(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        settings: &TextureSettings,
     ) -> Result<Self, String> {
         let rgba = img.to_rgba8();
         let dimensions = img.dimensions();
+        if dimensions.0 == 0 || dimensions.1 == 0 {
+            return Err("the image is empty".to_string());
+        }
 
         let size = wgpu::Extent3d {
             width: dimensions.0,
             height: dimensions.1,
             depth_or_array_layers: 1,
         };
-        let format = wgpu::TextureFormat::Rgba8UnormSrgb;
+        let mip_level_count = if settings.mipmaps {
+            Self::mip_level_count(dimensions.0, dimensions.1)
+        } else {
+            1
+        };
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: None,
             size,
-            mip_level_count: 1,
+            mip_level_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format,
+            format: settings.format(),
             usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
             view_formats: &[],
         });
 
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                aspect: wgpu::TextureAspect::All,
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            &rgba,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1),
-            },
-            size,
-        );
+        let mut level = rgba;
+        for mip_level in 0..mip_level_count {
+            if mip_level > 0 {
+                level = downsample(&level, settings.srgb);
+            }
+
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    aspect: wgpu::TextureAspect::All,
+                    texture: &texture,
+                    mip_level,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                &level,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * level.width()),
+                    rows_per_image: Some(level.height()),
+                },
+                wgpu::Extent3d {
+                    width: level.width(),
+                    height: level.height(),
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            mipmap_filter: wgpu::FilterMode::Linear,
-            ..Default::default()
-        });
+        let sampler = settings.sampler.create_sampler(device);
 
         Ok(Self {
             texture,
@@ -161,4 +322,185 @@ impl Texture {
             sampler,
         })
     }
+
+    /// the levels of a full mip chain, down to 1x1
+    pub fn mip_level_count(width: u32, height: u32) -> u32 {
+        32 - width.max(height).max(1).leading_zeros()
+    }
+}
+
+/// the next mip level of an srgb or linear image, alpha is always linear
+fn downsample(image: &image::RgbaImage, srgb: bool) -> image::RgbaImage {
+    let is_srgb = |channel: usize| srgb && channel < 3;
+
+    downsample_with(
+        image,
+        |channel, value| {
+            let value = value as f32 / 255.0;
+            if is_srgb(channel) {
+                srgb_to_linear(value)
+            } else {
+                value
+            }
+        },
+        |channel, value| {
+            let value = if is_srgb(channel) {
+                linear_to_srgb(value)
+            } else {
+                value
+            };
+            (value.clamp(0.0, 1.0) * 255.0 + 0.5) as u8
+        },
+    )
+}
+
+/// the next mip level, every pixel is the average of 2x2 pixels.
+/// odd sides take a weighted average of 3 pixels so the last row or column is not dropped.
+/// channels are averaged after `to_linear` and stored with `from_linear`, both get the channel index
+pub(crate) fn downsample_with<P: image::Pixel>(
+    image: &image::ImageBuffer<P, Vec<P::Subpixel>>,
+    to_linear: impl Fn(usize, P::Subpixel) -> f32,
+    from_linear: impl Fn(usize, f32) -> P::Subpixel,
+) -> image::ImageBuffer<P, Vec<P::Subpixel>> {
+    let width = (image.width() / 2).max(1);
+    let height = (image.height() / 2).max(1);
+
+    image::ImageBuffer::from_fn(width, height, |x, y| {
+        // pixels have at most 4 channels
+        let mut sum = [0.0f32; 4];
+        for (sx, wx) in box_filter_taps(image.width(), x) {
+            for (sy, wy) in box_filter_taps(image.height(), y) {
+                let pixel = image.get_pixel(sx, sy);
+                for (channel, (sum, value)) in sum.iter_mut().zip(pixel.channels()).enumerate() {
+                    *sum += to_linear(channel, *value) * wx * wy;
+                }
+            }
+        }
+
+        let mut pixel = *image.get_pixel(x * 2, y * 2);
+        for (channel, (value, sum)) in pixel.channels_mut().iter_mut().zip(sum).enumerate() {
+            *value = from_linear(channel, sum);
+        }
+        pixel
+    })
+}
+
+/// the source pixels and their weights for pixel `index` of the next mip level
+/// along a side of `size`. odd sides are filtered with 3 taps whose weights shift along the side,
+/// every source pixel adds up to the same weight. unused taps have a weight of 0
+fn box_filter_taps(size: u32, index: u32) -> [(u32, f32); 3] {
+    let start = index * 2;
+    if size <= 1 {
+        [(0, 1.0), (0, 0.0), (0, 0.0)]
+    } else if size.is_multiple_of(2) {
+        [(start, 0.5), (start + 1, 0.5), (start, 0.0)]
+    } else {
+        let half = (size / 2) as f32;
+        let index = index as f32;
+        let total = size as f32;
+        [
+            (start, (half - index) / total),
+            (start + 1, half / total),
+            (start + 2, (index + 1.0) / total),
+        ]
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mip_chains_go_down_to_one_pixel() {
+        assert_eq!(Texture::mip_level_count(1, 1), 1);
+        assert_eq!(Texture::mip_level_count(2, 2), 2);
+        assert_eq!(Texture::mip_level_count(256, 256), 9);
+        // odd sizes round down every level, 5x5 -> 2x2 -> 1x1
+        assert_eq!(Texture::mip_level_count(5, 5), 3);
+        assert_eq!(Texture::mip_level_count(255, 255), 8);
+        // the longer side decides
+        assert_eq!(Texture::mip_level_count(256, 1), 9);
+        assert_eq!(Texture::mip_level_count(3, 64), 7);
+        assert_eq!(Texture::mip_level_count(0, 0), 1);
+    }
+
+    #[test]
+    fn downsample_filters_odd_sizes_with_three_taps() {
+        let image = image::RgbaImage::from_fn(3, 5, |x, y| {
+            image::Rgba([x as u8 * 100, y as u8 * 50, 0, 255])
+        });
+
+        let level = downsample(&image, false);
+        assert_eq!(level.dimensions(), (1, 2));
+        // the middle row of 5 is shared, the last column and row still count
+        assert_eq!(level.get_pixel(0, 0).0, [100, 40, 0, 255]);
+        assert_eq!(level.get_pixel(0, 1).0, [100, 160, 0, 255]);
+
+        // the longer side keeps halving while the other one stays at 1
+        let level = downsample(&level, false);
+        assert_eq!(level.dimensions(), (1, 1));
+        assert_eq!(level.get_pixel(0, 0).0, [100, 100, 0, 255]);
+        assert_eq!(downsample(&level, false).dimensions(), (1, 1));
+    }
+
+    #[test]
+    fn box_filter_taps_weigh_every_pixel_the_same() {
+        for size in [1, 2, 3, 6, 7, 255] {
+            let mut weights = vec![0.0; size as usize];
+            for index in 0..(size / 2).max(1) {
+                let taps = box_filter_taps(size, index);
+                let sum: f32 = taps.iter().map(|(_, weight)| weight).sum();
+                assert!((sum - 1.0).abs() < 1e-5, "{} {}", size, index);
+                for (pixel, weight) in taps {
+                    weights[pixel as usize] += weight;
+                }
+            }
+
+            let expected = weights[0];
+            assert!(
+                weights.iter().all(|x| (x - expected).abs() < 1e-5),
+                "{}: {:?}",
+                size,
+                weights
+            );
+        }
+    }
+
+    #[test]
+    fn downsample_averages_srgb_in_linear_space() {
+        let image = image::RgbaImage::from_fn(2, 2, |x, _| {
+            let value = if x == 0 { 0 } else { 255 };
+            image::Rgba([value, value, value, value])
+        });
+
+        // half of the light is 188 in srgb, not 128
+        assert_eq!(
+            downsample(&image, true).get_pixel(0, 0).0,
+            [188, 188, 188, 128]
+        );
+        assert_eq!(
+            downsample(&image, false).get_pixel(0, 0).0,
+            [128, 128, 128, 128]
+        );
+
+        // a flat color keeps its value
+        let gray = image::RgbaImage::from_pixel(4, 4, image::Rgba([90, 90, 90, 255]));
+        assert_eq!(downsample(&gray, true).get_pixel(1, 1).0, [90, 90, 90, 255]);
+    }
 }
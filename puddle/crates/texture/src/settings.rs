@@ -0,0 +1,122 @@
+/// how a texture is sampled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SamplerSettings {
+    pub address_mode_u: wgpu::AddressMode,
+    pub address_mode_v: wgpu::AddressMode,
+    pub address_mode_w: wgpu::AddressMode,
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    pub mipmap_filter: wgpu::FilterMode,
+    /// 1 turns it off, up to 16. only used when every filter is linear
+    pub anisotropy: u16,
+}
+
+impl Default for SamplerSettings {
+    /// linear filtering, clamped at the edges
+    fn default() -> Self {
+        Self {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            anisotropy: 1,
+        }
+    }
+}
+
+impl SamplerSettings {
+    /// linear filtering, tiled in every direction
+    pub fn repeat() -> Self {
+        Self::default().address_mode(wgpu::AddressMode::Repeat)
+    }
+
+    /// keeps the pixels sharp up close, for pixel art and lookup textures
+    pub fn nearest() -> Self {
+        Self {
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        }
+    }
+
+    /// the same address mode in every direction
+    pub fn address_mode(mut self, mode: wgpu::AddressMode) -> Self {
+        self.address_mode_u = mode;
+        self.address_mode_v = mode;
+        self.address_mode_w = mode;
+        self
+    }
+
+    pub fn anisotropy(mut self, anisotropy: u16) -> Self {
+        self.anisotropy = anisotropy;
+        self
+    }
+
+    pub fn descriptor<'a>(&self, label: Option<&'a str>) -> wgpu::SamplerDescriptor<'a> {
+        let linear = self.mag_filter == wgpu::FilterMode::Linear
+            && self.min_filter == wgpu::FilterMode::Linear
+            && self.mipmap_filter == wgpu::FilterMode::Linear;
+
+        wgpu::SamplerDescriptor {
+            label,
+            address_mode_u: self.address_mode_u,
+            address_mode_v: self.address_mode_v,
+            address_mode_w: self.address_mode_w,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
+            // wgpu rejects anisotropy with nearest filters
+            anisotropy_clamp: if linear {
+                self.anisotropy.clamp(1, 16)
+            } else {
+                1
+            },
+            ..Default::default()
+        }
+    }
+
+    pub fn create_sampler(&self, device: &wgpu::Device) -> wgpu::Sampler {
+        device.create_sampler(&self.descriptor(None))
+    }
+}
+
+/// how `Texture::from_image_with` turns an image into a texture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextureSettings {
+    /// colors are stored in srgb, turn it off for normal maps and other data
+    pub srgb: bool,
+    /// generates the full mip chain on the cpu with a box filter
+    pub mipmaps: bool,
+    pub sampler: SamplerSettings,
+}
+
+impl Default for TextureSettings {
+    fn default() -> Self {
+        Self {
+            srgb: true,
+            mipmaps: true,
+            sampler: SamplerSettings::default(),
+        }
+    }
+}
+
+impl TextureSettings {
+    /// for normal maps, roughness and other textures that aren't colors
+    pub fn linear() -> Self {
+        Self {
+            srgb: false,
+            ..Default::default()
+        }
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        if self.srgb {
+            wgpu::TextureFormat::Rgba8UnormSrgb
+        } else {
+            wgpu::TextureFormat::Rgba8Unorm
+        }
+    }
+}
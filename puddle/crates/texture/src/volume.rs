@@ -0,0 +1,426 @@
+use std::marker::PhantomData;
+
+use crate::Texture;
+
+/// the texel type of a `VolumeTexture`
+pub trait VoxelFormat: Send + Sync + 'static {
+    type Texel: bytemuck::Pod + Send + Sync;
+
+    const FORMAT: wgpu::TextureFormat;
+    const SAMPLE_TYPE: wgpu::TextureSampleType;
+
+    /// one texel of the next mip level from the texels it covers,
+    /// 2x2x2 or up to 3x3x3 at the end of odd sides
+    fn downsample(texels: &[Self::Texel]) -> Self::Texel;
+}
+
+/// voxel ids up to 255, `texture_3d<u32>` in wgsl.
+/// a coarser mip level keeps the largest id, so it is solid where any of its voxels is
+pub struct R8Uint;
+
+/// voxel ids up to 65535, `texture_3d<u32>` in wgsl
+pub struct R16Uint;
+
+/// voxel ids that compute shaders can write, `texture_storage_3d<r32uint, write>` in wgsl
+pub struct R32Uint;
+
+/// colors that can be filtered, `texture_3d<f32>` in wgsl. mip levels are averaged
+pub struct Rgba8;
+
+impl VoxelFormat for R8Uint {
+    type Texel = u8;
+
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Uint;
+    const SAMPLE_TYPE: wgpu::TextureSampleType = wgpu::TextureSampleType::Uint;
+
+    fn downsample(texels: &[u8]) -> u8 {
+        texels.iter().copied().max().unwrap_or(0)
+    }
+}
+
+impl VoxelFormat for R16Uint {
+    type Texel = u16;
+
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R16Uint;
+    const SAMPLE_TYPE: wgpu::TextureSampleType = wgpu::TextureSampleType::Uint;
+
+    fn downsample(texels: &[u16]) -> u16 {
+        texels.iter().copied().max().unwrap_or(0)
+    }
+}
+
+impl VoxelFormat for R32Uint {
+    type Texel = u32;
+
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Uint;
+    const SAMPLE_TYPE: wgpu::TextureSampleType = wgpu::TextureSampleType::Uint;
+
+    fn downsample(texels: &[u32]) -> u32 {
+        texels.iter().copied().max().unwrap_or(0)
+    }
+}
+
+impl VoxelFormat for Rgba8 {
+    type Texel = [u8; 4];
+
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+    const SAMPLE_TYPE: wgpu::TextureSampleType =
+        wgpu::TextureSampleType::Float { filterable: true };
+
+    fn downsample(texels: &[[u8; 4]]) -> [u8; 4] {
+        let count = texels.len().max(1) as u32;
+        std::array::from_fn(|i| {
+            let sum: u32 = texels.iter().map(|x| x[i] as u32).sum();
+            ((sum + count / 2) / count) as u8
+        })
+    }
+}
+
+/// a 3d texture of voxels, the texels are x first, then y, then z
+pub struct VolumeTexture<F: VoxelFormat> {
+    pub texture: wgpu::Texture,
+    /// every mip level
+    pub view: wgpu::TextureView,
+    size: [u32; 3],
+    format: PhantomData<F>,
+}
+
+impl<F: VoxelFormat> VolumeTexture<F> {
+    /// a volume without mip levels that can be sampled and written from the cpu
+    pub fn new(device: &wgpu::Device, size: [u32; 3], label: &str) -> Self {
+        Self::with_options(device, size, false, wgpu::TextureUsages::empty(), label)
+    }
+
+    /// `mipmaps` adds levels down to 1x1x1, they are filled by `write`.
+    /// `usage` is added to `TEXTURE_BINDING | COPY_DST`, like `STORAGE_BINDING` for compute shaders
+    pub fn with_options(
+        device: &wgpu::Device,
+        size: [u32; 3],
+        mipmaps: bool,
+        usage: wgpu::TextureUsages,
+        label: &str,
+    ) -> Self {
+        let size = size.map(|x| x.max(1));
+        let mip_level_count = if mipmaps {
+            Texture::mip_level_count(size[0].max(size[1]), size[2])
+        } else {
+            1
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size[0],
+                height: size[1],
+                depth_or_array_layers: size[2],
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: F::FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST | usage,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self {
+            texture,
+            view,
+            size,
+            format: PhantomData,
+        }
+    }
+
+    pub fn size(&self) -> [u32; 3] {
+        self.size
+    }
+
+    pub fn mip_level_count(&self) -> u32 {
+        self.texture.mip_level_count()
+    }
+
+    /// another view of every mip level, for materials that own their views
+    pub fn create_view(&self) -> wgpu::TextureView {
+        self.texture
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    /// a view of one level, storage textures can only bind one
+    pub fn create_mip_view(&self, level: u32) -> wgpu::TextureView {
+        self.texture.create_view(&wgpu::TextureViewDescriptor {
+            base_mip_level: level,
+            mip_level_count: Some(1),
+            ..Default::default()
+        })
+    }
+
+    /// the binding type of `view` for materials
+    pub fn binding_type(&self) -> wgpu::BindingType {
+        wgpu::BindingType::Texture {
+            sample_type: F::SAMPLE_TYPE,
+            view_dimension: wgpu::TextureViewDimension::D3,
+            multisampled: false,
+        }
+    }
+
+    /// the binding type of a view from `create_mip_view` for compute shaders
+    pub fn storage_binding_type(&self, access: wgpu::StorageTextureAccess) -> wgpu::BindingType {
+        wgpu::BindingType::StorageTexture {
+            access,
+            format: F::FORMAT,
+            view_dimension: wgpu::TextureViewDimension::D3,
+        }
+    }
+
+    /// writes every voxel and fills the mip levels from them
+    pub fn write(&self, queue: &wgpu::Queue, data: &[F::Texel]) -> Result<(), String> {
+        self.write_region(queue, [0; 3], self.size, data)?;
+
+        let mut level = data.to_vec();
+        let mut size = self.size;
+        for mip_level in 1..self.mip_level_count() {
+            (level, size) = downsample::<F>(&level, size);
+            self.write_level(queue, mip_level, [0; 3], size, &level);
+        }
+
+        Ok(())
+    }
+
+    /// writes a box of voxels of the first level, the mip levels aren't updated.
+    /// `data` holds `size[0] * size[1] * size[2]` texels
+    pub fn write_region(
+        &self,
+        queue: &wgpu::Queue,
+        origin: [u32; 3],
+        size: [u32; 3],
+        data: &[F::Texel],
+    ) -> Result<(), String> {
+        check_region(self.size, origin, size, data.len())?;
+
+        self.write_level(queue, 0, origin, size, data);
+        Ok(())
+    }
+
+    /// the bytes of a row of voxels in the buffers `copy_from_buffer` reads, a multiple of 256
+    pub fn padded_bytes_per_row(&self) -> u32 {
+        padded_bytes_per_row(self.size[0], std::mem::size_of::<F::Texel>() as u32)
+    }
+
+    /// the size of a buffer that holds every voxel of the first level with padded rows
+    pub fn padded_buffer_size(&self) -> wgpu::BufferAddress {
+        self.padded_bytes_per_row() as wgpu::BufferAddress
+            * self.size[1] as wgpu::BufferAddress
+            * self.size[2] as wgpu::BufferAddress
+    }
+
+    /// records a copy of the first level from a buffer of `padded_buffer_size` bytes,
+    /// like one a compute shader filled. the mip levels aren't updated
+    pub fn copy_from_buffer(&self, encoder: &mut wgpu::CommandEncoder, buffer: &wgpu::Buffer) {
+        encoder.copy_buffer_to_texture(
+            wgpu::ImageCopyBuffer {
+                buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.padded_bytes_per_row()),
+                    rows_per_image: Some(self.size[1]),
+                },
+            },
+            self.texture.as_image_copy(),
+            wgpu::Extent3d {
+                width: self.size[0],
+                height: self.size[1],
+                depth_or_array_layers: self.size[2],
+            },
+        );
+    }
+
+    fn write_level(
+        &self,
+        queue: &wgpu::Queue,
+        mip_level: u32,
+        origin: [u32; 3],
+        size: [u32; 3],
+        data: &[F::Texel],
+    ) {
+        // the rows are tightly packed, only copies from buffers need them aligned to 256 bytes
+        let texel_size = std::mem::size_of::<F::Texel>() as u32;
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture: &self.texture,
+                mip_level,
+                origin: wgpu::Origin3d {
+                    x: origin[0],
+                    y: origin[1],
+                    z: origin[2],
+                },
+            },
+            bytemuck::cast_slice(data),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(size[0] * texel_size),
+                rows_per_image: Some(size[1]),
+            },
+            wgpu::Extent3d {
+                width: size[0],
+                height: size[1],
+                depth_or_array_layers: size[2],
+            },
+        );
+    }
+}
+
+/// copies from buffers need rows aligned to `COPY_BYTES_PER_ROW_ALIGNMENT`
+fn padded_bytes_per_row(width: u32, texel_size: u32) -> u32 {
+    (width * texel_size).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+}
+
+/// whether a region of `len` texels fits into a volume of `volume` texels
+fn check_region(
+    volume: [u32; 3],
+    origin: [u32; 3],
+    size: [u32; 3],
+    len: usize,
+) -> Result<(), String> {
+    for i in 0..3 {
+        if origin[i]
+            .checked_add(size[i])
+            .is_none_or(|end| end > volume[i])
+        {
+            return Err(format!(
+                "the region {:?} + {:?} is outside the volume of size {:?}",
+                origin, size, volume
+            ));
+        }
+    }
+    let count = size.iter().map(|x| *x as usize).product::<usize>();
+    if len != count {
+        return Err(format!(
+            "a region of size {:?} has {} voxels, got {}",
+            size, count, len
+        ));
+    }
+
+    Ok(())
+}
+
+/// the next mip level, sizes are halved and rounded down to at least 1.
+/// the last texel of an odd side also covers the voxel that is left over
+fn downsample<F: VoxelFormat>(data: &[F::Texel], size: [u32; 3]) -> (Vec<F::Texel>, [u32; 3]) {
+    let next = size.map(|x| (x / 2).max(1));
+    // the voxels texel `i` of the next level covers along `axis`
+    let covered = |axis: usize, i: u32| {
+        let end = if i + 1 == next[axis] {
+            size[axis]
+        } else {
+            i * 2 + 2
+        };
+        i * 2..end
+    };
+
+    let mut level = Vec::with_capacity(next.iter().map(|x| *x as usize).product());
+    let mut texels = Vec::with_capacity(27);
+    for z in 0..next[2] {
+        for y in 0..next[1] {
+            for x in 0..next[0] {
+                texels.clear();
+                for vz in covered(2, z) {
+                    for vy in covered(1, y) {
+                        for vx in covered(0, x) {
+                            texels
+                                .push(data[(vx + vy * size[0] + vz * size[0] * size[1]) as usize]);
+                        }
+                    }
+                }
+                level.push(F::downsample(&texels));
+            }
+        }
+    }
+
+    (level, next)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn id_levels_keep_the_largest_id() {
+        let texels = [0, 3, 0, 0, 7, 0, 1, 0];
+        assert_eq!(R8Uint::downsample(&texels), 7);
+        assert_eq!(R16Uint::downsample(&texels.map(|x| x as u16 * 1000)), 7000);
+        assert_eq!(R32Uint::downsample(&[0; 8]), 0);
+    }
+
+    #[test]
+    fn color_levels_are_averaged() {
+        let mut texels = [[0, 0, 0, 255]; 8];
+        texels[0] = [255, 8, 0, 255];
+        texels[1] = [255, 0, 0, 0];
+
+        // rounded to the nearest value
+        assert_eq!(Rgba8::downsample(&texels), [64, 1, 0, 223]);
+    }
+
+    #[test]
+    fn odd_sizes_fold_the_last_voxel_into_the_last_texel() {
+        // 3x1x1, the only texel covers all three voxels
+        let (level, size) = downsample::<R8Uint>(&[1, 2, 5], [3, 1, 1]);
+        assert_eq!(size, [1, 1, 1]);
+        assert_eq!(level, vec![5]);
+
+        let data: Vec<u8> = (0..3 * 5 * 2).map(|x| x as u8).collect();
+        let (level, size) = downsample::<R8Uint>(&data, [3, 5, 2]);
+        assert_eq!(size, [1, 2, 1]);
+        // x 0..3, y 0..2, z 0..2 and x 0..3, y 2..5, z 0..2
+        assert_eq!(level, vec![20, 29]);
+
+        // colors are averaged over all the voxels the texel covers
+        let colors = [[0, 0, 0, 0], [0, 0, 0, 0], [30, 3, 0, 255]];
+        let (level, _) = downsample::<Rgba8>(&colors, [3, 1, 1]);
+        assert_eq!(level, vec![[10, 1, 0, 85]]);
+
+        // a full chain of an odd volume ends at one voxel
+        let mut level = vec![[10, 20, 30, 40]; 7 * 3 * 5];
+        let mut size = [7, 3, 5];
+        while size != [1, 1, 1] {
+            (level, size) = downsample::<Rgba8>(&level, size);
+            assert_eq!(level.len(), size.iter().product::<u32>() as usize);
+        }
+        assert_eq!(level, vec![[10, 20, 30, 40]]);
+    }
+
+    #[test]
+    fn buffer_rows_are_padded_to_256_bytes() {
+        assert_eq!(padded_bytes_per_row(1, 1), 256);
+        assert_eq!(padded_bytes_per_row(256, 1), 256);
+        assert_eq!(padded_bytes_per_row(257, 1), 512);
+        // 100 rgba8 texels
+        assert_eq!(padded_bytes_per_row(100, 4), 512);
+    }
+
+    #[test]
+    fn regions_have_to_fit_into_the_volume() {
+        let volume = [4, 4, 2];
+
+        assert!(check_region(volume, [0; 3], volume, 32).is_ok());
+        assert!(check_region(volume, [3, 3, 1], [1, 1, 1], 1).is_ok());
+        assert!(check_region(volume, [0; 3], [0, 0, 0], 0).is_ok());
+
+        assert!(check_region(volume, [1, 0, 0], [4, 1, 1], 4).is_err());
+        assert!(check_region(volume, [0, 0, 2], [1, 1, 1], 1).is_err());
+        // doesn't overflow
+        assert!(check_region(volume, [u32::MAX, 0, 0], [1, 1, 1], 1).is_err());
+        assert!(check_region(volume, [1, 0, 0], [u32::MAX, 1, 1], 1).is_err());
+    }
+
+    #[test]
+    fn regions_need_one_texel_per_voxel() {
+        let volume = [4, 4, 2];
+
+        assert!(check_region(volume, [0; 3], [2, 2, 2], 7).is_err());
+        assert!(check_region(volume, [0; 3], [2, 2, 2], 9).is_err());
+        assert!(check_region(volume, [0; 3], [2, 2, 2], 8).is_ok());
+    }
+}
@@ -5,3 +5,4 @@ pub use legion;
 pub use texture;
 pub use events;
 pub use input;
+pub use assets;
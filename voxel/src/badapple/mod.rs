@@ -1,52 +1,69 @@
-use image::EncodableLayout;
+use std::collections::HashMap;
 use std::time::Instant;
 
 use crate::{view::Chunktexture, PlaybackPuased};
-use image::GenericImageView;
+use image::{DynamicImage, GenericImageView};
 use legion::system;
-use puddle::{application::log::warn, rendering::wgpu};
+use puddle::application::log::error;
+use puddle::assets::{AssetServer, Handle};
+
+/// how many frames are loaded ahead of the one that is shown
+const PREFETCH: i32 = 30;
 
 #[system(for_each)]
 pub fn bad_apple(
     chunk: &Chunktexture,
     #[resource] paused: &PlaybackPuased,
     #[resource] renderer: &puddle::rendering::Renderer,
+    #[resource] assets: &AssetServer,
     #[state] frame: &Instant,
+    #[state] frames: &mut HashMap<i32, Handle<DynamicImage>>,
+    #[state] shown: &mut (i32, u32),
 ) {
-    if paused.0 {
-        return;
-    }
-
-    let frame = (frame.elapsed().as_secs_f64() * 60.0).floor() as i32;
+    // a paused video keeps the shown frame, it is only read again when its file is reloaded
+    let frame = if paused.0 {
+        shown.0
+    } else {
+        (frame.elapsed().as_secs_f64() * 60.0).floor() as i32
+    };
 
-    let image_path = format!("frames/{}.png", frame);
+    if !paused.0 {
+        // dropping the handles of skipped frames frees them, even if they are still loading
+        frames.retain(|index, _| *index >= frame);
+        for index in frame..frame + PREFETCH {
+            frames
+                .entry(index)
+                .or_insert_with(|| assets.load(format!("frames/{}.png", index)));
+        }
+    }
 
-    let image = match image::open(image_path.clone()) {
-        Ok(r) => r,
-        Err(e) => {
-            warn!("cant load image {}  because : {}", image_path, e);
+    let handle = match frames.get(&frame) {
+        Some(r) => r,
+        None => {
             return;
         }
     };
+    if *shown == (frame, handle.version()) {
+        return;
+    }
+    // failed frames are logged by the asset server
+    let image = match handle.get() {
+        Some(r) => r,
+        None => {
+            return;
+        }
+    };
+    *shown = (frame, handle.version());
+
     let size = image.dimensions();
+    let pixels = image.to_luma8();
 
-    renderer.queue.write_texture(
-        wgpu::ImageCopyTexture {
-            aspect: wgpu::TextureAspect::All,
-            texture: &chunk.texture,
-            mip_level: 0,
-            origin: wgpu::Origin3d::ZERO,
-        },
-        &image.to_luma8().as_bytes(),
-        wgpu::ImageDataLayout {
-            offset: 0,
-            bytes_per_row: Some(size.0),
-            rows_per_image: Some(size.1),
-        },
-        wgpu::Extent3d {
-            width: size.0,
-            height: size.1,
-            depth_or_array_layers: 1,
-        },
-    );
+    if let Err(e) = chunk.volume.write_region(
+        &renderer.queue,
+        [0; 3],
+        [size.0, size.1, 1],
+        pixels.as_raw(),
+    ) {
+        error!("{}", e);
+    }
 }
@@ -0,0 +1,117 @@
+// fills the chunk with terrain, one invocation per 4 voxels along x.
+// they are packed into a u32 each so the buffer can be copied into the r8uint chunk texture
+// value, worley and gradient noise stand in for the noise crate the chunk was generated with on the cpu
+
+struct ChunkSize {
+    size: vec3<u32>,
+    // the rows are padded to 256 bytes for the copy
+    words_per_row: u32,
+}
+
+@group(0) @binding(0)
+    var<storage, read_write> voxels: array<u32>;
+@group(0) @binding(1)
+    var<uniform> chunk: ChunkSize;
+
+const NOISE_RES: f32 = 100.0;
+
+fn hash(p: vec3<i32>) -> u32 {
+    var h = (u32(p.x) * 73856093u) ^ (u32(p.y) * 19349663u) ^ (u32(p.z) * 83492791u);
+    h = (h ^ (h >> 16u)) * 0x45d9f3bu;
+    h = (h ^ (h >> 16u)) * 0x45d9f3bu;
+    return h ^ (h >> 16u);
+}
+
+// -1 to 1
+fn random(p: vec3<i32>) -> f32 {
+    return f32(hash(p)) / 2147483647.5 - 1.0;
+}
+
+fn random3(p: vec3<i32>) -> vec3<f32> {
+    return vec3(random(p), random(p + vec3(17, 59, 113)), random(p + vec3(211, 7, 41)));
+}
+
+fn smooth_step(f: vec3<f32>) -> vec3<f32> {
+    return f * f * (3.0 - 2.0 * f);
+}
+
+fn value_noise(p: vec3<f32>) -> f32 {
+    let cell = vec3<i32>(floor(p));
+    let t = smooth_step(fract(p));
+
+    let x00 = mix(random(cell), random(cell + vec3(1, 0, 0)), t.x);
+    let x10 = mix(random(cell + vec3(0, 1, 0)), random(cell + vec3(1, 1, 0)), t.x);
+    let x01 = mix(random(cell + vec3(0, 0, 1)), random(cell + vec3(1, 0, 1)), t.x);
+    let x11 = mix(random(cell + vec3(0, 1, 1)), random(cell + vec3(1, 1, 1)), t.x);
+
+    return mix(mix(x00, x10, t.y), mix(x01, x11, t.y), t.z);
+}
+
+fn corner(cell: vec3<i32>, f: vec3<f32>, offset: vec3<i32>) -> f32 {
+    return dot(normalize(random3(cell + offset) + vec3(0.0001)), f - vec3<f32>(offset));
+}
+
+fn gradient_noise(p: vec3<f32>) -> f32 {
+    let cell = vec3<i32>(floor(p));
+    let f = fract(p);
+    let t = smooth_step(f);
+
+    let x00 = mix(corner(cell, f, vec3(0, 0, 0)), corner(cell, f, vec3(1, 0, 0)), t.x);
+    let x10 = mix(corner(cell, f, vec3(0, 1, 0)), corner(cell, f, vec3(1, 1, 0)), t.x);
+    let x01 = mix(corner(cell, f, vec3(0, 0, 1)), corner(cell, f, vec3(1, 0, 1)), t.x);
+    let x11 = mix(corner(cell, f, vec3(0, 1, 1)), corner(cell, f, vec3(1, 1, 1)), t.x);
+
+    return mix(mix(x00, x10, t.y), mix(x01, x11, t.y), t.z);
+}
+
+// the distance to the closest feature point, mapped to -1 to 1
+fn worley_noise(p: vec3<f32>) -> f32 {
+    let cell = vec3<i32>(floor(p));
+    let f = fract(p);
+
+    var closest = 2.0;
+    for (var x = -1; x <= 1; x++) {
+        for (var y = -1; y <= 1; y++) {
+            for (var z = -1; z <= 1; z++) {
+                let offset = vec3(x, y, z);
+                let feature = vec3<f32>(offset) + random3(cell + offset) * 0.5 + 0.5;
+                closest = min(closest, distance(feature, f));
+            }
+        }
+    }
+
+    return closest * 2.0 - 1.0;
+}
+
+fn voxel_at(p: vec3<f32>) -> u32 {
+    let pos = p / NOISE_RES;
+    let val = value_noise(pos) + worley_noise(pos) + gradient_noise(pos);
+
+    if (val <= 0.0) {
+        return 0u;
+    }
+    if (gradient_noise(p / 2.0) > 0.0) {
+        return 1u;
+    }
+    if (value_noise(p / 10.0) > 0.0) {
+        return 2u;
+    }
+    return 3u;
+}
+
+@compute @workgroup_size(4, 4, 4)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= chunk.words_per_row || id.y >= chunk.size.y || id.z >= chunk.size.z) {
+        return;
+    }
+
+    var packed = 0u;
+    for (var i = 0u; i < 4u; i++) {
+        let x = id.x * 4u + i;
+        if (x < chunk.size.x) {
+            packed |= voxel_at(vec3<f32>(f32(x), f32(id.y), f32(id.z))) << (i * 8u);
+        }
+    }
+
+    voxels[id.x + (id.y + id.z * chunk.size.y) * chunk.words_per_row] = packed;
+}
@@ -3,6 +3,7 @@ mod camera;
 mod chunk_gen;
 mod skybox;
 mod view;
+use std::collections::HashMap;
 use std::time::Instant;
 
 use badapple::bad_apple_system;
@@ -24,6 +25,7 @@ fn main() {
 
     let mut app = Application::new();
     app.add_plugin(puddle::window::WindowPlugin);
+    app.add_plugin(puddle::assets::AssetPlugin::default());
     app.add_plugin(puddle::rendering::RenderPlugin);
     app.add_plugin(puddle::input::InputPlugin);
 
@@ -44,10 +46,13 @@ fn main() {
         .add(Scheddules::Startup, camera::setup_cam_system());
     app.scheddules
         .add(Scheddules::Update, update_delta_time_system(Instant::now()));
-    //app.scheddules.add(Scheddules::Update, view::load_chunk_system(vec![], 0));
+    app.scheddules
+        .add(Scheddules::Update, view::generate_chunk_system(false));
+    app.scheddules
+        .add(Scheddules::Update, view::copy_generated_chunk_system());
 
     app.scheddules
-        .add(Scheddules::Update, bad_apple_system(Instant::now()));
+        .add(Scheddules::Update, bad_apple_system(Instant::now(), HashMap::new(), (-1, 0)));
 
     app.resources.insert(PlaybackPuased(false));
 
@@ -1,9 +1,5 @@
-struct CameraUniform {
-    view_proj: mat4x4<f32>,
-    camera_pos : vec4<f32>,
-};
-@group(0) @binding(0)
-var<uniform> camera: CameraUniform;
+#import puddle::camera
+#import puddle::voxel
 
 
 struct VertexInput {
@@ -46,103 +42,14 @@ struct Uniforms {
 
 
 
-fn getVoxel(c : vec3<i32>) -> Voxel {
-        let pos = vec3<i32>(c.x, -c.y, c.z / 2);
-        let val = textureLoad(voxel_data, pos, 0).r;
-
-        var voxel = Voxel();
-
-        //voxel.color = color_buffer[val];
-        voxel.color = vec4(0.0);
-
-        if val < 100 { voxel.is_empty = true; voxel.color = vec4(1.0); }
-
-	return voxel;
-}
-
-struct RaycastResult {
-   normal : vec3<f32>,
-   position : vec3<f32>,
-   color : vec4<f32>,
-   distance : f32,
-   hit : bool,
-   stepps : i32,
-}
+fn voxel_is_solid(c : vec3<i32>) -> bool {
+    let pos = vec3<i32>(c.x, -c.y, c.z / 2);
+    let val = textureLoad(voxel_data, pos, 0).r;
 
-struct Voxel {
-    is_empty : bool,
-    color : vec4<f32>,
+    return val >= 100u;
 }
 
 
-fn raycast(origin : vec3<f32>, direction : vec3<f32>) -> RaycastResult  {
-
-    let MAX_RAY_STEPPS : i32 = 500;
-
-    var mapPos = vec3<i32>(floor(origin));
-    var deltaDist = abs(vec3(length(direction)) / direction);
-    let rayStep = vec3<i32>(sign(direction));
-    var sideDist = (sign(direction) * (vec3<f32>(mapPos) - origin) + (sign(direction) * 0.5) + 0.5) * deltaDist; 
-    let dis_step = vec3<f32>(abs(rayStep));
-
-    let ray_normal_dir = ceil(-direction) - direction;
-
-    var normal = vec3<f32>(0.0);
-    var dis = 0.0;
-    var hit = false;
-    var color = vec4(0.5, 1.0, 1.0, 0.0);
-
-    var stepps = 0;
-
-    for (var i=0; i<MAX_RAY_STEPPS; i+=1) {
-        stepps += 1;
-
-        if (sideDist.x < sideDist.y) {
-            if (sideDist.x < sideDist.z) {
-                    sideDist.x += deltaDist.x;
-                    mapPos.x += rayStep.x;
-                    normal = vec3(ray_normal_dir.x, 0.0, 0.0);
-                    dis += dis_step.x;
-                } else {
-                    sideDist.z += deltaDist.z;
-                    mapPos.z += rayStep.z;
-                    normal = vec3(0.0, 0.0, ray_normal_dir.z);
-                    dis += dis_step.z;
-                }
-
-            } else {
-                if (sideDist.y < sideDist.z) {
-                        sideDist.y += deltaDist.y;
-                        mapPos.y += rayStep.y;
-                        normal = vec3(0.0, ray_normal_dir.y, 0.0);
-                        dis += dis_step.y;
-                } else {
-                        sideDist.z += deltaDist.z;
-                        mapPos.z += rayStep.z;
-                        normal = vec3(0.0, 0.0, ray_normal_dir.z);
-                        dis += dis_step.z;
-                }
-            }
-            let voxel = getVoxel(mapPos);
-            if !voxel.is_empty {hit = true; color = voxel.color; break;}
-        }
-
-        var res = RaycastResult();
-        res.normal = normal;
-        res.distance = dis;
-        res.position = vec3<f32>(mapPos) + normal;
-        res.hit = hit;
-        res.color = color;
-        res.stepps = stepps;
-
-        return res;
-}
-
-
-
-
-
-
 
 @fragment
 fn fs_main(in : VertexOutput) -> @location(0) vec4<f32> {
@@ -154,9 +61,9 @@ fn fs_main(in : VertexOutput) -> @location(0) vec4<f32> {
     let cameraPlaneV = vec3(0.0, 1.0, 0.0) * uniforms.height / uniforms.width;
 
     let rayDir = (vec4(cameraDir + screenPos.x * cameraPlaneU + screenPos.y * cameraPlaneV, 1.0) * camera.view_proj).xyz;
-    let rayPos = camera.camera_pos.xyz;
+    let rayPos = camera.camera_eye.xyz;
 
-    let RayRes = raycast(rayPos, rayDir);
+    let RayRes = voxel_raycast(rayPos, rayDir, 500);
 
     if (!RayRes.hit) {
         return vec4(0.0);
@@ -164,7 +71,7 @@ fn fs_main(in : VertexOutput) -> @location(0) vec4<f32> {
 
     switch uniforms.mode {
         case 1: {
-            return vec4(vec3(f32(RayRes.stepps) / 1000.0), 1.0);
+            return vec4(vec3(f32(RayRes.steps) / 1000.0), 1.0);
         }
         default: {
             return vec4(
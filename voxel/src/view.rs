@@ -1,10 +1,14 @@
+use std::sync::Arc;
 use std::time::Instant;
 
-use legion::{system, systems::CommandBuffer};
+use legion::{system, systems::CommandBuffer, Entity};
+use puddle::application::log::error;
+use puddle::assets::AssetServer;
 use puddle::rendering::{
-    wgpu::{self, util::DeviceExt},
-    CameraBindGroupLayout, Material, ModelMatrix, PuddleBindGroupEntry, Renderer, Vertex,
+    wgpu, CameraBindGroupLayout, ComputeMaterial, Dispatch, Material, MaterialResource,
+    ModelMatrix, PuddleBindGroupEntry, RenderQueue, Renderer, StorageVec, Uniform, Vertex,
 };
+use puddle::texture::{R8Uint, VolumeTexture};
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
@@ -15,124 +19,116 @@ pub struct Uniforms {
     pub render_mode: i32,
 }
 
-pub struct UniformBuffer(wgpu::Buffer);
 pub struct Chunktexture {
-    pub texture: wgpu::Texture,
-    size: wgpu::Extent3d,
+    pub volume: VolumeTexture<R8Uint>,
 }
 
-pub const MAP_SIZE: u32 = 200;
-
-fn gen_chunk() -> Vec<u8> {
-    let mut data: Vec<u8> = Vec::with_capacity(MAP_SIZE.pow(3) as usize);
-
-    let noise_res: f64 = 100.0;
-
-    let seed = 0;
-
-    let noise = noise::Worley::new(seed);
-    let noise2 = noise::SuperSimplex::new(seed);
-    let noise3 = noise::Value::new(seed);
-    use noise::NoiseFn;
+/// the voxels the generator writes for the chunk on the same entity, copied into its texture once the compute pass ran.
+/// compute shaders can't write r8uint textures, so every u32 holds 4 voxels with the rows padded for the copy
+pub struct GeneratedChunk {
+    voxels: Arc<wgpu::Buffer>,
+}
 
-    for x in 0..MAP_SIZE {
-        for y in 0..MAP_SIZE {
-            for z in 0..MAP_SIZE {
-                let pos = [
-                    x as f64 / noise_res,
-                    y as f64 / noise_res,
-                    z as f64 / noise_res,
-                ];
-                let val = noise3.get(pos) + noise.get(pos) + noise2.get(pos);
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ChunkSize {
+    size: [u32; 3],
+    words_per_row: u32,
+}
 
-                if val > 0.0 {
-                    let val2 = noise2.get([x as f64 / 2.0, y as f64 / 2.0, z as f64 / 2.0]);
-                    let val3 = noise3.get([x as f64 / 10.0, y as f64 / 10.0, z as f64 / 10.0]);
-                    if val2 > 0.0 {
-                        data.push(1);
-                    } else if val3 > 0.0 {
-                        data.push(2);
-                    } else {
-                        data.push(3);
-                    }
+/// fills the chunk texture with terrain on the gpu, the compute material runs once before the next frame is drawn
+#[system(for_each)]
+pub fn generate_chunk(
+    entity: &Entity,
+    texture: &Chunktexture,
+    #[state] started: &mut bool,
+    #[resource] renderer: &mut Renderer,
+    commands: &mut CommandBuffer,
+) {
+    if *started {
+        return;
+    }
+    *started = true;
+
+    let size = texture.volume.size();
+    let words_per_row = texture.volume.padded_bytes_per_row() / 4;
+    let words = (texture.volume.padded_buffer_size() / 4) as usize;
+
+    let voxels = StorageVec::new(&renderer.device, "generated chunk", vec![0u32; words]);
+    let chunk_size = Uniform::new(
+        &renderer.device,
+        "generated chunk size",
+        ChunkSize {
+            size,
+            words_per_row,
+        },
+    );
 
-                    continue;
-                }
-                data.push(0);
-            }
+    let mut material = match ComputeMaterial::from_wgsl(
+        "generate_chunk.wgsl",
+        include_str!("./generate_chunk.wgsl"),
+    )
+    .entry(voxels.entry_read_write("voxels", wgpu::ShaderStages::COMPUTE))
+    .entry(chunk_size.entry("chunk", wgpu::ShaderStages::COMPUTE))
+    .dispatch(Dispatch::Once)
+    .build(renderer)
+    {
+        Ok(r) => r,
+        Err(e) => {
+            error!("{}", e);
+            return;
         }
-    }
+    };
+    // one invocation per u32
+    material.dispatch_threads([words_per_row, size[1], size[2]]);
 
-    data
+    commands.add_component(*entity, material);
+    commands.add_component(
+        *entity,
+        GeneratedChunk {
+            voxels: voxels.buffer().clone(),
+        },
+    );
 }
 
-use std::thread;
-
+/// copies the generated voxels into the chunk texture after the compute pass wrote them
 #[system(for_each)]
-pub fn load_chunk(
-    texture: &mut Chunktexture,
-    #[state] thread_pool: &mut Vec<thread::JoinHandle<Vec<u8>>>,
-    #[state] state: &mut i32,
-    #[resource] renderer: &mut Renderer,
+pub fn copy_generated_chunk(
+    entity: &Entity,
+    texture: &Chunktexture,
+    material: &ComputeMaterial,
+    generated: &GeneratedChunk,
+    #[resource] renderer: &Renderer,
+    commands: &mut CommandBuffer,
 ) {
-    if *state == 0 {
-        let handle = thread::spawn(gen_chunk);
-        thread_pool.push(handle);
-        *state = 1;
+    // a material that runs once is paused after the frame with its dispatch was submitted
+    if material.dispatch != Dispatch::Paused {
+        return;
     }
 
-    let handle = thread_pool.first();
-
-    if *state == 1 && handle.is_some() {
-        if handle.unwrap().is_finished() {
-            let handle = thread_pool.remove(0);
-            let data = handle.join().unwrap();
-
-            renderer.queue.write_texture(
-                wgpu::ImageCopyTexture {
-                    aspect: wgpu::TextureAspect::All,
-                    texture: &texture.texture,
-                    mip_level: 0,
-                    origin: wgpu::Origin3d::ZERO,
-                },
-                &data,
-                wgpu::ImageDataLayout {
-                    offset: 0,
-                    bytes_per_row: Some(MAP_SIZE),
-                    rows_per_image: Some(MAP_SIZE),
-                },
-                texture.size,
-            );
+    let mut encoder = renderer
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("copy generated chunk"),
+        });
+    texture
+        .volume
+        .copy_from_buffer(&mut encoder, &generated.voxels);
+    renderer.queue.submit(Some(encoder.finish()));
 
-            *state = 2;
-        }
-    }
+    commands.remove_component::<ComputeMaterial>(*entity);
+    commands.remove_component::<GeneratedChunk>(*entity);
 }
 
 #[system]
 pub fn add_view(
     #[resource] renderer: &mut Renderer,
+    #[resource] assets: &AssetServer,
     #[resource] camera_bind_group_layout: &CameraBindGroupLayout,
     commands: &mut CommandBuffer,
 ) {
-    let tex_size = wgpu::Extent3d {
-        width: 256,
-        height: 144,
-        depth_or_array_layers: 1,
-    };
-
-    let texture = renderer.device.create_texture(&wgpu::TextureDescriptor {
-        label: None,
-        size: tex_size,
-        mip_level_count: 1,
-        sample_count: 1,
-        dimension: wgpu::TextureDimension::D3,
-        format: wgpu::TextureFormat::R8Uint,
-        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-        view_formats: &[],
-    });
-
-    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let volume = VolumeTexture::<R8Uint>::new(&renderer.device, [256, 144, 1], "chunk");
+    let view = Arc::new(volume.create_view());
 
     let mut colors: Vec<[f32; 4]> = Vec::with_capacity(255);
     colors.push([1.0, 1.0, 1.0, 1.0]);
@@ -141,116 +137,97 @@ pub fn add_view(
     colors.push([0.25, 0.25, 0.25, 1.0]);
     colors.push([0.16, 0.16, 0.16, 1.0]);
 
-    let color_buffer = renderer
-        .device
-        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("colro buffer"),
-            contents: bytemuck::cast_slice(&colors),
-            usage: wgpu::BufferUsages::STORAGE,
-        });
+    let colors = StorageVec::new(&renderer.device, "color buffer", colors);
 
-    let uniforms = Uniforms {
-        time: 0.5,
-        width: 0.0,
-        height: 0.0,
-        render_mode: 100,
-    };
-
-    let uniform_buffer = renderer
-        .device
-        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Uniform Buffer"),
-            contents: bytemuck::cast_slice(&[uniforms]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
+    let uniforms = Uniform::new(
+        &renderer.device,
+        "Uniform Buffer",
+        Uniforms {
+            time: 0.5,
+            width: 0.0,
+            height: 0.0,
+            render_mode: 100,
+        },
+    );
 
     let entries = vec![
+        uniforms.entry("uniforms", wgpu::ShaderStages::FRAGMENT),
         PuddleBindGroupEntry {
-            ty: wgpu::BindingType::Buffer {
-                ty: wgpu::BufferBindingType::Uniform,
-                has_dynamic_offset: false,
-                min_binding_size: None,
-            },
+            name: "voxel_data".to_string(),
+            ty: volume.binding_type(),
+            resource: MaterialResource::TextureView(view),
             visibility: wgpu::ShaderStages::FRAGMENT,
-            resource: wgpu::BindingResource::Buffer(uniform_buffer.as_entire_buffer_binding()),
-        },
-        PuddleBindGroupEntry {
-            ty: wgpu::BindingType::Texture {
-                sample_type: wgpu::TextureSampleType::Uint,
-                view_dimension: wgpu::TextureViewDimension::D3,
-                multisampled: false,
-            },
-            resource: wgpu::BindingResource::TextureView(&view),
-            visibility: wgpu::ShaderStages::FRAGMENT,
-        },
-        PuddleBindGroupEntry {
-            ty: wgpu::BindingType::Buffer {
-                ty: wgpu::BufferBindingType::Storage { read_only: true },
-                has_dynamic_offset: false,
-                min_binding_size: None,
-            },
-            visibility: wgpu::ShaderStages::FRAGMENT,
-            resource: wgpu::BindingResource::Buffer(color_buffer.as_entire_buffer_binding()),
         },
+        colors.entry("color_buffer", wgpu::ShaderStages::FRAGMENT),
     ];
 
-    let mut material = Material::new(
-        renderer,
-        entries,
-        camera_bind_group_layout,
-        wgpu::include_wgsl!("./shader.wgsl"),
-        false,
-    );
+    // debug builds load it from the asset root so edits show up without a rebuild,
+    // release builds don't depend on the source tree
+    let shader = if cfg!(debug_assertions) {
+        Material::from_file(assets.root().join("src/shader.wgsl"))
+    } else {
+        Material::from_wgsl("shader.wgsl", include_str!("./shader.wgsl"))
+    };
+    let mut material = match shader
+        .entries(entries)
+        .depth_test(false)
+        // blends the voxels over the skybox
+        .queue(RenderQueue::Background)
+        .queue_priority(1)
+        .build(renderer, camera_bind_group_layout)
+    {
+        Ok(r) => r,
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
 
     let vertices = vec![
         Vertex {
             position: [0.0, 0.0, 0.0],
             uv: [-1.0, -1.0],
+            normal: [0.0, 0.0, 0.0],
         },
         Vertex {
             position: [1.0, 0.0, 0.0],
             uv: [1.0, -1.0],
+            normal: [0.0, 0.0, 0.0],
         },
         Vertex {
             position: [1.0, 1.0, 0.0],
             uv: [1.0, 1.0],
+            normal: [0.0, 0.0, 0.0],
         },
         Vertex {
             position: [0.0, 1.0, 0.0],
             uv: [-1.0, 1.0],
+            normal: [0.0, 0.0, 0.0],
         },
     ];
 
     let indecies = vec![0, 1, 2, 2, 3, 0];
 
+    // fullscreen quad, the vertex shader ignores the camera
+    material.frustum_culling = false;
     material.add_mesh(vertices, indecies, ModelMatrix::default());
 
     let entity = commands.push(());
     commands.add_component(entity, material);
     commands.add_component(entity, uniforms);
-    commands.add_component(entity, UniformBuffer(uniform_buffer));
-    commands.add_component(
-        entity,
-        Chunktexture {
-            texture,
-            size: tex_size,
-        },
-    );
+    commands.add_component(entity, Chunktexture { volume });
 }
 
 #[system(for_each)]
 pub fn update_uniforms(
-    uniform: &mut Uniforms,
-    buffer: &UniformBuffer,
+    uniforms: &mut Uniform<Uniforms>,
     #[state] time: &Instant,
     #[resource] renderer: &mut puddle::rendering::Renderer,
 ) {
-    uniform.time = time.elapsed().as_secs_f32();
+    uniforms.time = time.elapsed().as_secs_f32();
 
-    uniform.width = renderer.surface_config.width as f32;
-    uniform.height = renderer.surface_config.height as f32;
+    uniforms.width = renderer.surface_config.width as f32;
+    uniforms.height = renderer.surface_config.height as f32;
 
-    renderer
-        .queue
-        .write_buffer(&buffer.0, 0, bytemuck::cast_slice(&[uniform.to_owned()]));
+    uniforms.upload(&renderer.queue);
 }